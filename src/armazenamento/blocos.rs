@@ -0,0 +1,772 @@
+//! Armazenamento persistente de blocos em um log de segmentos somente de
+//! anexação, com um arquivo de índice de tamanho fixo por altura.
+//!
+//! Cada registro no segmento é `[u32 comprimento][u32 checksum][bloco JSON]`
+//! e cada entrada do índice é `[u32 segmento][u64 deslocamento]
+//! [u32 comprimento][32 bytes do hash do bloco]`, tudo em big-endian. O
+//! registro é gravado antes da entrada de índice; na abertura, entradas que
+//! apontam para registros corrompidos são descartadas, registros completos
+//! ainda não indexados são reindexados e o que sobrar de um registro
+//! incompleto no fim do segmento é truncado.
+//!
+//! Os certificados de finalidade ficam em um log à parte, com registros no
+//! mesmo formato `[u32 comprimento][u32 checksum][certificado JSON]`. Os
+//! votos que a própria autoridade emitiu ficam em outro log igual: sem eles,
+//! depois de um reinício ela poderia votar de novo em outro bloco na mesma
+//! rodada ou esquecer a trava de um precommit.
+//!
+//! Um nó iniciado por instantâneo guarda uma cópia dele no diretório, e o
+//! primeiro bloco armazenado é o do instantâneo, não o gênesis.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::blockchain::{Blockchain, Bloco, CertificadoQuorum, InstantaneoAssinado, Voto};
+use crate::utils::config::Config;
+use crate::utils::helpers::{de_hex, para_hex};
+
+const TAMANHO_CABECALHO_REGISTRO: u64 = 8;
+const TAMANHO_ENTRADA_INDICE: u64 = 48;
+const NOME_INDICE: &str = "indice.idx";
+const NOME_CERTIFICADOS: &str = "certificados.log";
+const NOME_VOTOS: &str = "votos.log";
+const NOME_INSTANTANEO: &str = "instantaneo.json";
+
+/// Quando forçar a gravação dos dados no disco.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoliticaFsync {
+    /// Após cada bloco anexado.
+    Sempre,
+    /// A cada `n` blocos anexados.
+    ACada(u32),
+    /// Deixa a cargo do sistema operacional.
+    Nunca,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigArmazenamento {
+    pub diretorio: PathBuf,
+    pub politica_fsync: PoliticaFsync,
+    pub tamanho_maximo_segmento: u64,
+}
+
+impl Default for ConfigArmazenamento {
+    fn default() -> Self {
+        ConfigArmazenamento {
+            diretorio: PathBuf::from("dados"),
+            politica_fsync: PoliticaFsync::Sempre,
+            tamanho_maximo_segmento: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EntradaIndice {
+    segmento: u32,
+    deslocamento: u64,
+    comprimento: u32,
+    hash: String,
+}
+
+impl EntradaIndice {
+    fn codificar(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(TAMANHO_ENTRADA_INDICE as usize);
+        bytes.extend_from_slice(&self.segmento.to_be_bytes());
+        bytes.extend_from_slice(&self.deslocamento.to_be_bytes());
+        bytes.extend_from_slice(&self.comprimento.to_be_bytes());
+        let mut hash = de_hex(&self.hash).unwrap_or_default();
+        hash.resize(32, 0);
+        bytes.extend_from_slice(&hash);
+        bytes
+    }
+
+    fn decodificar(bytes: &[u8]) -> Self {
+        EntradaIndice {
+            segmento: u32::from_be_bytes(bytes[0..4].try_into().unwrap()),
+            deslocamento: u64::from_be_bytes(bytes[4..12].try_into().unwrap()),
+            comprimento: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+            hash: para_hex(&bytes[16..48]),
+        }
+    }
+
+    fn fim(&self) -> u64 {
+        self.deslocamento + TAMANHO_CABECALHO_REGISTRO + self.comprimento as u64
+    }
+}
+
+fn checksum(dados: &[u8]) -> u32 {
+    let resumo = Sha256::digest(dados);
+    u32::from_be_bytes(resumo[0..4].try_into().unwrap())
+}
+
+fn dados_invalidos(mensagem: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, mensagem.to_string())
+}
+
+#[derive(Debug)]
+pub struct ArmazenamentoBlocos {
+    config: ConfigArmazenamento,
+    /// Altura do primeiro bloco armazenado.
+    altura_base: u64,
+    /// Uma entrada por bloco, da altura base em diante.
+    entradas: Vec<EntradaIndice>,
+    /// Posição de cada bloco em `entradas`.
+    por_hash: HashMap<String, u64>,
+    indice: File,
+    segmento_atual: u32,
+    segmento: File,
+    tamanho_segmento: u64,
+    anexados_sem_fsync: u32,
+}
+
+impl ArmazenamentoBlocos {
+    /// Abre (ou cria) o armazenamento e recupera um eventual desligamento
+    /// abrupto durante a última gravação.
+    pub fn abrir(config: ConfigArmazenamento) -> io::Result<Self> {
+        fs::create_dir_all(&config.diretorio)?;
+
+        let mut indice = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(config.diretorio.join(NOME_INDICE))?;
+        let mut bytes_indice = Vec::new();
+        indice.read_to_end(&mut bytes_indice)?;
+
+        let mut entradas: Vec<EntradaIndice> = bytes_indice
+            .chunks_exact(TAMANHO_ENTRADA_INDICE as usize)
+            .map(EntradaIndice::decodificar)
+            .collect();
+
+        // Descarta entradas finais cujo registro não está íntegro no segmento
+        while let Some(ultima) = entradas.last() {
+            if Self::ler_registro(&config.diretorio, ultima).is_ok() {
+                break;
+            }
+            entradas.pop();
+        }
+
+        let (segmento_atual, fim_indexado) = entradas
+            .last()
+            .map(|e| (e.segmento, e.fim()))
+            .unwrap_or((0, 0));
+
+        let mut armazenamento = ArmazenamentoBlocos {
+            segmento: Self::abrir_segmento(&config.diretorio, segmento_atual)?,
+            config,
+            altura_base: 0,
+            por_hash: HashMap::new(),
+            entradas: Vec::new(),
+            indice,
+            segmento_atual,
+            tamanho_segmento: fim_indexado,
+            anexados_sem_fsync: 0,
+        };
+        armazenamento.indice.set_len(entradas.len() as u64 * TAMANHO_ENTRADA_INDICE)?;
+        for (altura, entrada) in entradas.iter().enumerate() {
+            armazenamento.por_hash.insert(entrada.hash.clone(), altura as u64);
+        }
+        armazenamento.entradas = entradas;
+        armazenamento.recuperar_cauda()?;
+        armazenamento.remover_segmentos_apos(armazenamento.segmento_atual)?;
+        armazenamento.sincronizar_disco()?;
+        if let Some(primeiro) = armazenamento.bloco_na_posicao(0)? {
+            armazenamento.altura_base = primeiro.cabecalho.indice as u64;
+        }
+        Ok(armazenamento)
+    }
+
+    /// Quantidade de blocos armazenados.
+    pub fn altura(&self) -> u64 {
+        self.entradas.len() as u64
+    }
+
+    pub fn anexar(&mut self, bloco: &Bloco) -> io::Result<()> {
+        let dados = serde_json::to_vec(bloco).map_err(|e| dados_invalidos(&e.to_string()))?;
+
+        if self.tamanho_segmento > 0
+            && self.tamanho_segmento + TAMANHO_CABECALHO_REGISTRO + dados.len() as u64
+                > self.config.tamanho_maximo_segmento
+        {
+            self.sincronizar_disco()?;
+            self.segmento_atual += 1;
+            self.segmento = Self::abrir_segmento(&self.config.diretorio, self.segmento_atual)?;
+            self.tamanho_segmento = 0;
+        }
+
+        let entrada = EntradaIndice {
+            segmento: self.segmento_atual,
+            deslocamento: self.tamanho_segmento,
+            comprimento: dados.len() as u32,
+            hash: bloco.hash_atual.clone(),
+        };
+
+        let mut registro = Vec::with_capacity(TAMANHO_CABECALHO_REGISTRO as usize + dados.len());
+        registro.extend_from_slice(&entrada.comprimento.to_be_bytes());
+        registro.extend_from_slice(&checksum(&dados).to_be_bytes());
+        registro.extend_from_slice(&dados);
+        self.segmento.seek(SeekFrom::Start(entrada.deslocamento))?;
+        self.segmento.write_all(&registro)?;
+        self.tamanho_segmento = entrada.fim();
+
+        self.indexar(entrada)?;
+
+        self.anexados_sem_fsync += 1;
+        let sincronizar = match self.config.politica_fsync {
+            PoliticaFsync::Sempre => true,
+            PoliticaFsync::ACada(n) => self.anexados_sem_fsync >= n.max(1),
+            PoliticaFsync::Nunca => false,
+        };
+        if sincronizar {
+            self.sincronizar_disco()?;
+        }
+        Ok(())
+    }
+
+    pub fn bloco_por_altura(&self, altura: u64) -> io::Result<Option<Bloco>> {
+        match altura.checked_sub(self.altura_base) {
+            Some(posicao) => self.bloco_na_posicao(posicao),
+            None => Ok(None),
+        }
+    }
+
+    fn bloco_na_posicao(&self, posicao: u64) -> io::Result<Option<Bloco>> {
+        match self.entradas.get(posicao as usize) {
+            Some(entrada) => {
+                let dados = Self::ler_registro(&self.config.diretorio, entrada)?;
+                serde_json::from_slice(&dados)
+                    .map(Some)
+                    .map_err(|e| dados_invalidos(&e.to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn bloco_por_hash(&self, hash: &str) -> io::Result<Option<Bloco>> {
+        match self.por_hash.get(hash) {
+            Some(&posicao) => self.bloco_na_posicao(posicao),
+            None => Ok(None),
+        }
+    }
+
+    /// Lê todos os blocos, em ordem de altura.
+    pub fn carregar_blocos(&self) -> io::Result<Vec<Bloco>> {
+        (0..self.altura())
+            .map(|posicao| {
+                self.bloco_na_posicao(posicao)?
+                    .ok_or_else(|| dados_invalidos("bloco ausente no armazenamento"))
+            })
+            .collect()
+    }
+
+    /// Descarta os blocos a partir da posição `posicao` (inclusive).
+    fn truncar(&mut self, posicao: u64) -> io::Result<()> {
+        let primeira_removida = match self.entradas.get(posicao as usize) {
+            Some(entrada) => entrada.clone(),
+            None => return Ok(()),
+        };
+
+        for entrada in self.entradas.drain(posicao as usize..) {
+            self.por_hash.remove(&entrada.hash);
+        }
+        self.indice.set_len(posicao * TAMANHO_ENTRADA_INDICE)?;
+
+        if primeira_removida.segmento != self.segmento_atual {
+            self.segmento_atual = primeira_removida.segmento;
+            self.segmento = Self::abrir_segmento(&self.config.diretorio, self.segmento_atual)?;
+        }
+        self.segmento.set_len(primeira_removida.deslocamento)?;
+        self.tamanho_segmento = primeira_removida.deslocamento;
+        self.remover_segmentos_apos(self.segmento_atual)?;
+        self.sincronizar_disco()
+    }
+
+    /// Grava os blocos de `cadeia` posteriores à ponta armazenada, conferindo
+    /// só a ponta. Se ela não estiver mais na cadeia, recorre a
+    /// `sincronizar_com`.
+    pub fn anexar_novos(&mut self, cadeia: &[Bloco]) -> io::Result<()> {
+        let armazenados = self.entradas.len();
+        let mesma_base = cadeia.first().map(|bloco| bloco.cabecalho.indice as u64) == Some(self.altura_base);
+        let ponta_na_cadeia = armazenados
+            .checked_sub(1)
+            .and_then(|ultima| cadeia.get(ultima).map(|bloco| bloco.hash_atual == self.entradas[ultima].hash))
+            .unwrap_or(false);
+        if !mesma_base || !ponta_na_cadeia {
+            return self.sincronizar_com(cadeia);
+        }
+        for bloco in &cadeia[armazenados..] {
+            self.anexar(bloco)?;
+        }
+        Ok(())
+    }
+
+    /// Faz o armazenamento refletir `cadeia`: mantém o prefixo comum,
+    /// descarta o que divergir e anexa os blocos restantes. Percorre todo o
+    /// índice; para a cadeia que só cresceu, `anexar_novos` basta.
+    pub fn sincronizar_com(&mut self, cadeia: &[Bloco]) -> io::Result<()> {
+        let comum = self
+            .entradas
+            .iter()
+            .zip(cadeia.iter())
+            .take_while(|(entrada, bloco)| entrada.hash == bloco.hash_atual)
+            .count();
+
+        self.truncar(comum as u64)?;
+        self.altura_base = cadeia.first().map_or(0, |bloco| bloco.cabecalho.indice as u64);
+        for bloco in &cadeia[comum..] {
+            self.anexar(bloco)?;
+        }
+        if self.config.politica_fsync != PoliticaFsync::Nunca {
+            self.sincronizar_disco()?;
+        }
+        Ok(())
+    }
+
+    /// Importa, uma única vez, uma cadeia gravada no antigo formato
+    /// `blockchain.json`. O arquivo é validado, copiado para o armazenamento
+    /// e renomeado com o sufixo `.migrado`. Retorna `false` se não havia nada
+    /// a migrar. Um arquivo da versão inicial, que não pode ser migrado, é
+    /// recusado e fica onde está.
+    pub fn migrar_json(&mut self, caminho: &str, config: &Config) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.altura() > 0 || !Path::new(caminho).exists() {
+            return Ok(false);
+        }
+        let blockchain = Blockchain::carregar_do_disco(caminho, config)?;
+        self.sincronizar_com(&blockchain.cadeia)?;
+        fs::rename(caminho, format!("{}.migrado", caminho))?;
+        Ok(true)
+    }
+
+    /// Guarda o instantâneo de que o nó partiu; sem ele, os blocos
+    /// armazenados não podem ser validados na próxima abertura.
+    pub fn gravar_instantaneo(&self, instantaneo: &InstantaneoAssinado) -> io::Result<()> {
+        instantaneo.salvar(&self.config.diretorio.join(NOME_INSTANTANEO))
+    }
+
+    /// Instantâneo de que o nó partiu, se não partiu do gênesis.
+    pub fn carregar_instantaneo(&self) -> io::Result<Option<InstantaneoAssinado>> {
+        match InstantaneoAssinado::ler(&self.config.diretorio.join(NOME_INSTANTANEO)) {
+            Ok(instantaneo) => Ok(Some(instantaneo)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Grava um certificado de finalidade, sempre com fsync: um bloco final
+    /// não pode voltar a ser reorganizável depois de um reinício.
+    pub fn anexar_certificado(&mut self, certificado: &CertificadoQuorum) -> io::Result<()> {
+        self.anexar_ao_log(NOME_CERTIFICADOS, certificado)
+    }
+
+    /// Lê os certificados gravados, em ordem.
+    pub fn carregar_certificados(&self) -> io::Result<Vec<CertificadoQuorum>> {
+        self.carregar_log(NOME_CERTIFICADOS)
+    }
+
+    /// Grava um voto desta autoridade, sempre com fsync, antes de ele ser
+    /// difundido.
+    pub fn anexar_voto(&mut self, voto: &Voto) -> io::Result<()> {
+        self.anexar_ao_log(NOME_VOTOS, voto)
+    }
+
+    /// Lê os votos desta autoridade gravados, em ordem.
+    pub fn carregar_votos(&self) -> io::Result<Vec<Voto>> {
+        self.carregar_log(NOME_VOTOS)
+    }
+
+    fn anexar_ao_log<T: Serialize>(&self, nome: &str, valor: &T) -> io::Result<()> {
+        let dados = serde_json::to_vec(valor).map_err(|e| dados_invalidos(&e.to_string()))?;
+        let mut arquivo = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.config.diretorio.join(nome))?;
+
+        let mut registro = Vec::with_capacity(TAMANHO_CABECALHO_REGISTRO as usize + dados.len());
+        registro.extend_from_slice(&(dados.len() as u32).to_be_bytes());
+        registro.extend_from_slice(&checksum(&dados).to_be_bytes());
+        registro.extend_from_slice(&dados);
+        arquivo.write_all(&registro)?;
+        arquivo.sync_data()
+    }
+
+    /// Lê os registros de um log à parte. Um registro incompleto no fim do
+    /// log, deixado por um desligamento abrupto, é descartado.
+    fn carregar_log<T: DeserializeOwned>(&self, nome: &str) -> io::Result<Vec<T>> {
+        let caminho = self.config.diretorio.join(nome);
+        let dados = match fs::read(&caminho) {
+            Ok(dados) => dados,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut registros = Vec::new();
+        let mut posicao = 0usize;
+        while let Some(carga) = Self::carga_registro(&dados, posicao) {
+            let registro = serde_json::from_slice(carga).map_err(|e| dados_invalidos(&e.to_string()))?;
+            registros.push(registro);
+            posicao += TAMANHO_CABECALHO_REGISTRO as usize + carga.len();
+        }
+        if posicao < dados.len() {
+            OpenOptions::new().write(true).open(&caminho)?.set_len(posicao as u64)?;
+        }
+        Ok(registros)
+    }
+
+    fn carga_registro(dados: &[u8], posicao: usize) -> Option<&[u8]> {
+        let cabecalho = dados.get(posicao..posicao + TAMANHO_CABECALHO_REGISTRO as usize)?;
+        let comprimento = u32::from_be_bytes(cabecalho[0..4].try_into().unwrap());
+        let soma = u32::from_be_bytes(cabecalho[4..8].try_into().unwrap());
+        let inicio = posicao + TAMANHO_CABECALHO_REGISTRO as usize;
+        let carga = dados.get(inicio..inicio + comprimento as usize)?;
+        (checksum(carga) == soma).then_some(carga)
+    }
+
+    fn indexar(&mut self, entrada: EntradaIndice) -> io::Result<()> {
+        self.indice.seek(SeekFrom::Start(self.altura() * TAMANHO_ENTRADA_INDICE))?;
+        self.indice.write_all(&entrada.codificar())?;
+        self.por_hash.insert(entrada.hash.clone(), self.altura());
+        self.entradas.push(entrada);
+        Ok(())
+    }
+
+    /// Reindexa registros completos gravados após a última entrada do índice,
+    /// inclusive em segmentos seguintes, e trunca o segmento no primeiro
+    /// registro incompleto ou corrompido.
+    fn recuperar_cauda(&mut self) -> io::Result<()> {
+        loop {
+            let inicio = self.tamanho_segmento;
+            let mut cauda = Vec::new();
+            self.segmento.seek(SeekFrom::Start(inicio))?;
+            self.segmento.read_to_end(&mut cauda)?;
+
+            let mut relativa = 0u64;
+            while let Some((comprimento, bloco)) = Self::decodificar_registro(&cauda, relativa) {
+                self.indexar(EntradaIndice {
+                    segmento: self.segmento_atual,
+                    deslocamento: inicio + relativa,
+                    comprimento,
+                    hash: bloco.hash_atual,
+                })?;
+                relativa += TAMANHO_CABECALHO_REGISTRO + comprimento as u64;
+            }
+
+            let incompleto = relativa < cauda.len() as u64;
+            if incompleto {
+                println!(
+                    "Armazenamento: descartando {} byte(s) de registro incompleto no segmento {}.",
+                    cauda.len() as u64 - relativa,
+                    self.segmento_atual
+                );
+            }
+            self.tamanho_segmento = inicio + relativa;
+            self.segmento.set_len(self.tamanho_segmento)?;
+
+            let proximo = self.segmento_atual + 1;
+            if incompleto || !Self::caminho_segmento(&self.config.diretorio, proximo).exists() {
+                return Ok(());
+            }
+            self.segmento_atual = proximo;
+            self.segmento = Self::abrir_segmento(&self.config.diretorio, proximo)?;
+            self.tamanho_segmento = 0;
+        }
+    }
+
+    fn decodificar_registro(dados: &[u8], posicao: u64) -> Option<(u32, Bloco)> {
+        let carga = Self::carga_registro(dados, posicao as usize)?;
+        serde_json::from_slice(carga).ok().map(|bloco| (carga.len() as u32, bloco))
+    }
+
+    fn ler_registro(diretorio: &Path, entrada: &EntradaIndice) -> io::Result<Vec<u8>> {
+        let mut arquivo = File::open(Self::caminho_segmento(diretorio, entrada.segmento))?;
+        arquivo.seek(SeekFrom::Start(entrada.deslocamento))?;
+
+        let mut cabecalho = [0u8; TAMANHO_CABECALHO_REGISTRO as usize];
+        arquivo.read_exact(&mut cabecalho)?;
+        let comprimento = u32::from_be_bytes(cabecalho[0..4].try_into().unwrap());
+        let soma = u32::from_be_bytes(cabecalho[4..8].try_into().unwrap());
+        if comprimento != entrada.comprimento {
+            return Err(dados_invalidos("comprimento do registro diverge do índice"));
+        }
+
+        let mut dados = vec![0u8; comprimento as usize];
+        arquivo.read_exact(&mut dados)?;
+        if checksum(&dados) != soma {
+            return Err(dados_invalidos("checksum do registro inválido"));
+        }
+        Ok(dados)
+    }
+
+    fn caminho_segmento(diretorio: &Path, segmento: u32) -> PathBuf {
+        diretorio.join(format!("segmento_{:06}.log", segmento))
+    }
+
+    fn abrir_segmento(diretorio: &Path, segmento: u32) -> io::Result<File> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(Self::caminho_segmento(diretorio, segmento))
+    }
+
+    fn remover_segmentos_apos(&self, segmento: u32) -> io::Result<()> {
+        let mut proximo = segmento + 1;
+        while Self::caminho_segmento(&self.config.diretorio, proximo).exists() {
+            fs::remove_file(Self::caminho_segmento(&self.config.diretorio, proximo))?;
+            proximo += 1;
+        }
+        Ok(())
+    }
+
+    /// Força a gravação em disco do que a política de fsync ainda não
+    /// sincronizou.
+    pub fn sincronizar_disco(&mut self) -> io::Result<()> {
+        self.segmento.sync_data()?;
+        self.indice.sync_data()?;
+        self.anexados_sem_fsync = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes::{self, matricula};
+    use crate::blockchain::MotorFinalidade;
+
+    fn diretorio_temporario(nome: &str) -> PathBuf {
+        let diretorio = std::env::temp_dir().join(format!("uniblockchain-{}-{}", nome, std::process::id()));
+        let _ = fs::remove_dir_all(&diretorio);
+        diretorio
+    }
+
+    fn abrir(diretorio: &Path) -> ArmazenamentoBlocos {
+        ArmazenamentoBlocos::abrir(ConfigArmazenamento {
+            diretorio: diretorio.to_path_buf(),
+            politica_fsync: PoliticaFsync::Nunca,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn hashes(blocos: &[Bloco]) -> Vec<String> {
+        blocos.iter().map(|bloco| bloco.hash_atual.clone()).collect()
+    }
+
+    #[test]
+    fn anexar_novos_grava_so_os_blocos_apos_a_ponta() {
+        let config = testes::config();
+        let diretorio = diretorio_temporario("anexar-novos");
+        let mut armazenamento = abrir(&diretorio);
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        armazenamento.anexar_novos(&blockchain.cadeia).unwrap();
+        testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, &config);
+        testes::produzir(&mut blockchain, vec![matricula(2, 2)], 2, &config);
+
+        armazenamento.anexar_novos(&blockchain.cadeia).unwrap();
+        let tamanho = armazenamento.tamanho_segmento;
+        armazenamento.anexar_novos(&blockchain.cadeia).unwrap();
+        assert_eq!(armazenamento.tamanho_segmento, tamanho);
+
+        drop(armazenamento);
+        let reaberto = abrir(&diretorio);
+        assert_eq!(hashes(&reaberto.carregar_blocos().unwrap()), hashes(&blockchain.cadeia));
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+
+    #[test]
+    fn anexar_novos_refaz_o_armazenamento_quando_a_ponta_sai_da_cadeia() {
+        let config = testes::config();
+        let diretorio = diretorio_temporario("anexar-reorganizada");
+        let mut armazenamento = abrir(&diretorio);
+        let mut original = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut original, vec![matricula(1, 1)], 1, &config);
+        armazenamento.anexar_novos(&original.cadeia).unwrap();
+
+        let mut ramo = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut ramo, vec![matricula(2, 2)], 1, &config);
+        testes::produzir(&mut ramo, vec![matricula(3, 3)], 2, &config);
+        armazenamento.anexar_novos(&ramo.cadeia).unwrap();
+
+        assert_eq!(hashes(&armazenamento.carregar_blocos().unwrap()), hashes(&ramo.cadeia));
+        assert!(armazenamento.bloco_por_hash(&original.cadeia[1].hash_atual).unwrap().is_none());
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+
+    #[test]
+    fn votos_gravados_sobrevivem_a_um_registro_incompleto() {
+        let config = testes::config();
+        let diretorio = diretorio_temporario("votos");
+        let mut armazenamento = abrir(&diretorio);
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, &config);
+        let mut motor = MotorFinalidade::novo(Some((1, testes::chave_autoridade(1).clone())));
+        let agora = chrono::Utc::now();
+        let mut votos = motor.ao_mudar_ponta(&blockchain, &config, agora).votos;
+        let depois_do_prazo = agora + config.finalidade.tempo_limite_rodada + chrono::Duration::seconds(1);
+        votos.extend(motor.ao_expirar(&blockchain, &config, depois_do_prazo).votos);
+        assert_eq!(votos.len(), 2);
+        for voto in &votos {
+            armazenamento.anexar_voto(voto).unwrap();
+        }
+        OpenOptions::new()
+            .append(true)
+            .open(diretorio.join(NOME_VOTOS))
+            .unwrap()
+            .write_all(&[0, 0, 1])
+            .unwrap();
+
+        assert_eq!(abrir(&diretorio).carregar_votos().unwrap(), votos);
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+
+    #[test]
+    fn registro_incompleto_no_fim_do_segmento_e_truncado_na_abertura() {
+        let config = testes::config();
+        let diretorio = diretorio_temporario("cauda-incompleta");
+        let mut armazenamento = abrir(&diretorio);
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, &config);
+        armazenamento.anexar_novos(&blockchain.cadeia).unwrap();
+        let tamanho = armazenamento.tamanho_segmento;
+        drop(armazenamento);
+
+        // Cabeçalho de um registro de 500 bytes do qual só 20 foram gravados
+        let caminho = ArmazenamentoBlocos::caminho_segmento(&diretorio, 0);
+        let mut cauda = 500u32.to_be_bytes().to_vec();
+        cauda.extend_from_slice(&[0; 24]);
+        OpenOptions::new().append(true).open(&caminho).unwrap().write_all(&cauda).unwrap();
+
+        let mut reaberto = abrir(&diretorio);
+        assert_eq!(reaberto.altura(), 2);
+        assert_eq!(fs::metadata(&caminho).unwrap().len(), tamanho);
+        testes::produzir(&mut blockchain, vec![matricula(2, 2)], 2, &config);
+        reaberto.anexar_novos(&blockchain.cadeia).unwrap();
+        drop(reaberto);
+        assert_eq!(hashes(&abrir(&diretorio).carregar_blocos().unwrap()), hashes(&blockchain.cadeia));
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+
+    #[test]
+    fn abertura_reindexa_registros_completos_e_descarta_os_corrompidos() {
+        let config = testes::config();
+        let diretorio = diretorio_temporario("reindexar");
+        let mut armazenamento = abrir(&diretorio);
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, &config);
+        testes::produzir(&mut blockchain, vec![matricula(2, 2)], 2, &config);
+        armazenamento.anexar_novos(&blockchain.cadeia).unwrap();
+        let inicio_ultimo = armazenamento.entradas[2].deslocamento;
+        drop(armazenamento);
+
+        // O último registro chegou ao segmento, mas não ao índice
+        let caminho_indice = diretorio.join(NOME_INDICE);
+        OpenOptions::new().write(true).open(&caminho_indice).unwrap().set_len(2 * TAMANHO_ENTRADA_INDICE).unwrap();
+        assert_eq!(hashes(&abrir(&diretorio).carregar_blocos().unwrap()), hashes(&blockchain.cadeia));
+
+        // Um byte trocado no último registro invalida a entrada e o registro
+        let caminho = ArmazenamentoBlocos::caminho_segmento(&diretorio, 0);
+        let mut segmento = fs::read(&caminho).unwrap();
+        *segmento.last_mut().unwrap() ^= 0xff;
+        fs::write(&caminho, segmento).unwrap();
+
+        let reaberto = abrir(&diretorio);
+        assert_eq!(hashes(&reaberto.carregar_blocos().unwrap()), hashes(&blockchain.cadeia[..2]));
+        assert_eq!(fs::metadata(&caminho).unwrap().len(), inicio_ultimo);
+        assert_eq!(fs::metadata(&caminho_indice).unwrap().len(), 2 * TAMANHO_ENTRADA_INDICE);
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+
+    #[test]
+    fn migrar_json_importa_cadeia_com_cabecalho_uma_unica_vez() {
+        let config = testes::config();
+        let diretorio = diretorio_temporario("migrar");
+        let mut armazenamento = abrir(&diretorio);
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, &config);
+        let caminho = diretorio.join("blockchain.json");
+        fs::write(&caminho, serde_json::to_string(&blockchain).unwrap()).unwrap();
+        let caminho = caminho.to_str().unwrap();
+
+        assert!(armazenamento.migrar_json(caminho, &config).unwrap());
+        assert_eq!(hashes(&armazenamento.carregar_blocos().unwrap()), hashes(&blockchain.cadeia));
+        assert!(!Path::new(caminho).exists());
+        assert!(Path::new(&format!("{}.migrado", caminho)).exists());
+        assert!(!armazenamento.migrar_json(caminho, &config).unwrap());
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+
+    /// `blockchain.json` gravado pela versão inicial: campos do cabeçalho
+    /// soltos no bloco e transações com o retrato inteiro do estudante.
+    const BLOCKCHAIN_VERSAO_INICIAL: &str = r#"{
+        "cadeia": [
+            {
+                "indice": 0,
+                "hash_anterior": "0",
+                "hash_atual": "5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9",
+                "timestamp": "1970-01-01T00:00:00Z",
+                "transacoes": [],
+                "id_autoridade": 0,
+                "assinatura_autoridade": ""
+            },
+            {
+                "indice": 1,
+                "hash_anterior": "5feceb66ffc86f38d952786c6d696c79c2dbc239dd4e91b46729d73a27fb57e9",
+                "hash_atual": "6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b",
+                "timestamp": "2024-03-01T12:00:00Z",
+                "transacoes": [
+                    {
+                        "id_transacao": 1,
+                        "estudante": {
+                            "id_estudante": 1,
+                            "nome": "Ana",
+                            "data_nascimento": "2001-12-31",
+                            "periodos_letivos": []
+                        },
+                        "periodo_letivo": {
+                            "id_periodo": 1,
+                            "ano": 2024,
+                            "semestre": 1,
+                            "disciplinas": [
+                                {
+                                    "id_disciplina": 1,
+                                    "nome": "Cálculo I",
+                                    "codigo": "MAT101",
+                                    "notas": [{ "id_nota": 1, "valor": 8.5, "tipo": "Prova", "data": "2024-03-01" }],
+                                    "media": 8.5,
+                                    "frequencia": 100.0
+                                }
+                            ]
+                        },
+                        "timestamp": "2024-03-01T11:59:00Z"
+                    }
+                ],
+                "id_autoridade": 1,
+                "assinatura_autoridade": "YXNzaW5hdHVyYQ=="
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn migrar_json_recusa_cadeia_da_versao_inicial_e_mantem_o_arquivo() {
+        let config = testes::config();
+        let diretorio = diretorio_temporario("migrar-inicial");
+        let mut armazenamento = abrir(&diretorio);
+        let caminho = diretorio.join("blockchain.json");
+        fs::write(&caminho, BLOCKCHAIN_VERSAO_INICIAL).unwrap();
+
+        let erro = armazenamento.migrar_json(caminho.to_str().unwrap(), &config).unwrap_err();
+        assert!(erro.to_string().contains("novo gênesis"), "{}", erro);
+        assert_eq!(armazenamento.altura(), 0);
+        assert!(caminho.exists());
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+}
@@ -0,0 +1,5 @@
+// src/armazenamento/mod.rs
+
+mod blocos;
+
+pub use blocos::{ArmazenamentoBlocos, ConfigArmazenamento, PoliticaFsync};
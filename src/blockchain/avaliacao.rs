@@ -0,0 +1,353 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::codificacao::{CodificacaoCanonica, Codificador};
+use super::Nota;
+
+// Diferença máxima aceita entre um valor gravado e o recalculado.
+const TOLERANCIA: f32 = 1e-4;
+
+// Potências de dez exatas em f32 usadas no arredondamento. Ao contrário de
+// `powi`, cujo resultado pode variar entre plataformas, multiplicação,
+// divisão e `round` têm resultado fixado pelo IEEE 754, de modo que todos
+// os nós chegam à mesma média. Com mais casas do que a tabela cobre, um f32 já não
+// tem dígitos para arredondar.
+const POTENCIAS_DE_DEZ: [f32; 7] = [1.0, 10.0, 100.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0];
+
+/// Como as notas regulares são combinadas em uma média.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TipoMedia {
+    Aritmetica,
+    /// Média ponderada pelo `Nota::tipo`; tipos sem peso valem 1.
+    Ponderada(HashMap<String, f32>),
+}
+
+/// O que a nota de recuperação faz com as notas regulares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegraRecuperacao {
+    /// A recuperação substitui a média, se for maior.
+    SubstituiMedia,
+    /// A recuperação substitui a menor nota regular, se for maior.
+    SubstituiMenor,
+    /// A média final é a média entre a média regular e a recuperação.
+    MediaComRecuperacao,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arredondamento {
+    Nenhum,
+    CasasDecimais(u32),
+    /// Para o meio ponto mais próximo (7,3 vira 7,5).
+    MeioPonto,
+}
+
+/// Política de avaliação da instituição. Todos os nós precisam usar a mesma
+/// política, já que a validação de blocos recalcula médias com ela.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoliticaAvaliacao {
+    pub tipo_media: TipoMedia,
+    pub descartar_menor: bool,
+    /// `Nota::tipo` que identifica a nota de recuperação; `None` desativa.
+    pub tipo_recuperacao: Option<String>,
+    pub regra_recuperacao: RegraRecuperacao,
+    pub arredondamento: Arredondamento,
+    /// Média mínima para aprovação.
+    pub media_minima: f32,
+    /// Frequência mínima, em percentual, para aprovação.
+    pub frequencia_minima: f32,
+}
+
+/// Situação do estudante em uma disciplina.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SituacaoDisciplina {
+    #[default]
+    Cursando,
+    Aprovado,
+    ReprovadoPorNota,
+    ReprovadoPorFalta,
+    Trancado,
+    Dispensado,
+}
+
+impl SituacaoDisciplina {
+    pub fn codigo(&self) -> u8 {
+        match self {
+            SituacaoDisciplina::Cursando => 0,
+            SituacaoDisciplina::Aprovado => 1,
+            SituacaoDisciplina::ReprovadoPorNota => 2,
+            SituacaoDisciplina::ReprovadoPorFalta => 3,
+            SituacaoDisciplina::Trancado => 4,
+            SituacaoDisciplina::Dispensado => 5,
+        }
+    }
+
+    /// Situações que encerram a disciplina pelo resultado das notas e da
+    /// frequência, e que por isso precisam conferir com elas.
+    pub fn eh_resultado_final(&self) -> bool {
+        matches!(
+            self,
+            SituacaoDisciplina::Aprovado
+                | SituacaoDisciplina::ReprovadoPorNota
+                | SituacaoDisciplina::ReprovadoPorFalta
+        )
+    }
+}
+
+impl fmt::Display for SituacaoDisciplina {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let texto = match self {
+            SituacaoDisciplina::Cursando => "Cursando",
+            SituacaoDisciplina::Aprovado => "Aprovado",
+            SituacaoDisciplina::ReprovadoPorNota => "Reprovado por nota",
+            SituacaoDisciplina::ReprovadoPorFalta => "Reprovado por falta",
+            SituacaoDisciplina::Trancado => "Trancado",
+            SituacaoDisciplina::Dispensado => "Dispensado",
+        };
+        f.write_str(texto)
+    }
+}
+
+impl Default for PoliticaAvaliacao {
+    fn default() -> Self {
+        PoliticaAvaliacao {
+            tipo_media: TipoMedia::Aritmetica,
+            descartar_menor: false,
+            tipo_recuperacao: None,
+            regra_recuperacao: RegraRecuperacao::SubstituiMedia,
+            arredondamento: Arredondamento::Nenhum,
+            media_minima: 6.0,
+            frequencia_minima: 75.0,
+        }
+    }
+}
+
+/// Posição da menor nota em uma lista de pares (valor, peso).
+fn posicao_menor(notas: &[(f32, f32)]) -> Option<usize> {
+    notas
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.0.total_cmp(&b.0))
+        .map(|(i, _)| i)
+}
+
+impl PoliticaAvaliacao {
+    fn eh_recuperacao(&self, nota: &Nota) -> bool {
+        self.tipo_recuperacao
+            .as_deref()
+            .is_some_and(|tipo| nota.tipo.eq_ignore_ascii_case(tipo))
+    }
+
+    fn peso(&self, nota: &Nota) -> f32 {
+        match &self.tipo_media {
+            TipoMedia::Aritmetica => 1.0,
+            TipoMedia::Ponderada(pesos) => pesos.get(&nota.tipo).copied().unwrap_or(1.0),
+        }
+    }
+
+    fn arredondar(&self, valor: f32) -> f32 {
+        match self.arredondamento {
+            Arredondamento::Nenhum => valor,
+            Arredondamento::CasasDecimais(casas) => match POTENCIAS_DE_DEZ.get(casas as usize) {
+                Some(fator) => (valor * fator).round() / fator,
+                None => valor,
+            },
+            Arredondamento::MeioPonto => (valor * 2.0).round() / 2.0,
+        }
+    }
+
+    /// Calcula a média final de uma disciplina a partir das suas notas. Sem
+    /// notas regulares a média é zero.
+    pub fn calcular_media(&self, notas: &[Nota]) -> f32 {
+        let recuperacao = notas
+            .iter()
+            .filter(|nota| self.eh_recuperacao(nota))
+            .map(|nota| nota.valor)
+            .max_by(f32::total_cmp);
+        let mut regulares: Vec<(f32, f32)> = notas
+            .iter()
+            .filter(|nota| !self.eh_recuperacao(nota))
+            .map(|nota| (nota.valor, self.peso(nota)))
+            .collect();
+
+        // Substituir a menor nota acontece antes do descarte, para que a
+        // recuperação não seja ela mesma descartada.
+        if let (Some(valor), RegraRecuperacao::SubstituiMenor) = (recuperacao, self.regra_recuperacao) {
+            if let Some(i) = posicao_menor(&regulares) {
+                regulares[i].0 = regulares[i].0.max(valor);
+            }
+        }
+        if self.descartar_menor && regulares.len() > 1 {
+            if let Some(i) = posicao_menor(&regulares) {
+                regulares.remove(i);
+            }
+        }
+
+        let soma_pesos: f32 = regulares.iter().map(|(_, peso)| peso).sum();
+        let mut media = if soma_pesos > 0.0 {
+            regulares.iter().map(|(valor, peso)| valor * peso).sum::<f32>() / soma_pesos
+        } else {
+            0.0
+        };
+
+        if let Some(valor) = recuperacao {
+            media = match self.regra_recuperacao {
+                RegraRecuperacao::SubstituiMedia => media.max(valor),
+                RegraRecuperacao::MediaComRecuperacao => (media + valor) / 2.0,
+                RegraRecuperacao::SubstituiMenor => media,
+            };
+        }
+
+        self.arredondar(media)
+    }
+
+    /// Resultado final de uma disciplina encerrada. A reprovação por falta
+    /// prevalece sobre a por nota; sem aulas registradas a frequência não é
+    /// avaliada.
+    pub fn resultado_final(&self, media: f32, frequencia: f32, houve_aulas: bool) -> SituacaoDisciplina {
+        if houve_aulas && frequencia < self.frequencia_minima {
+            SituacaoDisciplina::ReprovadoPorFalta
+        } else if media < self.media_minima {
+            SituacaoDisciplina::ReprovadoPorNota
+        } else {
+            SituacaoDisciplina::Aprovado
+        }
+    }
+}
+
+/// Compara um valor gravado com o recalculado, tolerando o ruído de ponto
+/// flutuante da serialização.
+pub fn valores_conferem(gravado: f32, calculado: f32) -> bool {
+    (gravado - calculado).abs() <= TOLERANCIA
+}
+
+/// Pesos da média ponderada em ordem do tipo de nota.
+impl CodificacaoCanonica for PoliticaAvaliacao {
+    fn codificar(&self, saida: &mut Codificador) {
+        match &self.tipo_media {
+            TipoMedia::Aritmetica => {
+                saida.u8(0);
+            }
+            TipoMedia::Ponderada(pesos) => {
+                let pesos: BTreeMap<&String, &f32> = pesos.iter().collect();
+                saida.u8(1).u32(pesos.len() as u32);
+                for (tipo, peso) in pesos {
+                    saida.texto(tipo).f32(*peso);
+                }
+            }
+        }
+        saida.u8(self.descartar_menor as u8);
+        match &self.tipo_recuperacao {
+            Some(tipo) => saida.u8(1).texto(tipo),
+            None => saida.u8(0),
+        };
+        saida.u8(match self.regra_recuperacao {
+            RegraRecuperacao::SubstituiMedia => 0,
+            RegraRecuperacao::SubstituiMenor => 1,
+            RegraRecuperacao::MediaComRecuperacao => 2,
+        });
+        match self.arredondamento {
+            Arredondamento::Nenhum => saida.u8(0),
+            Arredondamento::CasasDecimais(casas) => saida.u8(1).u32(casas),
+            Arredondamento::MeioPonto => saida.u8(2),
+        };
+        saida.f32(self.media_minima).f32(self.frequencia_minima);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn notas(valores: &[(f32, &str)]) -> Vec<Nota> {
+        let data = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        valores
+            .iter()
+            .enumerate()
+            .map(|(i, (valor, tipo))| Nota::nova_nota(i as u32 + 1, *valor, tipo, data))
+            .collect()
+    }
+
+    fn provas(valores: &[f32]) -> Vec<Nota> {
+        notas(&valores.iter().map(|valor| (*valor, "Prova")).collect::<Vec<_>>())
+    }
+
+    fn com_recuperacao(regra_recuperacao: RegraRecuperacao) -> PoliticaAvaliacao {
+        PoliticaAvaliacao {
+            tipo_recuperacao: Some("Recuperacao".into()),
+            regra_recuperacao,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn media_aritmetica_e_zero_sem_notas() {
+        let politica = PoliticaAvaliacao::default();
+        assert_eq!(politica.calcular_media(&provas(&[6.0, 7.0, 8.0])), 7.0);
+        assert_eq!(politica.calcular_media(&[]), 0.0);
+    }
+
+    #[test]
+    fn media_ponderada_pelo_tipo_com_peso_um_para_tipos_sem_peso() {
+        let politica = PoliticaAvaliacao {
+            tipo_media: TipoMedia::Ponderada([("Prova".to_string(), 2.0), ("Trabalho".to_string(), 1.0)].into()),
+            ..Default::default()
+        };
+        assert_eq!(politica.calcular_media(&notas(&[(9.0, "Prova"), (6.0, "Trabalho")])), 8.0);
+        assert_eq!(politica.calcular_media(&notas(&[(9.0, "Prova"), (3.0, "Seminario")])), 7.0);
+    }
+
+    #[test]
+    fn descartar_menor_mantem_nota_unica() {
+        let politica = PoliticaAvaliacao { descartar_menor: true, ..Default::default() };
+        assert_eq!(politica.calcular_media(&provas(&[4.0, 8.0, 9.0])), 8.5);
+        assert_eq!(politica.calcular_media(&provas(&[4.0])), 4.0);
+    }
+
+    #[test]
+    fn recuperacao_substitui_a_media_so_se_for_maior() {
+        let politica = com_recuperacao(RegraRecuperacao::SubstituiMedia);
+        assert_eq!(politica.calcular_media(&notas(&[(4.0, "Prova"), (5.0, "Prova"), (7.0, "recuperacao")])), 7.0);
+        assert_eq!(politica.calcular_media(&notas(&[(4.0, "Prova"), (5.0, "Prova"), (3.0, "Recuperacao")])), 4.5);
+    }
+
+    #[test]
+    fn recuperacao_substitui_a_menor_antes_do_descarte() {
+        let politica = com_recuperacao(RegraRecuperacao::SubstituiMenor);
+        let valores = notas(&[(4.0, "Prova"), (8.0, "Prova"), (6.0, "Recuperacao")]);
+        assert_eq!(politica.calcular_media(&valores), 7.0);
+
+        let politica = PoliticaAvaliacao { descartar_menor: true, ..politica };
+        assert_eq!(politica.calcular_media(&valores), 8.0);
+    }
+
+    #[test]
+    fn recuperacao_entra_na_media_com_a_regular() {
+        let politica = com_recuperacao(RegraRecuperacao::MediaComRecuperacao);
+        assert_eq!(politica.calcular_media(&notas(&[(4.0, "Prova"), (6.0, "Prova"), (8.0, "Recuperacao")])), 6.5);
+    }
+
+    #[test]
+    fn arredondamento_por_casas_decimais_e_meio_ponto() {
+        let casas = |n| PoliticaAvaliacao { arredondamento: Arredondamento::CasasDecimais(n), ..Default::default() };
+        assert_eq!(casas(0).calcular_media(&provas(&[7.5])), 8.0);
+        assert_eq!(casas(1).calcular_media(&provas(&[7.25])), 7.3);
+        assert_eq!(casas(2).calcular_media(&provas(&[2.0, 0.0, 0.0])), 0.67);
+        assert_eq!(casas(30).calcular_media(&provas(&[2.0, 0.0, 0.0])), 2.0 / 3.0);
+
+        let meio_ponto = PoliticaAvaliacao { arredondamento: Arredondamento::MeioPonto, ..Default::default() };
+        assert_eq!(meio_ponto.calcular_media(&provas(&[7.3])), 7.5);
+        assert_eq!(meio_ponto.calcular_media(&provas(&[7.2])), 7.0);
+    }
+
+    #[test]
+    fn reprovacao_por_falta_prevalece_sobre_a_por_nota() {
+        let politica = PoliticaAvaliacao::default();
+        assert_eq!(politica.resultado_final(5.0, 50.0, true), SituacaoDisciplina::ReprovadoPorFalta);
+        assert_eq!(politica.resultado_final(5.0, 50.0, false), SituacaoDisciplina::ReprovadoPorNota);
+        assert_eq!(politica.resultado_final(6.0, 75.0, true), SituacaoDisciplina::Aprovado);
+    }
+}
@@ -0,0 +1,103 @@
+use std::cmp::Ordering;
+
+use crate::utils::config::Config;
+
+use super::Bloco;
+
+/// Resultado da escolha entre a cadeia local e uma cadeia candidata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecisaoBifurcacao {
+    ManterLocal,
+    AdotarCandidata,
+}
+
+/// Resumo de uma reorganização aplicada à cadeia local.
+#[derive(Debug, Clone)]
+pub struct Reorganizacao {
+    pub altura_ancestral: usize,
+    pub blocos_removidos: usize,
+    pub blocos_adicionados: usize,
+    pub transacoes_devolvidas: usize,
+}
+
+/// Soma o peso das autoridades que assinaram os blocos do ramo.
+pub fn peso_ramo(blocos: &[Bloco], config: &Config) -> u64 {
+    blocos
+        .iter()
+        .map(|bloco| config.genesis.peso_autoridade(bloco.cabecalho.id_autoridade))
+        .sum()
+}
+
+/// Regra de escolha determinística entre os ramos local e candidato, ambos
+/// a partir do ancestral comum: vence o de maior peso de autoridades; em
+/// caso de empate vence o ramo cuja ponta tem o menor hash. Se nada mudar, a
+/// cadeia local é mantida.
+pub fn escolher_ramo(ramo_local: &[Bloco], ramo_candidato: &[Bloco], config: &Config) -> DecisaoBifurcacao {
+    if ramo_candidato.is_empty() {
+        return DecisaoBifurcacao::ManterLocal;
+    }
+    if ramo_local.is_empty() {
+        return DecisaoBifurcacao::AdotarCandidata;
+    }
+
+    let peso_local = peso_ramo(ramo_local, config);
+    let peso_candidato = peso_ramo(ramo_candidato, config);
+
+    match peso_candidato.cmp(&peso_local) {
+        Ordering::Greater => DecisaoBifurcacao::AdotarCandidata,
+        Ordering::Less => DecisaoBifurcacao::ManterLocal,
+        Ordering::Equal => {
+            let ponta_local = &ramo_local.last().unwrap().hash_atual;
+            let ponta_candidata = &ramo_candidato.last().unwrap().hash_atual;
+            if ponta_candidata < ponta_local {
+                DecisaoBifurcacao::AdotarCandidata
+            } else {
+                DecisaoBifurcacao::ManterLocal
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes;
+
+    fn bloco(id_autoridade: u32, hash: &str) -> Bloco {
+        let mut bloco = Bloco::novo_bloco("", 0, String::new(), Vec::new(), String::new(), None);
+        bloco.cabecalho.id_autoridade = id_autoridade;
+        bloco.hash_atual = hash.to_string();
+        bloco
+    }
+
+    #[test]
+    fn vence_o_ramo_de_maior_peso_mesmo_mais_curto() {
+        let mut config = testes::config();
+        config.genesis.pesos_autoridades.insert(1, 3);
+        let pesado = [bloco(1, "p")];
+        let longo = [bloco(2, "l1"), bloco(2, "l2")];
+
+        assert_eq!(escolher_ramo(&pesado, &longo, &config), DecisaoBifurcacao::ManterLocal);
+        assert_eq!(escolher_ramo(&longo, &pesado, &config), DecisaoBifurcacao::AdotarCandidata);
+    }
+
+    #[test]
+    fn empate_de_peso_vence_a_ponta_de_menor_hash() {
+        let config = testes::config();
+        let menor = [bloco(1, "0a")];
+        let maior = [bloco(2, "0b")];
+
+        assert_eq!(escolher_ramo(&maior, &menor, &config), DecisaoBifurcacao::AdotarCandidata);
+        assert_eq!(escolher_ramo(&menor, &maior, &config), DecisaoBifurcacao::ManterLocal);
+        assert_eq!(escolher_ramo(&menor, &menor, &config), DecisaoBifurcacao::ManterLocal);
+    }
+
+    #[test]
+    fn ramo_vazio_nunca_vence() {
+        let config = testes::config();
+        let ramo = [bloco(1, "f")];
+
+        assert_eq!(escolher_ramo(&ramo, &[], &config), DecisaoBifurcacao::ManterLocal);
+        assert_eq!(escolher_ramo(&[], &ramo, &config), DecisaoBifurcacao::AdotarCandidata);
+    }
+}
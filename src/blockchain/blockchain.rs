@@ -1,100 +1,967 @@
-use super::{Bloco, Transacao};
-use std::collections::VecDeque;
+use super::bifurcacao::{self, DecisaoBifurcacao, Reorganizacao};
+use super::cabecalho;
+use super::codificacao;
+use super::consenso;
+use super::historico::HistoricoEscolar;
+use super::indices::{IndicesBlockchain, Localizacao, RegistroIndexado};
+use super::estado::{EstadoAcademico, InstantaneosEstado};
+use super::finalidade::CertificadoQuorum;
+use super::instantaneo::InstantaneoAssinado;
+use super::mempool::{EntradaMempool, Mempool, PoliticaDespejo};
+use super::{Bloco, ConjuntoAutoridades, EspecificacaoGenesis, SituacaoDisciplina, TipoTransacao, Transacao};
+use std::collections::BTreeMap;
 use crate::utils::config::Config;
-use crate::utils::erros::BlocoErro;
+use crate::utils::erros::{BlocoErro, CadeiaErro, FinalidadeErro, InstantaneoErro, TransacaoErro};
 use rsa::RsaPrivateKey;
-use chrono::{Utc, TimeZone};
+use chrono::Utc;
 use serde::{Serialize, Deserialize};
-use std::fs::{File, OpenOptions};
-use std::io::{Write, Read};
+use std::fs::File;
+use std::io::Read;
+use crate::armazenamento::ArmazenamentoBlocos;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Blockchain {
+    /// Blocos a partir de `altura_base`: o gênesis, ou o checkpoint de que
+    /// um nó iniciado por instantâneo partiu.
     pub cadeia: Vec<Bloco>,
     #[serde(skip)]
-    pub transacoes_pendentes: VecDeque<Transacao>,
+    altura_base: u32,
+    #[serde(skip)]
+    mempool: Mempool,
+    #[serde(skip)]
+    indices: IndicesBlockchain,
+    #[serde(skip)]
+    estado: EstadoAcademico,
+    #[serde(skip)]
+    instantaneos: InstantaneosEstado,
+    /// Certificados de finalidade por altura; o bloco certificado e todos os
+    /// anteriores são finais.
+    #[serde(skip)]
+    certificados: BTreeMap<u32, CertificadoQuorum>,
+    /// Estado com as transações do pool aplicadas, atualizado a cada
+    /// admissão. `None` quando precisa ser refeito: depois de um bloco, de
+    /// uma reorganização ou de entradas despejadas ou expiradas.
+    #[serde(skip)]
+    estado_pendente: Option<EstadoAcademico>,
 }
 
 impl Blockchain {
-    pub fn nova_blockchain() -> Self {
-        let timestamp_genesis = Utc.timestamp_opt(0, 0).unwrap();
-        let bloco_genesis = Bloco::novo_bloco(
-            0,
-            String::from("0"),
-            Vec::new(),
-            Some(timestamp_genesis),
-        );
+    pub fn nova_blockchain(especificacao: &EspecificacaoGenesis) -> Self {
+        Self::com_genesis(Self::bloco_genesis(especificacao))
+    }
+
+    fn com_genesis(genesis: Bloco) -> Self {
+        Self::com_base(genesis, EstadoAcademico::default())
+    }
+
+    /// Parte de um instantâneo já verificado: a cadeia local começa no bloco
+    /// dele, que passa a ser final, e só os blocos seguintes são validados.
+    pub fn do_instantaneo(instantaneo: InstantaneoAssinado) -> Self {
+        Self::com_base(instantaneo.bloco, instantaneo.estado)
+    }
+
+    /// Cadeia cujo primeiro bloco é `base`, com o estado resultante dele.
+    fn com_base(base: Bloco, estado: EstadoAcademico) -> Self {
+        let altura_base = base.cabecalho.indice;
         let mut blockchain = Blockchain {
-            cadeia: vec![bloco_genesis],
-            transacoes_pendentes: VecDeque::new(),
+            cadeia: Vec::new(),
+            altura_base,
+            mempool: Mempool::default(),
+            indices: IndicesBlockchain::default(),
+            estado: EstadoAcademico::default(),
+            instantaneos: InstantaneosEstado::com_base(altura_base as usize, estado.clone()),
+            certificados: BTreeMap::new(),
+            estado_pendente: None,
         };
-        blockchain.cadeia[0].hash_atual = blockchain.cadeia[0].calcular_hash();
+        blockchain.anexar_bloco(base, estado);
         blockchain
     }
 
-    pub fn adicionar_transacao(&mut self, transacao: Transacao) {
-        self.transacoes_pendentes.push_back(transacao);
+    /// Monta a blockchain a partir de uma sequência de blocos, validando-os
+    /// do gênesis em diante.
+    fn com_blocos(cadeia: Vec<Bloco>, config: &Config) -> Result<Self, CadeiaErro> {
+        let mut blocos = cadeia.into_iter();
+        let genesis = blocos.next().ok_or(CadeiaErro::CadeiaVazia)?;
+        Self::validar_genesis(&genesis, config).map_err(|erro| CadeiaErro::BlocoInvalido {
+            indice: 0,
+            hash: genesis.hash_atual.clone(),
+            erro,
+        })?;
+
+        Self::com_genesis(genesis).continuar_com(blocos, config)
+    }
+
+    /// Valida e anexa os blocos seguintes à ponta atual.
+    fn continuar_com(mut self, blocos: impl IntoIterator<Item = Bloco>, config: &Config) -> Result<Self, CadeiaErro> {
+        for bloco in blocos {
+            let indice = self.altura() as usize + 1;
+            let hash = bloco.hash_atual.clone();
+            self.adicionar_bloco_externo(bloco, config)
+                .map_err(|erro| CadeiaErro::BlocoInvalido { indice, hash, erro })?;
+        }
+        Ok(self)
+    }
+
+    /// Anexa um bloco já validado à cadeia e aos índices, junto com o estado
+    /// acadêmico resultante dele.
+    fn anexar_bloco(&mut self, bloco: Bloco, estado: EstadoAcademico) {
+        let altura = self.altura_base as usize + self.cadeia.len();
+        self.indices.indexar_bloco(altura, &bloco);
+        self.cadeia.push(bloco);
+        self.instantaneos.registrar(altura, &estado);
+        self.estado = estado;
+        self.estado_pendente = None;
+    }
+
+    /// O gênesis não tem bloco anterior: no lugar do hash anterior vai o
+    /// hash da especificação, que o hash do gênesis passa a comprometer.
+    fn bloco_genesis(especificacao: &EspecificacaoGenesis) -> Bloco {
+        let mut bloco_genesis = Bloco::novo_bloco(
+            &especificacao.id_cadeia,
+            0,
+            especificacao.hash(),
+            Vec::new(),
+            EstadoAcademico::default().raiz(),
+            Some(especificacao.timestamp),
+        );
+        bloco_genesis.hash_atual = bloco_genesis.calcular_hash();
+        bloco_genesis
+    }
+
+    /// Hash do bloco gênesis da especificação, que identifica a rede mesmo
+    /// em nós que não guardam o gênesis.
+    pub fn hash_genesis(especificacao: &EspecificacaoGenesis) -> String {
+        Self::bloco_genesis(especificacao).hash_atual
+    }
+
+    /// Altura do último bloco da cadeia.
+    pub fn altura(&self) -> u32 {
+        self.altura_base + self.cadeia.len() as u32 - 1
+    }
+
+    /// Bloco na altura, se ele estiver na cadeia local.
+    pub fn bloco(&self, altura: u32) -> Option<&Bloco> {
+        self.cadeia.get(altura.checked_sub(self.altura_base)? as usize)
+    }
+
+    /// Bloco com o hash, se ele estiver na cadeia local. A busca parte da
+    /// ponta, onde estão os blocos que os peers costumam pedir.
+    pub fn bloco_por_hash(&self, hash: &str) -> Option<&Bloco> {
+        self.cadeia.iter().rev().find(|bloco| bloco.hash_atual == hash)
+    }
+
+    /// Aceita a transação no pool se ela for válida depois das que já estão
+    /// pendentes. Se o pool estava cheio e a política de despejo descartou
+    /// outra transação, ela é devolvida.
+    pub fn adicionar_transacao(&mut self, transacao: Transacao, config: &Config) -> Result<Option<Transacao>, TransacaoErro> {
+        let agora = Utc::now();
+        // Mensagens de gossip repetidas são descartadas sem reaplicar o pool
+        let hash = transacao.hash();
+        if self.mempool.contem(&hash) {
+            return Err(TransacaoErro::JaPendente(hash));
+        }
+        config
+            .genesis
+            .parametros_cadeia
+            .verificar_timestamp_transacao(&transacao, agora)?;
+        if self.mempool.expirar(&config.mempool, agora) > 0 {
+            self.estado_pendente = None;
+        }
+        // Com o pool cheio e sem despejo, recusa antes de tocar o estado
+        // pendente, que teria de ser refeito
+        if self.mempool.len() >= config.mempool.capacidade && config.mempool.politica_despejo == PoliticaDespejo::Recusar {
+            return Err(TransacaoErro::PoolCheio(config.mempool.capacidade));
+        }
+        // Uma transação rejeitada não altera o estado pendente
+        self.estado_pendente_mut(config).aplicar(&transacao, config)?;
+        let resultado = self.mempool.inserir(transacao, &config.mempool, agora);
+        // A despejada pode ter sido a base de outras pendentes
+        if !matches!(resultado, Ok(None)) {
+            self.estado_pendente = None;
+        }
+        resultado
+    }
+
+    /// Transações pendentes, em ordem de chegada.
+    pub fn mempool(&self) -> &Mempool {
+        &self.mempool
+    }
+
+    /// Recoloca no pool as entradas gravadas em uma execução anterior,
+    /// revalidando-as contra o estado atual. Entradas expiradas, já incluídas
+    /// na cadeia ou que deixaram de ser válidas são descartadas. Devolve
+    /// quantas foram recuperadas.
+    pub fn restaurar_mempool(&mut self, entradas: Vec<EntradaMempool>, config: &Config) -> usize {
+        let agora = Utc::now();
+        let mut estado = self.estado.clone();
+        let validas: Vec<EntradaMempool> = entradas
+            .into_iter()
+            .filter(|e| agora - e.recebida_em <= config.mempool.validade)
+            .filter(|e| e.hash == e.transacao.hash())
+            .filter(|e| {
+                config
+                    .genesis
+                    .parametros_cadeia
+                    .verificar_timestamp_transacao(&e.transacao, agora)
+                    .is_ok()
+            })
+            .filter(|e| estado.aplicar(&e.transacao, config).is_ok())
+            .take(config.mempool.capacidade)
+            .collect();
+        let recuperadas = validas.len();
+        self.mempool.substituir(validas);
+        self.estado_pendente = Some(estado);
+        recuperadas
     }
 
+    /// Estado acadêmico resultante do último bloco da cadeia.
+    pub fn estado(&self) -> &EstadoAcademico {
+        &self.estado
+    }
+
+    /// Estado acadêmico da cadeia com as transações pendentes aplicadas por
+    /// cima; as que deixaram de ser válidas são ignoradas.
+    pub fn estado_com_pendentes(&mut self, config: &Config) -> &EstadoAcademico {
+        self.estado_pendente_mut(config)
+    }
+
+    /// Estado pendente, refeito a partir do pool se tiver sido descartado.
+    fn estado_pendente_mut(&mut self, config: &Config) -> &mut EstadoAcademico {
+        if self.estado_pendente.is_none() {
+            let mut estado = self.estado.clone();
+            for transacao in self.mempool.transacoes() {
+                let _ = estado.aplicar(transacao, config);
+            }
+            self.estado_pendente = Some(estado);
+        }
+        self.estado_pendente.as_mut().unwrap()
+    }
+
+    /// Autoridades em vigor na altura, de acordo com as alterações
+    /// registradas na cadeia local.
+    pub fn autoridades_em(&self, altura: u32, config: &Config) -> ConjuntoAutoridades {
+        self.estado.autoridades_em(altura, config)
+    }
+
+    /// Altura do último bloco final. O primeiro bloco da cadeia local (o
+    /// gênesis ou o checkpoint) é sempre final.
+    pub fn altura_finalizada(&self) -> u32 {
+        self.certificados.keys().next_back().copied().unwrap_or(self.altura_base)
+    }
+
+    /// Certificado que finalizou a altura, se houver um para ela.
+    pub fn certificado(&self, altura: u32) -> Option<&CertificadoQuorum> {
+        self.certificados.get(&altura)
+    }
+
+    /// Verifica um certificado de finalidade para um bloco da cadeia local e
+    /// o guarda. Devolve `false` se a altura já era final.
+    pub fn registrar_certificado(&mut self, certificado: CertificadoQuorum, config: &Config) -> Result<bool, FinalidadeErro> {
+        certificado.verificar(&self.autoridades_em(certificado.altura, config), config)?;
+        let bloco = self
+            .bloco(certificado.altura)
+            .ok_or(FinalidadeErro::BlocoDesconhecido(certificado.altura))?;
+        if bloco.hash_atual != certificado.hash_bloco {
+            return Err(FinalidadeErro::BlocoDivergente(certificado.altura));
+        }
+        if certificado.altura <= self.altura_finalizada() {
+            return Ok(false);
+        }
+        self.certificados.insert(certificado.altura, certificado);
+        Ok(true)
+    }
+
+    /// Reaplica a cadeia inteira a partir do gênesis (ou do instantâneo de
+    /// que o nó partiu), sem os instantâneos intermediários, e devolve o
+    /// estado obtido. Deve coincidir sempre com `estado()`.
+    pub fn reproduzir_do_genesis(&self, config: &Config) -> Result<EstadoAcademico, CadeiaErro> {
+        self.instantaneos.apenas_base().restaurar(&self.cadeia, self.altura() as usize, config)
+    }
+
+    /// Exporta o estado resultante do bloco `altura`, assinado pela
+    /// autoridade.
+    pub fn exportar_instantaneo(
+        &self,
+        altura: u32,
+        chave_privada: &RsaPrivateKey,
+        id_autoridade: u32,
+        config: &Config,
+    ) -> Result<InstantaneoAssinado, InstantaneoErro> {
+        let bloco = self.bloco(altura).ok_or(InstantaneoErro::AlturaIndisponivel(altura))?;
+        let estado = self
+            .instantaneos
+            .restaurar(&self.cadeia, altura as usize, config)
+            .map_err(|_| InstantaneoErro::AlturaIndisponivel(altura))?;
+        Ok(InstantaneoAssinado::novo(bloco.clone(), estado, chave_privada, id_autoridade))
+    }
+
+    /// Cria e assina um bloco com as transações pendentes. As que deixaram de
+    /// ser válidas (por exemplo, depois de uma reorganização) são descartadas.
+    /// Falha, sem tocar no pool, se não for a vez de `id_autoridade` ou se
+    /// ela estiver suspensa.
     pub fn criar_e_adicionar_bloco(
         &mut self,
         chave_privada: &RsaPrivateKey,
         id_autoridade: u32,
-    ) -> Bloco {
-        let indice = self.cadeia.len() as u32;
-        let hash_anterior = self.cadeia.last().unwrap().hash_atual.clone();
-        let transacoes = self.transacoes_pendentes.drain(..).collect();
-
-        let mut novo_bloco = Bloco {
-            indice,
-            hash_anterior,
-            hash_atual: String::new(),
-            timestamp: Utc::now(),
-            transacoes,
-            id_autoridade,
-            assinatura_autoridade: String::new(),
-        };
+        permitir_vazio: bool,
+        config: &Config,
+    ) -> Result<Bloco, BlocoErro> {
+        if self.estado.autoridade_suspensa(id_autoridade) {
+            return Err(BlocoErro::AutoridadeSuspensa(id_autoridade));
+        }
+        let ultimo = self.cadeia.last().unwrap();
+        let timestamp = Utc::now();
+        let indice = self.altura() + 1;
+        let autoridades = self.autoridades_em(indice, config);
+        match consenso::espera_para_produzir(&ultimo.cabecalho, id_autoridade, timestamp, &autoridades, config) {
+            None => return Err(BlocoErro::AutoridadeDesconhecida),
+            Some(espera) if espera > chrono::Duration::zero() => {
+                return Err(BlocoErro::ForaDoTurno {
+                    id_autoridade,
+                    indice,
+                    esperada: consenso::autoridade_da_vez(indice, &autoridades).unwrap_or_default(),
+                });
+            }
+            Some(_) => {}
+        }
+        let hash_anterior = ultimo.hash_atual.clone();
+        let id_cadeia = ultimo.cabecalho.id_cadeia.clone();
+
+        self.mempool.expirar(&config.mempool, Utc::now());
+        // O pool é consumido mesmo se o bloco acabar vazio
+        self.estado_pendente = None;
+        let mut estado = self.estado.clone();
+        // O bloco vazio, já assinado, dá o tamanho de partida; cada transação
+        // acrescenta exatamente a sua codificação
+        let mut rascunho = Bloco::novo_bloco(&id_cadeia, indice, hash_anterior.clone(), Vec::new(), estado.raiz(), Some(timestamp));
+        rascunho.assinar_bloco(chave_privada, id_autoridade);
+        let mut tamanho = codificacao::codificar(&rascunho).len();
+        let parametros = &config.genesis.parametros_cadeia;
+        let limite = config.producao.max_transacoes_por_bloco.min(parametros.max_transacoes_bloco);
+
+        // Inclui as transações em ordem de chegada até os limites do bloco;
+        // as que não se aplicam ao estado são descartadas
+        let mut transacoes = Vec::new();
+        while transacoes.len() < limite {
+            let Some(tamanho_transacao) = self.mempool.transacoes().next().map(|t| codificacao::codificar(t).len()) else {
+                break;
+            };
+            let cabe = tamanho + tamanho_transacao <= parametros.tamanho_maximo_bloco;
+            // Uma transação que não cabe nem em um bloco vazio nunca será
+            // incluída; as demais esperam o próximo bloco
+            if !cabe && !transacoes.is_empty() {
+                break;
+            }
+            let transacao = self.mempool.retirar().unwrap();
+            if cabe && estado.aplicar(&transacao, config).is_ok() {
+                tamanho += tamanho_transacao;
+                transacoes.push(transacao);
+            }
+        }
+        if transacoes.is_empty() && !permitir_vazio {
+            return Err(BlocoErro::BlocoVazio);
+        }
+        estado.concluir_bloco(indice);
+
+        let mut novo_bloco = Bloco::novo_bloco(&id_cadeia, indice, hash_anterior, transacoes, estado.raiz(), Some(timestamp));
 
         novo_bloco.assinar_bloco(chave_privada, id_autoridade);
-        self.cadeia.push(novo_bloco.clone());
-        novo_bloco
+        self.anexar_bloco(novo_bloco.clone(), estado);
+        Ok(novo_bloco)
     }
 
     pub fn adicionar_bloco_externo(&mut self, bloco: Bloco, config: &Config) -> Result<(), BlocoErro> {
-        // Verifica se o hash anterior corresponde
-        if bloco.hash_anterior != self.cadeia.last().unwrap().hash_atual {
-            return Err(BlocoErro::HashAnteriorNaoCorresponde);
+        // Verifica encadeamento, hash e assinatura em relação ao último bloco,
+        // com as autoridades em vigor na altura do bloco
+        let autoridades = self.autoridades_em(bloco.cabecalho.indice, config);
+        let altura = self.altura() + 1;
+        Self::validar_bloco_seguinte(self.cadeia.last().unwrap(), &bloco, altura, &autoridades, config)?;
+        // Aplica as transações sobre uma cópia do estado e confere a raiz
+        let mut estado = self.estado.clone();
+        estado.aplicar_bloco(&bloco, config)?;
+
+        // Adiciona o bloco à cadeia e tira do pool o que ele já inclui
+        self.mempool.remover_incluidas(&bloco.transacoes);
+        self.anexar_bloco(bloco, estado);
+        Ok(())
+    }
+
+    pub fn consultar_por_estudante(&self, id_estudante: u32) -> Vec<RegistroIndexado<'_>> {
+        self.resolver(self.indices.por_estudante(id_estudante))
+    }
+
+    pub fn consultar_por_periodo(&self, ano: u32, semestre: u8) -> Vec<RegistroIndexado<'_>> {
+        self.resolver(self.indices.por_periodo(ano, semestre))
+    }
+
+    pub fn consultar_por_disciplina(&self, codigo: &str) -> Vec<RegistroIndexado<'_>> {
+        self.resolver(self.indices.por_disciplina(codigo))
+    }
+
+    pub fn consultar_por_transacao(&self, id_transacao: u32) -> Option<RegistroIndexado<'_>> {
+        self.indices
+            .por_transacao(id_transacao)
+            .and_then(|l| self.resolver(&[l]).pop())
+    }
+
+    /// Consolida todas as transações de um estudante em um histórico escolar.
+    pub fn historico_escolar(&self, id_estudante: u32) -> Option<HistoricoEscolar> {
+        let estudante = self.estado.estudante(id_estudante)?;
+        Some(HistoricoEscolar::consolidar(estudante, &self.consultar_por_estudante(id_estudante)))
+    }
+
+    /// Reconstrói os índices secundários a partir da cadeia.
+    pub fn reconstruir_indices(&mut self) {
+        self.indices = IndicesBlockchain::reconstruir(&self.cadeia);
+    }
+
+    fn situacao_disciplina(&self, tipo: &TipoTransacao) -> Option<SituacaoDisciplina> {
+        let disciplina = self
+            .estado
+            .disciplina(tipo.id_estudante()?, tipo.id_periodo()?, tipo.codigo_disciplina()?)?;
+        Some(disciplina.situacao)
+    }
+
+    fn resolver(&self, localizacoes: &[Localizacao]) -> Vec<RegistroIndexado<'_>> {
+        localizacoes
+            .iter()
+            .filter_map(|l| {
+                let bloco = self.bloco(l.altura as u32)?;
+                let transacao = bloco.transacoes.get(l.posicao)?;
+                Some(RegistroIndexado {
+                    altura: l.altura,
+                    hash_bloco: &bloco.hash_atual,
+                    transacao,
+                    situacao: self.situacao_disciplina(&transacao.tipo),
+                })
+            })
+            .collect()
+    }
+
+    /// Compara a cadeia local com blocos recebidos da rede e, se a regra de
+    /// escolha de bifurcação favorecer o ramo recebido, reorganiza a cadeia
+    /// local. As transações dos blocos órfãos que continuam válidas sobre o
+    /// novo ramo voltam para o pool. Nada é alterado se o ramo for inválido.
+    ///
+    /// `candidata` é uma sequência contígua da outra cadeia, até a ponta
+    /// dela: pode começar no gênesis ou em qualquer altura, desde que logo
+    /// após um bloco local. Os blocos que a cadeia local já tem (e, em um nó
+    /// iniciado por instantâneo, os anteriores ao checkpoint) são ignorados.
+    /// Só o ramo posterior ao ancestral comum é validado: o estado volta à
+    /// altura do ancestral e os blocos novos são aplicados sobre ele. Ramos
+    /// que divergem antes do último bloco final são rejeitados.
+    pub fn reorganizar(
+        &mut self,
+        candidata: Vec<Bloco>,
+        config: &Config,
+    ) -> Result<Option<Reorganizacao>, CadeiaErro> {
+        let mut candidata = candidata
+            .into_iter()
+            .skip_while(|bloco| bloco.cabecalho.indice < self.altura_base)
+            .peekable();
+        while candidata
+            .next_if(|bloco| self.bloco(bloco.cabecalho.indice).is_some_and(|local| local.hash_atual == bloco.hash_atual))
+            .is_some()
+        {}
+        let ramo_novo: Vec<Bloco> = candidata.collect();
+        let Some(primeiro) = ramo_novo.first() else {
+            return Ok(None);
+        };
+        let ancestral = primeiro
+            .cabecalho
+            .indice
+            .checked_sub(1)
+            .and_then(|altura| self.bloco(altura))
+            .filter(|bloco| bloco.hash_atual == primeiro.cabecalho.hash_anterior)
+            .ok_or(CadeiaErro::SemAncestralComum)?;
+        let altura_ancestral = ancestral.cabecalho.indice as usize;
+        if (altura_ancestral as u32) < self.altura_finalizada() {
+            return Err(CadeiaErro::BlocoFinalizado(self.altura_finalizada()));
         }
+        let posicao_ancestral = altura_ancestral - self.altura_base as usize;
 
-        // Verifica a assinatura do bloco
-        bloco.verificar_assinatura(config)?;
+        let estado_ancestral = self.instantaneos.restaurar(&self.cadeia, altura_ancestral, config)?;
+        let estados_ramo = Self::validar_blocos(ancestral, estado_ancestral, &ramo_novo, config)?;
+        let mut estado = estados_ramo.last().unwrap().clone();
 
-        // Adiciona o bloco à cadeia
-        self.cadeia.push(bloco);
-        Ok(())
+        let ramo_local = &self.cadeia[posicao_ancestral + 1..];
+        if bifurcacao::escolher_ramo(ramo_local, &ramo_novo, config) == DecisaoBifurcacao::ManterLocal {
+            return Ok(None);
+        }
+
+        // Órfãs e pendentes voltam ao pool se continuarem válidas sobre o
+        // novo ramo; as já incluídas nele caem como duplicadas.
+        let agora = Utc::now();
+        let mut pendentes: Vec<EntradaMempool> = ramo_local
+            .iter()
+            .flat_map(|bloco| bloco.transacoes.iter().cloned())
+            .map(|transacao| EntradaMempool { hash: transacao.hash(), transacao, recebida_em: agora })
+            .filter(|e| estado.aplicar(&e.transacao, config).is_ok())
+            .collect();
+        let transacoes_devolvidas = pendentes.len();
+        pendentes.extend(
+            self.mempool
+                .entradas()
+                .filter(|e| estado.aplicar(&e.transacao, config).is_ok())
+                .cloned(),
+        );
+
+        let reorganizacao = Reorganizacao {
+            altura_ancestral,
+            blocos_removidos: self.cadeia.len() - posicao_ancestral - 1,
+            blocos_adicionados: ramo_novo.len(),
+            transacoes_devolvidas,
+        };
+
+        self.cadeia.truncate(posicao_ancestral + 1);
+        self.instantaneos.descartar_a_partir(altura_ancestral + 1);
+        self.reconstruir_indices();
+        for (bloco, estado) in ramo_novo.into_iter().zip(estados_ramo) {
+            self.anexar_bloco(bloco, estado);
+        }
+        self.mempool.substituir(pendentes);
+        self.estado_pendente = None;
+        Ok(Some(reorganizacao))
     }
 
-    pub fn salvar_em_disco(&self, caminho: &str) -> std::io::Result<()> {
-        let dados = serde_json::to_string(&self).unwrap();
-        let mut arquivo = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(caminho)?;
-        arquivo.write_all(dados.as_bytes())?;
+    /// Verifica a cadeia inteira, do gênesis (ou do bloco do instantâneo de
+    /// que o nó partiu) ao último bloco, e retorna o primeiro bloco que
+    /// falhar.
+    pub fn validar_cadeia(&self, config: &Config) -> Result<(), CadeiaErro> {
+        let (base, blocos) = self.cadeia.split_first().ok_or(CadeiaErro::CadeiaVazia)?;
+        if base.cabecalho.indice == 0 {
+            Self::validar_genesis(base, config).map_err(|erro| CadeiaErro::BlocoInvalido {
+                indice: 0,
+                hash: base.hash_atual.clone(),
+                erro,
+            })?;
+        }
+        let estado_base = self
+            .instantaneos
+            .apenas_base()
+            .restaurar(&self.cadeia, self.altura_base as usize, config)?;
+        Self::validar_blocos(base, estado_base, blocos, config).map(|_| ())
+    }
+
+    /// Valida `blocos` em sequência logo após `anterior`, cujo estado
+    /// resultante é `estado`, e devolve o estado resultante de cada um. O
+    /// erro identifica o primeiro bloco inválido.
+    fn validar_blocos(
+        anterior: &Bloco,
+        mut estado: EstadoAcademico,
+        blocos: &[Bloco],
+        config: &Config,
+    ) -> Result<Vec<EstadoAcademico>, CadeiaErro> {
+        let mut estados = Vec::with_capacity(blocos.len());
+        let mut anterior = anterior;
+        for (altura, bloco) in (anterior.cabecalho.indice + 1..).zip(blocos) {
+            let autoridades = estado.autoridades_em(bloco.cabecalho.indice, config);
+            Self::validar_bloco_seguinte(anterior, bloco, altura, &autoridades, config)
+                .and_then(|()| estado.aplicar_bloco(bloco, config))
+                .map_err(|erro| CadeiaErro::BlocoInvalido {
+                    indice: altura as usize,
+                    hash: bloco.hash_atual.clone(),
+                    erro,
+                })?;
+            estados.push(estado.clone());
+            anterior = bloco;
+        }
+        Ok(estados)
+    }
+
+    fn validar_genesis(bloco: &Bloco, config: &Config) -> Result<(), BlocoErro> {
+        bloco.cabecalho.verificar_versao()?;
+        if bloco.cabecalho.indice != 0 {
+            return Err(BlocoErro::IndiceInvalido { esperado: 0, encontrado: bloco.cabecalho.indice });
+        }
+        if bloco.hash_atual != bloco.calcular_hash() {
+            return Err(BlocoErro::HashInvalido);
+        }
+        bloco.verificar_raiz_merkle()?;
+        if bloco.hash_atual != Self::bloco_genesis(&config.genesis).hash_atual {
+            return Err(BlocoErro::GenesisInvalido);
+        }
         Ok(())
     }
 
-    pub fn carregar_do_disco(caminho: &str) -> Result<Self, Box<dyn std::error::Error>> {
+    /// Valida um bloco que ocuparia a posição `altura` da cadeia, logo após
+    /// `anterior`: limites dos parâmetros da cadeia, encadeamento, raiz de
+    /// Merkle, assinatura e vez da autoridade.
+    fn validar_bloco_seguinte(
+        anterior: &Bloco,
+        bloco: &Bloco,
+        altura: u32,
+        autoridades: &ConjuntoAutoridades,
+        config: &Config,
+    ) -> Result<(), BlocoErro> {
+        config.genesis.parametros_cadeia.verificar_bloco(bloco, altura, Utc::now())?;
+        cabecalho::validar_encadeamento(&anterior.cabecalho, &anterior.hash_atual, &bloco.cabecalho)?;
+        bloco.verificar_raiz_merkle()?;
+        bloco.verificar_assinatura(autoridades)?;
+        consenso::verificar_turno(&anterior.cabecalho, &bloco.cabecalho, autoridades, config)
+    }
+
+    /// Carrega a cadeia do armazenamento de blocos, validando-a do gênesis
+    /// em diante, junto com os certificados de finalidade gravados. Se o nó
+    /// partiu de um instantâneo, a cadeia é validada a partir dele.
+    pub fn carregar_do_armazenamento(
+        armazenamento: &ArmazenamentoBlocos,
+        config: &Config,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if armazenamento.altura() == 0 {
+            return Err("Armazenamento de blocos vazio".into());
+        }
+        let blocos = armazenamento.carregar_blocos()?;
+        // Um primeiro bloco que não é o gênesis só pode ser o de um instantâneo
+        let mut blockchain = if blocos[0].cabecalho.indice == 0 {
+            Self::com_blocos(blocos, config)?
+        } else {
+            let instantaneo = armazenamento
+                .carregar_instantaneo()?
+                .ok_or("Primeiro bloco armazenado não é o gênesis e não há instantâneo")?;
+            let checkpoint = config
+                .instantaneo
+                .hash_checkpoint
+                .clone()
+                .unwrap_or_else(|| instantaneo.bloco.hash_atual.clone());
+            instantaneo.verificar(&checkpoint, config)?;
+            let mut blocos = blocos.into_iter();
+            if blocos.next().map(|b| b.hash_atual) != Some(checkpoint) {
+                return Err("Primeiro bloco armazenado não é o do instantâneo".into());
+            }
+            Self::do_instantaneo(instantaneo).continuar_com(blocos, config)?
+        };
+        for certificado in armazenamento.carregar_certificados()? {
+            blockchain.registrar_certificado(certificado, config)?;
+        }
+        Ok(blockchain)
+    }
+
+    /// Lê o antigo formato `blockchain.json`; usado apenas na migração para o
+    /// armazenamento de blocos.
+    ///
+    /// Só cadeias já gravadas com cabeçalho de bloco são aceitas. As da
+    /// versão inicial, com os campos do cabeçalho soltos no bloco e cada
+    /// transação trazendo um retrato inteiro do estudante, não têm emissor
+    /// nem assinatura por transação, e seus hashes não seguem a codificação
+    /// canônica: não há como convertê-las em blocos válidos, e a rede precisa
+    /// partir de um novo gênesis.
+    pub fn carregar_do_disco(caminho: &str, config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
         let mut arquivo = File::open(caminho)?;
         let mut dados = String::new();
         arquivo.read_to_string(&mut dados)?;
         if dados.trim().is_empty() {
-            Err("Arquivo de blockchain vazio".into())
-        } else {
-            let mut blockchain: Blockchain = serde_json::from_str(&dados)?;
-            blockchain.transacoes_pendentes = VecDeque::new();
-            Ok(blockchain)
+            return Err("Arquivo de blockchain vazio".into());
+        }
+        let valor: serde_json::Value = serde_json::from_str(&dados)?;
+        if Self::formato_inicial(&valor) {
+            return Err(
+                "Arquivo de blockchain no formato da versão inicial, sem cabeçalho de bloco nem transações assinadas; a cadeia não pode ser migrada e precisa de um novo gênesis".into(),
+            );
+        }
+        let blockchain: Blockchain = serde_json::from_value(valor)?;
+        Ok(Self::com_blocos(blockchain.cadeia, config)?)
+    }
+
+    /// Blocos da versão inicial trazem `indice` no próprio bloco, e não no
+    /// cabeçalho.
+    fn formato_inicial(valor: &serde_json::Value) -> bool {
+        valor["cadeia"]
+            .as_array()
+            .is_some_and(|blocos| blocos.iter().any(|bloco| bloco.get("cabecalho").is_none() && bloco.get("indice").is_some()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::blockchain::testes::{self, matricula};
+    use crate::blockchain::RegistroAula;
+
+    fn cadeia_com_tres_blocos(config: &Config) -> Blockchain {
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, config);
+        testes::produzir(&mut blockchain, vec![matricula(2, 2)], 2, config);
+        testes::produzir(&mut blockchain, vec![matricula(3, 3)], 1, config);
+        blockchain
+    }
+
+    #[test]
+    fn validar_cadeia_aceita_cadeia_integra() {
+        let config = testes::config();
+        cadeia_com_tres_blocos(&config).validar_cadeia(&config).unwrap();
+    }
+
+    #[test]
+    fn validar_cadeia_aponta_o_primeiro_bloco_invalido() {
+        let config = testes::config();
+        let mut blockchain = cadeia_com_tres_blocos(&config);
+        let esperado = blockchain.cadeia[2].hash_atual.clone();
+        blockchain.cadeia[2].transacoes.clear();
+        blockchain.cadeia[3].transacoes.clear();
+
+        match blockchain.validar_cadeia(&config) {
+            Err(CadeiaErro::BlocoInvalido { indice, hash, .. }) => {
+                assert_eq!(indice, 2);
+                assert_eq!(hash, esperado);
+            }
+            outro => panic!("esperado bloco 2 inválido, obtido {:?}", outro),
         }
     }
+
+    #[test]
+    fn validar_cadeia_recusa_outro_genesis() {
+        let config = testes::config();
+        let mut blockchain = cadeia_com_tres_blocos(&config);
+        blockchain.cadeia[0].cabecalho.timestamp += chrono::Duration::seconds(1);
+
+        assert!(matches!(
+            blockchain.validar_cadeia(&config),
+            Err(CadeiaErro::BlocoInvalido { indice: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn pool_valida_cada_transacao_sobre_as_pendentes() {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        for transacao in testes::matricula_em_disciplina(1, 1) {
+            blockchain.adicionar_transacao(transacao, &config).unwrap();
+        }
+        blockchain.adicionar_transacao(testes::lancamento_nota(4, 1, 8.0), &config).unwrap();
+        assert!(matches!(
+            blockchain.adicionar_transacao(matricula(5, 1), &config),
+            Err(TransacaoErro::EstudanteJaMatriculado(1))
+        ));
+
+        let notas = |estado: &EstadoAcademico| estado.disciplina(1, 1, testes::DISCIPLINA).map(|d| d.notas.len());
+        assert_eq!(notas(blockchain.estado_com_pendentes(&config)), Some(1));
+        assert_eq!(notas(blockchain.estado()), None);
+
+        testes::produzir(&mut blockchain, Vec::new(), 1, &config);
+        assert!(blockchain.mempool().is_empty());
+        assert_eq!(notas(blockchain.estado()), Some(1));
+        assert_eq!(notas(blockchain.estado_com_pendentes(&config)), Some(1));
+    }
+
+    #[test]
+    fn producao_respeita_o_limite_por_bloco_e_pula_blocos_vazios() {
+        let mut config = testes::config();
+        config.producao.max_transacoes_por_bloco = 2;
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        let chave = testes::chave_autoridade(1);
+        assert!(matches!(
+            blockchain.criar_e_adicionar_bloco(chave, 1, false, &config),
+            Err(BlocoErro::BlocoVazio)
+        ));
+        assert_eq!(blockchain.altura(), 0);
+
+        for id in 1..=3 {
+            blockchain.adicionar_transacao(matricula(id, id), &config).unwrap();
+        }
+        let bloco = blockchain.criar_e_adicionar_bloco(chave, 1, false, &config).unwrap();
+        let ids: Vec<u32> = bloco.transacoes.iter().map(|t| t.id_transacao).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(blockchain.mempool().len(), 1);
+
+        let bloco = blockchain.criar_e_adicionar_bloco(chave, 1, false, &config).unwrap();
+        assert_eq!(bloco.transacoes[0].id_transacao, 3);
+        let vazio = blockchain.criar_e_adicionar_bloco(chave, 1, true, &config).unwrap();
+        assert!(vazio.transacoes.is_empty());
+        assert_eq!(blockchain.altura(), 3);
+    }
+
+    #[test]
+    fn despejo_refaz_o_estado_pendente() {
+        let mut config = testes::config();
+        config.mempool.capacidade = 1;
+        config.mempool.politica_despejo = PoliticaDespejo::DescartarMaisAntiga;
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        blockchain.adicionar_transacao(matricula(1, 1), &config).unwrap();
+
+        let despejada = blockchain.adicionar_transacao(matricula(2, 2), &config).unwrap();
+        assert_eq!(despejada.map(|t| t.id_transacao), Some(1));
+        let estado = blockchain.estado_com_pendentes(&config);
+        assert!(estado.estudante(1).is_none());
+        assert!(estado.estudante(2).is_some());
+    }
+
+    /// Cadeia local com um bloco e um ramo concorrente, mais pesado, de
+    /// dois blocos a partir do gênesis.
+    fn bifurcacao(config: &Config) -> (Blockchain, Vec<Bloco>) {
+        let mut local = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut local, vec![matricula(1, 1)], 1, config);
+        let mut outra = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut outra, vec![matricula(2, 2)], 1, config);
+        testes::produzir(&mut outra, vec![matricula(3, 3)], 2, config);
+        (local, outra.cadeia)
+    }
+
+    fn ids_pendentes(blockchain: &Blockchain) -> Vec<u32> {
+        let mut ids: Vec<u32> = blockchain.mempool().transacoes().map(|t| t.id_transacao).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn reorganizar_devolve_as_orfas_e_descarta_as_pendentes_ja_incluidas() {
+        let config = testes::config();
+        let (mut local, candidata) = bifurcacao(&config);
+        local.adicionar_transacao(matricula(3, 3), &config).unwrap();
+        local.adicionar_transacao(matricula(4, 4), &config).unwrap();
+        let ponta = candidata[2].hash_atual.clone();
+
+        let reorganizacao = local.reorganizar(candidata, &config).unwrap().unwrap();
+        assert_eq!(reorganizacao.altura_ancestral, 0);
+        assert_eq!(reorganizacao.blocos_removidos, 1);
+        assert_eq!(reorganizacao.blocos_adicionados, 2);
+        assert_eq!(reorganizacao.transacoes_devolvidas, 1);
+        assert_eq!(local.cadeia.last().unwrap().hash_atual, ponta);
+        assert_eq!(ids_pendentes(&local), [1, 4]);
+    }
+
+    #[test]
+    fn reorganizar_recusa_ramo_invalido_sem_alterar_a_cadeia() {
+        let config = testes::config();
+        let (mut local, mut candidata) = bifurcacao(&config);
+        candidata[2].transacoes.clear();
+        let ponta = local.cadeia.last().unwrap().hash_atual.clone();
+
+        assert!(matches!(
+            local.reorganizar(candidata, &config),
+            Err(CadeiaErro::BlocoInvalido { indice: 2, .. })
+        ));
+        assert_eq!(local.cadeia.last().unwrap().hash_atual, ponta);
+        assert!(ids_pendentes(&local).is_empty());
+    }
+
+    #[test]
+    fn reorganizar_recusa_divergencia_anterior_ao_bloco_final() {
+        let config = testes::config();
+        let (mut local, candidata) = bifurcacao(&config);
+        testes::certificar(&mut local, 1, &config);
+
+        assert!(matches!(
+            local.reorganizar(candidata, &config),
+            Err(CadeiaErro::BlocoFinalizado(1))
+        ));
+    }
+
+    fn indices_coincidem_com_a_reconstrucao(blockchain: &Blockchain) {
+        assert_eq!(blockchain.indices, IndicesBlockchain::reconstruir(&blockchain.cadeia));
+    }
+
+    #[test]
+    fn indices_incrementais_coincidem_com_a_reconstrucao() {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, testes::matricula_em_disciplina(1, 1), 1, &config);
+        testes::produzir(&mut blockchain, vec![testes::lancamento_nota(4, 1, 7.5), matricula(5, 2)], 2, &config);
+
+        indices_coincidem_com_a_reconstrucao(&blockchain);
+        assert_eq!(blockchain.consultar_por_estudante(1).len(), 4);
+        assert_eq!(blockchain.consultar_por_periodo(2024, 1).len(), 3);
+        assert_eq!(blockchain.consultar_por_disciplina(testes::DISCIPLINA).len(), 2);
+        assert_eq!(blockchain.consultar_por_transacao(4).unwrap().altura, 2);
+    }
+
+    #[test]
+    fn indices_coincidem_com_a_reconstrucao_depois_de_reorganizar() {
+        let config = testes::config();
+        let (mut local, candidata) = bifurcacao(&config);
+        local.reorganizar(candidata, &config).unwrap().unwrap();
+
+        indices_coincidem_com_a_reconstrucao(&local);
+        assert!(local.consultar_por_transacao(1).is_none());
+        assert_eq!(local.consultar_por_transacao(3).unwrap().altura, 2);
+        assert!(local.consultar_por_estudante(1).is_empty());
+    }
+
+    #[test]
+    fn indices_coincidem_com_a_reconstrucao_a_partir_de_um_instantaneo() {
+        let config = testes::config();
+        let mut origem = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut origem, vec![matricula(1, 1)], 1, &config);
+        testes::produzir(&mut origem, vec![matricula(2, 2)], 2, &config);
+        let instantaneo = origem.exportar_instantaneo(2, testes::chave_autoridade(1), 1, &config).unwrap();
+        let bloco_3 = testes::produzir(&mut origem, vec![matricula(3, 3)], 1, &config);
+
+        let mut blockchain = Blockchain::do_instantaneo(instantaneo);
+        blockchain.adicionar_bloco_externo(bloco_3, &config).unwrap();
+
+        indices_coincidem_com_a_reconstrucao(&blockchain);
+        assert!(blockchain.consultar_por_transacao(1).is_none());
+        assert_eq!(blockchain.consultar_por_transacao(2).unwrap().altura, 2);
+        assert_eq!(blockchain.consultar_por_transacao(3).unwrap().altura, 3);
+    }
+
+    fn encerramento(id_transacao: u32, id_estudante: u32, media: f32, frequencia: f32, situacao: SituacaoDisciplina) -> Transacao {
+        testes::transacao(
+            id_transacao,
+            TipoTransacao::EncerramentoDisciplina {
+                id_estudante,
+                id_periodo: 1,
+                codigo_disciplina: testes::DISCIPLINA.into(),
+                media,
+                frequencia,
+                situacao,
+            },
+        )
+    }
+
+    #[test]
+    fn consultas_trazem_a_situacao_na_disciplina() {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        let mut transacoes = Vec::new();
+        for id_estudante in 1..=3 {
+            transacoes.extend(testes::matricula_em_disciplina(id_estudante * 10, id_estudante));
+        }
+        testes::produzir(&mut blockchain, transacoes, 1, &config);
+        let ausencia = testes::transacao(
+            43,
+            TipoTransacao::LancamentoFrequencia {
+                id_estudante: 3,
+                id_periodo: 1,
+                codigo_disciplina: testes::DISCIPLINA.into(),
+                aula: RegistroAula { data: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), presente: false },
+            },
+        );
+        let notas = vec![
+            testes::lancamento_nota(13, 1, 8.0),
+            testes::lancamento_nota(23, 2, 4.0),
+            testes::lancamento_nota(33, 3, 9.0),
+            ausencia,
+        ];
+        testes::produzir(&mut blockchain, notas, 2, &config);
+        let encerramentos = vec![
+            encerramento(14, 1, 8.0, 0.0, SituacaoDisciplina::Aprovado),
+            encerramento(24, 2, 4.0, 0.0, SituacaoDisciplina::ReprovadoPorNota),
+            encerramento(34, 3, 9.0, 0.0, SituacaoDisciplina::ReprovadoPorFalta),
+        ];
+        testes::produzir(&mut blockchain, encerramentos, 1, &config);
+
+        let situacoes = |registros: Vec<RegistroIndexado>| -> BTreeMap<u32, Option<SituacaoDisciplina>> {
+            registros
+                .into_iter()
+                .filter(|r| r.transacao.tipo.codigo_disciplina().is_some())
+                .map(|r| (r.transacao.tipo.id_estudante().unwrap(), r.situacao))
+                .collect()
+        };
+        let esperadas = BTreeMap::from([
+            (1, Some(SituacaoDisciplina::Aprovado)),
+            (2, Some(SituacaoDisciplina::ReprovadoPorNota)),
+            (3, Some(SituacaoDisciplina::ReprovadoPorFalta)),
+        ]);
+        assert_eq!(situacoes(blockchain.consultar_por_disciplina(testes::DISCIPLINA)), esperadas);
+        assert_eq!(situacoes(blockchain.consultar_por_periodo(2024, 1)), esperadas);
+
+        let do_estudante_2 = blockchain.consultar_por_estudante(2);
+        assert_eq!(do_estudante_2.len(), 5);
+        assert!(do_estudante_2[0].situacao.is_none());
+        assert!(do_estudante_2[1].situacao.is_none());
+        assert!(do_estudante_2[2..].iter().all(|r| r.situacao == Some(SituacaoDisciplina::ReprovadoPorNota)));
+    }
 }
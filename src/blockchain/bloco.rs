@@ -1,98 +1,146 @@
-use base64::{decode, encode};
+use base64::encode;
 use chrono::{DateTime, Utc};
-use rsa::pkcs1v15::{Signature as RsaSignature, SigningKey, VerifyingKey};
+use rsa::pkcs1v15::SigningKey;
 use rsa::RsaPrivateKey;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use signature::{Signer, Verifier};
+use sha2::Sha256;
+use signature::Signer;
 
-use crate::utils::config::Config;
 use crate::utils::erros::BlocoErro;
+use crate::utils::helpers::de_hex;
 
-use super::Transacao;
+use super::codificacao::{CodificacaoCanonica, Codificador, VERSAO_FORMATO_BLOCO};
+use super::{merkle, CabecalhoBloco, ConjuntoAutoridades, Transacao};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Bloco {
-    pub indice: u32,
-    pub hash_anterior: String,
+    pub cabecalho: CabecalhoBloco,
     pub hash_atual: String,
-    pub timestamp: DateTime<Utc>,
     pub transacoes: Vec<Transacao>,
-    pub id_autoridade: u32,
     pub assinatura_autoridade: String,
 }
 
 impl Bloco {
     pub fn novo_bloco(
+        id_cadeia: &str,
         indice: u32,
         hash_anterior: String,
         transacoes: Vec<Transacao>,
+        raiz_estado: String,
         timestamp: Option<DateTime<Utc>>,
     ) -> Self {
         Bloco {
-            indice,
-            hash_anterior,
+            cabecalho: CabecalhoBloco {
+                versao: VERSAO_FORMATO_BLOCO,
+                id_cadeia: id_cadeia.to_string(),
+                indice,
+                hash_anterior,
+                raiz_merkle: merkle::raiz_merkle(&transacoes),
+                raiz_estado,
+                timestamp: timestamp.unwrap_or_else(Utc::now),
+                id_autoridade: 0,
+            },
             hash_atual: String::new(),
-            timestamp: timestamp.unwrap_or_else(|| Utc::now()),
             transacoes,
-            id_autoridade: 0,
             assinatura_autoridade: String::new(),
         }
     }
 
+    /// O hash do bloco é o hash do cabeçalho; as transações entram por meio
+    /// da raiz de Merkle.
     pub fn calcular_hash(&self) -> String {
-        let mut bloco_clone = self.clone();
-        bloco_clone.hash_atual = String::new();
-        let bloco_serializado = serde_json::to_string(&bloco_clone).unwrap();
-        let mut hasher = Sha256::new();
-        hasher.update(bloco_serializado.as_bytes());
-        let resultado = hasher.finalize();
-        format!("{:x}", resultado)
+        self.cabecalho.calcular_hash()
     }
 
+    /// Verifica se a raiz de Merkle do cabeçalho corresponde às transações.
+    pub fn verificar_raiz_merkle(&self) -> Result<(), BlocoErro> {
+        if self.cabecalho.raiz_merkle != merkle::raiz_merkle(&self.transacoes) {
+            return Err(BlocoErro::RaizMerkleInvalida);
+        }
+        Ok(())
+    }
+
+    /// Define a autoridade no cabeçalho e assina exatamente o hash do
+    /// cabeçalho resultante.
     pub fn assinar_bloco(&mut self, chave_privada: &RsaPrivateKey, id_autoridade: u32) {
-        self.id_autoridade = id_autoridade;
+        self.cabecalho.id_autoridade = id_autoridade;
         self.hash_atual = self.calcular_hash();
 
-        let dados_para_assinar = format!(
-            "{}{}{}{}{:?}",
-            self.indice,
-            self.hash_anterior,
-            self.hash_atual,
-            self.timestamp,
-            self.transacoes
-        );
+        let hash_bytes = de_hex(&self.hash_atual).unwrap();
 
         let signing_key = SigningKey::<Sha256>::new(chave_privada.clone());
 
-        let assinatura = signing_key.sign(dados_para_assinar.as_bytes());
+        let assinatura = signing_key.sign(&hash_bytes);
         self.assinatura_autoridade = encode(assinatura.as_ref());
     }
 
     pub fn verificar_assinatura(
         &self,
-        config: &Config,
+        autoridades: &ConjuntoAutoridades,
     ) -> Result<(), BlocoErro> {
-        let chave_publica = config
-            .obter_chave_publica(self.id_autoridade)
-            .ok_or(BlocoErro::AutoridadeDesconhecida)?;
-
-        let dados_assinados = format!(
-            "{}{}{}{}{:?}",
-            self.indice,
-            self.hash_anterior,
-            self.hash_atual,
-            self.timestamp,
-            self.transacoes
-        );
-
-        let assinatura_bytes = decode(&self.assinatura_autoridade).map_err(|_| BlocoErro::AssinaturaInvalida)?;
-        let assinatura = RsaSignature::from(assinatura_bytes.into_boxed_slice());
-
-        let verifying_key = VerifyingKey::<Sha256>::new(chave_publica.clone());
-
-        verifying_key
-            .verify(dados_assinados.as_bytes(), &assinatura)
-            .map_err(|_| BlocoErro::AssinaturaInvalida)
+        self.cabecalho
+            .verificar_assinatura(&self.hash_atual, &self.assinatura_autoridade, autoridades)
+    }
+}
+
+/// Usada para medir o tamanho do bloco; o hash do bloco cobre só o
+/// cabeçalho.
+impl CodificacaoCanonica for Bloco {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .valor(&self.cabecalho)
+            .texto(&self.hash_atual)
+            .lista(&self.transacoes)
+            .texto(&self.assinatura_autoridade);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes::{self, matricula};
+
+    fn bloco_assinado() -> Bloco {
+        let transacoes = vec![matricula(1, 1), matricula(2, 2)];
+        let mut bloco = Bloco::novo_bloco("uniblockchain", 1, "0".repeat(64), transacoes, String::new(), None);
+        bloco.assinar_bloco(testes::chave_autoridade(1), 1);
+        bloco
+    }
+
+    #[test]
+    fn alterar_o_corpo_quebra_a_raiz_de_merkle() {
+        let mut bloco = bloco_assinado();
+        assert!(bloco.verificar_raiz_merkle().is_ok());
+
+        bloco.transacoes.pop();
+        assert!(matches!(bloco.verificar_raiz_merkle(), Err(BlocoErro::RaizMerkleInvalida)));
+
+        let mut bloco = bloco_assinado();
+        bloco.transacoes.swap(0, 1);
+        assert!(matches!(bloco.verificar_raiz_merkle(), Err(BlocoErro::RaizMerkleInvalida)));
+    }
+
+    #[test]
+    fn assinatura_cobre_so_o_hash_do_cabecalho() {
+        let config = testes::config();
+        let autoridades = ConjuntoAutoridades::do_genesis(&config);
+        let bloco = bloco_assinado();
+        assert!(bloco.verificar_assinatura(&autoridades).is_ok());
+
+        // O corpo não entra no hash: só a raiz de Merkle denuncia a troca.
+        let mut corpo_trocado = bloco.clone();
+        corpo_trocado.transacoes = vec![matricula(3, 3)];
+        assert_eq!(corpo_trocado.calcular_hash(), bloco.hash_atual);
+        assert!(corpo_trocado.verificar_assinatura(&autoridades).is_ok());
+        assert!(corpo_trocado.verificar_raiz_merkle().is_err());
+
+        let mut cabecalho_trocado = bloco.clone();
+        cabecalho_trocado.cabecalho.raiz_estado = "ff".into();
+        assert!(matches!(cabecalho_trocado.verificar_assinatura(&autoridades), Err(BlocoErro::HashInvalido)));
+
+        let mut outra_autoridade = bloco.clone();
+        outra_autoridade.cabecalho.id_autoridade = 2;
+        outra_autoridade.hash_atual = outra_autoridade.calcular_hash();
+        assert!(matches!(outra_autoridade.verificar_assinatura(&autoridades), Err(BlocoErro::AssinaturaInvalida)));
     }
 }
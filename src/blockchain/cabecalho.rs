@@ -0,0 +1,111 @@
+use base64::decode;
+use chrono::{DateTime, Utc};
+use rsa::pkcs1v15::{Signature as RsaSignature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use signature::Verifier;
+
+use crate::utils::erros::BlocoErro;
+use crate::utils::helpers::de_hex;
+
+use super::codificacao::{self, CodificacaoCanonica, Codificador, VERSAO_FORMATO_BLOCO};
+use super::ConjuntoAutoridades;
+
+/// Cabeçalho do bloco: tudo o que o hash do bloco cobre. As transações
+/// entram apenas pela raiz de Merkle, de modo que um cliente leve consegue
+/// encadear e verificar cabeçalhos sem baixar os corpos.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CabecalhoBloco {
+    pub versao: u32,
+    pub id_cadeia: String,
+    pub indice: u32,
+    pub hash_anterior: String,
+    pub raiz_merkle: String,
+    /// Raiz do estado acadêmico depois de aplicadas as transações do bloco.
+    pub raiz_estado: String,
+    pub timestamp: DateTime<Utc>,
+    pub id_autoridade: u32,
+}
+
+impl CodificacaoCanonica for CabecalhoBloco {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .u32(self.versao)
+            .texto(&self.id_cadeia)
+            .u32(self.indice)
+            .texto(&self.hash_anterior)
+            .texto(&self.raiz_merkle)
+            .texto(&self.raiz_estado)
+            .data_hora(&self.timestamp)
+            .u32(self.id_autoridade);
+    }
+}
+
+impl CabecalhoBloco {
+    pub fn calcular_hash(&self) -> String {
+        codificacao::hash_hex(&codificacao::codificar(self))
+    }
+
+    /// Rejeita cabeçalhos gravados em um formato que este nó não conhece.
+    pub fn verificar_versao(&self) -> Result<(), BlocoErro> {
+        if self.versao != VERSAO_FORMATO_BLOCO {
+            return Err(BlocoErro::VersaoNaoSuportada(self.versao));
+        }
+        Ok(())
+    }
+
+    /// Verifica se `hash` é o hash deste cabeçalho e se `assinatura` é a
+    /// assinatura desse hash pela autoridade indicada no próprio cabeçalho,
+    /// com a chave que ela tem no conjunto em vigor na altura do bloco.
+    pub fn verificar_assinatura(
+        &self,
+        hash: &str,
+        assinatura: &str,
+        autoridades: &ConjuntoAutoridades,
+    ) -> Result<(), BlocoErro> {
+        if hash != self.calcular_hash() {
+            return Err(BlocoErro::HashInvalido);
+        }
+
+        let chave_publica = autoridades
+            .chave(self.id_autoridade)
+            .ok_or(BlocoErro::AutoridadeDesconhecida)?;
+
+        let hash_bytes = de_hex(hash).ok_or(BlocoErro::HashInvalido)?;
+        let assinatura_bytes = decode(assinatura).map_err(|_| BlocoErro::AssinaturaInvalida)?;
+        let assinatura = RsaSignature::from(assinatura_bytes.into_boxed_slice());
+
+        let verifying_key = VerifyingKey::<Sha256>::new(chave_publica.clone());
+
+        verifying_key
+            .verify(&hash_bytes, &assinatura)
+            .map_err(|_| BlocoErro::AssinaturaInvalida)
+    }
+}
+
+/// Verifica se `cabecalho` continua corretamente o cabeçalho `anterior`,
+/// cujo hash é `hash_anterior`. Junto com `verificar_assinatura`, é tudo o
+/// que um cliente leve precisa para validar uma sequência de cabeçalhos.
+pub fn validar_encadeamento(
+    anterior: &CabecalhoBloco,
+    hash_anterior: &str,
+    cabecalho: &CabecalhoBloco,
+) -> Result<(), BlocoErro> {
+    cabecalho.verificar_versao()?;
+    if cabecalho.id_cadeia != anterior.id_cadeia {
+        return Err(BlocoErro::CadeiaIncorreta);
+    }
+    if cabecalho.indice != anterior.indice + 1 {
+        return Err(BlocoErro::IndiceInvalido {
+            esperado: anterior.indice + 1,
+            encontrado: cabecalho.indice,
+        });
+    }
+    if cabecalho.hash_anterior != hash_anterior {
+        return Err(BlocoErro::HashAnteriorNaoCorresponde);
+    }
+    if cabecalho.timestamp < anterior.timestamp {
+        return Err(BlocoErro::TimestampRetroativo);
+    }
+    Ok(())
+}
@@ -0,0 +1,298 @@
+//! Codificação binária canônica usada em tudo o que a cadeia assina ou
+//! resume por hash. Ao contrário do JSON do serde ou da saída `Debug`, ela
+//! não depende da ordem de campos, da formatação de floats nem da versão do
+//! compilador: inteiros são big-endian de largura fixa, textos e listas são
+//! prefixados pelo comprimento (u32) e floats são gravados pelos seus bits
+//! IEEE 754 normalizados.
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::utils::helpers::para_hex;
+
+/// Versão atual do formato de bloco. Muda sempre que a codificação
+/// canônica ou o conjunto de campos do cabeçalho mudar.
+pub const VERSAO_FORMATO_BLOCO: u32 = 1;
+
+#[derive(Default)]
+pub struct Codificador {
+    bytes: Vec<u8>,
+}
+
+impl Codificador {
+    pub fn novo() -> Self {
+        Codificador::default()
+    }
+
+    pub fn u8(&mut self, valor: u8) -> &mut Self {
+        self.bytes.push(valor);
+        self
+    }
+
+    pub fn u32(&mut self, valor: u32) -> &mut Self {
+        self.bytes.extend_from_slice(&valor.to_be_bytes());
+        self
+    }
+
+    pub fn i32(&mut self, valor: i32) -> &mut Self {
+        self.bytes.extend_from_slice(&valor.to_be_bytes());
+        self
+    }
+
+    pub fn i64(&mut self, valor: i64) -> &mut Self {
+        self.bytes.extend_from_slice(&valor.to_be_bytes());
+        self
+    }
+
+    /// Zero negativo vira zero e qualquer NaN vira o NaN silencioso padrão,
+    /// para que valores iguais sempre produzam os mesmos bytes.
+    pub fn f32(&mut self, valor: f32) -> &mut Self {
+        let bits = if valor.is_nan() {
+            0x7fc0_0000
+        } else if valor == 0.0 {
+            0
+        } else {
+            valor.to_bits()
+        };
+        self.u32(bits)
+    }
+
+    pub fn bytes(&mut self, valor: &[u8]) -> &mut Self {
+        self.u32(valor.len() as u32);
+        self.bytes.extend_from_slice(valor);
+        self
+    }
+
+    pub fn texto(&mut self, valor: &str) -> &mut Self {
+        self.bytes(valor.as_bytes())
+    }
+
+    /// Datas são gravadas como número de dias desde 0001-01-01 (CE).
+    pub fn data(&mut self, valor: &NaiveDate) -> &mut Self {
+        self.i32(valor.num_days_from_ce())
+    }
+
+    pub fn data_hora(&mut self, valor: &DateTime<Utc>) -> &mut Self {
+        self.i64(valor.timestamp());
+        self.u32(valor.timestamp_subsec_nanos())
+    }
+
+    pub fn lista<T: CodificacaoCanonica>(&mut self, itens: &[T]) -> &mut Self {
+        self.u32(itens.len() as u32);
+        for item in itens {
+            item.codificar(self);
+        }
+        self
+    }
+
+    pub fn valor<T: CodificacaoCanonica + ?Sized>(&mut self, valor: &T) -> &mut Self {
+        valor.codificar(self);
+        self
+    }
+
+    pub fn finalizar(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Cada tipo implementa a própria codificação no módulo em que é definido.
+pub trait CodificacaoCanonica {
+    fn codificar(&self, saida: &mut Codificador);
+}
+
+/// Bytes canônicos de um valor.
+pub fn codificar<T: CodificacaoCanonica + ?Sized>(valor: &T) -> Vec<u8> {
+    let mut codificador = Codificador::novo();
+    valor.codificar(&mut codificador);
+    codificador.finalizar()
+}
+
+/// SHA-256, em hexadecimal, de uma sequência de bytes canônicos.
+pub fn hash_hex(bytes: &[u8]) -> String {
+    para_hex(&Sha256::digest(bytes))
+}
+
+/// Vetores de referência da codificação canônica: o SHA-256 esperado dos
+/// bytes de valores fixos. Qualquer mudança que os altere invalida as
+/// assinaturas já gravadas e exige uma nova `VERSAO_FORMATO_BLOCO`.
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::blockchain::equivocacao::ProvaEquivocacao;
+    use crate::blockchain::estado::EstadoAcademico;
+    use crate::blockchain::finalidade::Etapa;
+    use crate::blockchain::{
+        merkle, Arredondamento, AssinaturaAutoridade, CabecalhoBloco, Disciplina, EspecificacaoGenesis, Estudante, Nota, PeriodoLetivo, PoliticaAvaliacao, TipoMedia, TipoTransacao, Transacao, Voto,
+    };
+
+    struct Valores {
+        nota: Nota,
+        nota_zero_negativo: Nota,
+        nota_zero: Nota,
+        disciplina: Disciplina,
+        estudante: Estudante,
+        transacao: Transacao,
+        transacao_autoridades: Transacao,
+        transacao_suspensao: Transacao,
+        especificacao: EspecificacaoGenesis,
+        cabecalho_genesis: CabecalhoBloco,
+    }
+
+    fn valores() -> Valores {
+        let nota = Nota::nova_nota(7, 8.5, "Prova", NaiveDate::from_ymd_opt(2024, 3, 15).unwrap());
+        let mut nota_zero_negativo = nota.clone();
+        nota_zero_negativo.valor = -0.0;
+        let mut nota_zero = nota.clone();
+        nota_zero.valor = 0.0;
+        let mut disciplina = Disciplina::nova_disciplina(3, "Cálculo I", "MAT101");
+        disciplina.adicionar_nota(nota.clone());
+        disciplina.registrar_aula(NaiveDate::from_ymd_opt(2024, 3, 11).unwrap(), true);
+        disciplina.registrar_aula(NaiveDate::from_ymd_opt(2024, 3, 13).unwrap(), false);
+        disciplina.recalcular(&PoliticaAvaliacao::default());
+        disciplina.encerrar(&PoliticaAvaliacao::default());
+        let mut periodo = PeriodoLetivo::novo_periodo(2, 2024, 1);
+        periodo.adicionar_disciplina(disciplina.clone());
+        let mut estudante = Estudante::novo_estudante(42, "Ana", NaiveDate::from_ymd_opt(2001, 12, 31).unwrap());
+        estudante.adicionar_periodo_letivo(periodo);
+        let transacao = Transacao {
+            id_transacao: 1,
+            timestamp: Utc.timestamp_opt(1_700_000_000, 5).unwrap(),
+            tipo: TipoTransacao::LancamentoNota {
+                id_estudante: 42,
+                id_periodo: 2,
+                codigo_disciplina: String::from("MAT101"),
+                nota: nota.clone(),
+            },
+            id_emissor: 5,
+            assinatura: String::from("YXNzaW5hdHVyYQ=="),
+        };
+        let transacao_autoridades = Transacao {
+            id_transacao: 2,
+            timestamp: Utc.timestamp_opt(1_700_000_000, 5).unwrap(),
+            tipo: TipoTransacao::AlteracaoAutoridades {
+                id_autoridade: 3,
+                chave_publica_pem: None,
+                altura_vigencia: 10,
+                assinaturas: vec![AssinaturaAutoridade {
+                    id_autoridade: 1,
+                    assinatura: String::from("YXByb3ZhZG8="),
+                }],
+            },
+            id_emissor: 1,
+            assinatura: String::new(),
+        };
+        let voto = Voto {
+            etapa: Etapa::Precommit,
+            id_cadeia: String::from("uniblockchain"),
+            altura: 4,
+            rodada: 1,
+            hash_bloco: String::from("aa"),
+            id_autoridade: 2,
+            assinatura: String::from("dm90bw=="),
+        };
+        let transacao_suspensao = Transacao {
+            id_transacao: 3,
+            timestamp: Utc.timestamp_opt(1_700_000_000, 5).unwrap(),
+            tipo: TipoTransacao::SuspensaoAutoridade {
+                prova: ProvaEquivocacao::VotosConflitantes(
+                    voto.clone(),
+                    Voto { hash_bloco: String::from("bb"), ..voto },
+                ),
+            },
+            id_emissor: 1,
+            assinatura: String::new(),
+        };
+
+        let especificacao = EspecificacaoGenesis {
+            id_cadeia: String::from("uniblockchain"),
+            instituicao: String::from("Universidade Exemplo"),
+            pesos_autoridades: [(1, 3)].into(),
+            politica_avaliacao: PoliticaAvaliacao {
+                tipo_media: TipoMedia::Ponderada([(String::from("Trabalho"), 1.0), (String::from("Prova"), 2.0)].into()),
+                tipo_recuperacao: Some(String::from("Recuperacao")),
+                arredondamento: Arredondamento::CasasDecimais(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let cabecalho_genesis = CabecalhoBloco {
+            versao: VERSAO_FORMATO_BLOCO,
+            id_cadeia: String::from("uniblockchain"),
+            indice: 0,
+            hash_anterior: especificacao.hash(),
+            raiz_merkle: merkle::raiz_merkle(&[]),
+            raiz_estado: EstadoAcademico::default().raiz(),
+            timestamp: Utc.timestamp_opt(0, 0).unwrap(),
+            id_autoridade: 0,
+        };
+
+        Valores {
+            nota,
+            nota_zero_negativo,
+            nota_zero,
+            disciplina,
+            estudante,
+            transacao,
+            transacao_autoridades,
+            transacao_suspensao,
+            especificacao,
+            cabecalho_genesis,
+        }
+    }
+
+    fn conferir(bytes: Vec<u8>, esperado: &str) {
+        assert_eq!(hash_hex(&bytes), esperado);
+    }
+
+    #[test]
+    fn vetor_nota() {
+        conferir(codificar(&valores().nota), "4abe6b344eaa789453b65b400639ad63e9899e7163d43226d6f7d7d7df77a0ac");
+    }
+
+    #[test]
+    fn vetor_nota_com_zero_negativo() {
+        conferir(codificar(&valores().nota_zero_negativo), "d40baa7f91235f6f1c6bbbf03ba84921d225074d5210bffb2587688d8ea0dd8d");
+    }
+
+    #[test]
+    fn vetor_nota_com_zero() {
+        conferir(codificar(&valores().nota_zero), "d40baa7f91235f6f1c6bbbf03ba84921d225074d5210bffb2587688d8ea0dd8d");
+    }
+
+    #[test]
+    fn vetor_disciplina() {
+        conferir(codificar(&valores().disciplina), "e28b322723c17e506b1061b8c88ce162fabdd02c01d3264d6f4efc6acf1635f8");
+    }
+
+    #[test]
+    fn vetor_estudante() {
+        conferir(codificar(&valores().estudante), "e5f80a806af4929fc4491ff904ee9397673b136735c3b41377f61c05049144c0");
+    }
+
+    #[test]
+    fn vetor_transacao() {
+        conferir(codificar(&valores().transacao), "5cd917805a0d83b670f9f33c65f02b55810aff126cd93cd605b01d135eb4333e");
+    }
+
+    #[test]
+    fn vetor_transacao_de_autoridades() {
+        conferir(codificar(&valores().transacao_autoridades), "6ed00de5fab338133ace17b8786b1b3ca862ff9ac7ca24f148a29e372cd769d2");
+    }
+
+    #[test]
+    fn vetor_transacao_de_suspensao() {
+        conferir(codificar(&valores().transacao_suspensao), "5d70b651e16af1c3c57da70d6a69fb00021b89ec944262077eb070671a4b2690");
+    }
+
+    #[test]
+    fn vetor_especificacao_do_genesis() {
+        conferir(codificar(&valores().especificacao), "1f54df60f561b0c83a902f7828fe15ae2a470972b37c47f05332a85c4a5b4d8a");
+    }
+
+    #[test]
+    fn vetor_cabecalho_genesis() {
+        conferir(codificar(&valores().cabecalho_genesis), "b32cadb2756821a4023e2a3ab4b7949f4e3c4b7c596b73bb3109a2a14e9bcc7d");
+    }
+}
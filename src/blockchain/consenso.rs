@@ -0,0 +1,188 @@
+//! Prova de autoridade com rodízio: as autoridades em vigor, em ordem
+//! crescente de id, se revezam na produção dos blocos pela altura. Se a autoridade da vez
+//! não produzir o bloco dentro de `tempo_limite_slot` contado a partir do
+//! timestamp do bloco anterior, a seguinte no rodízio também passa a poder
+//! produzi-lo, e assim por diante.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::utils::config::Config;
+use crate::utils::erros::BlocoErro;
+
+use super::{CabecalhoBloco, ConjuntoAutoridades};
+
+#[derive(Debug, Clone)]
+pub struct ConfigConsenso {
+    /// Tempo que cada autoridade tem para produzir o bloco antes de a
+    /// seguinte no rodízio poder assumir. Zero desativa o rodízio.
+    pub tempo_limite_slot: Duration,
+}
+
+impl Default for ConfigConsenso {
+    fn default() -> Self {
+        ConfigConsenso {
+            tempo_limite_slot: Duration::seconds(30),
+        }
+    }
+}
+
+/// Produção automática de blocos em nós de autoridade.
+#[derive(Debug, Clone)]
+pub struct ConfigProducao {
+    /// Intervalo entre as tentativas de produzir um bloco. Zero desativa a
+    /// produção automática, deixando só o comando `criar_bloco`.
+    pub intervalo: Duration,
+    /// Máximo de transações do pool incluídas em cada bloco, limitado
+    /// também pelos parâmetros da cadeia; as demais ficam para os blocos
+    /// seguintes.
+    pub max_transacoes_por_bloco: usize,
+    /// Não produz blocos automaticamente quando não há transações válidas.
+    pub pular_blocos_vazios: bool,
+}
+
+impl Default for ConfigProducao {
+    fn default() -> Self {
+        ConfigProducao {
+            intervalo: Duration::seconds(10),
+            max_transacoes_por_bloco: 500,
+            pular_blocos_vazios: true,
+        }
+    }
+}
+
+/// Autoridade da vez para produzir o bloco de `altura`, entre as
+/// autoridades em vigor nela.
+pub fn autoridade_da_vez(altura: u32, autoridades: &ConjuntoAutoridades) -> Option<u32> {
+    let ids = autoridades.ids();
+    if ids.is_empty() {
+        return None;
+    }
+    Some(ids[altura as usize % ids.len()])
+}
+
+/// Quantas posições depois da autoridade da vez `id_autoridade` está no
+/// rodízio da `altura`.
+fn posicao_no_rodizio(altura: u32, id_autoridade: u32, autoridades: &ConjuntoAutoridades) -> Option<u32> {
+    let ids = autoridades.ids();
+    let posicao = ids.iter().position(|&id| id == id_autoridade)?;
+    let primeira = altura as usize % ids.len();
+    Some(((posicao + ids.len() - primeira) % ids.len()) as u32)
+}
+
+/// A partir de quanto tempo depois do bloco anterior a autoridade pode
+/// produzir o bloco seguinte.
+fn inicio_do_slot(altura: u32, id_autoridade: u32, autoridades: &ConjuntoAutoridades, config: &Config) -> Option<Duration> {
+    let posicao = posicao_no_rodizio(altura, id_autoridade, autoridades)?;
+    Some(config.genesis.consenso.tempo_limite_slot * posicao as i32)
+}
+
+/// Quanto falta para `id_autoridade` poder produzir o bloco seguinte a
+/// `anterior`; zero se já pode. `None` se ela não for autoridade em vigor
+/// na altura desse bloco.
+pub fn espera_para_produzir(
+    anterior: &CabecalhoBloco,
+    id_autoridade: u32,
+    agora: DateTime<Utc>,
+    autoridades: &ConjuntoAutoridades,
+    config: &Config,
+) -> Option<Duration> {
+    let inicio = inicio_do_slot(anterior.indice + 1, id_autoridade, autoridades, config)?;
+    let decorrido = agora - anterior.timestamp;
+    Some((inicio - decorrido).max(Duration::zero()))
+}
+
+/// Rejeita blocos produzidos por uma autoridade fora da sua vez, de acordo
+/// com o timestamp do bloco e do anterior.
+pub fn verificar_turno(
+    anterior: &CabecalhoBloco,
+    cabecalho: &CabecalhoBloco,
+    autoridades: &ConjuntoAutoridades,
+    config: &Config,
+) -> Result<(), BlocoErro> {
+    let inicio = inicio_do_slot(cabecalho.indice, cabecalho.id_autoridade, autoridades, config)
+        .ok_or(BlocoErro::AutoridadeDesconhecida)?;
+    if cabecalho.timestamp - anterior.timestamp < inicio {
+        return Err(BlocoErro::ForaDoTurno {
+            id_autoridade: cabecalho.id_autoridade,
+            indice: cabecalho.indice,
+            esperada: autoridade_da_vez(cabecalho.indice, autoridades).unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::blockchain::testes;
+
+    fn config_com_slot() -> Config {
+        let mut config = testes::config();
+        config.genesis.consenso.tempo_limite_slot = Duration::seconds(30);
+        config
+    }
+
+    fn cabecalho(indice: u32, id_autoridade: u32, segundos: i64) -> CabecalhoBloco {
+        CabecalhoBloco {
+            versao: 0,
+            id_cadeia: String::new(),
+            indice,
+            hash_anterior: String::new(),
+            raiz_merkle: String::new(),
+            raiz_estado: String::new(),
+            timestamp: Utc.timestamp_opt(1_700_000_000 + segundos, 0).unwrap(),
+            id_autoridade,
+        }
+    }
+
+    #[test]
+    fn autoridades_se_revezam_pela_altura() {
+        let config = config_com_slot();
+        let autoridades = ConjuntoAutoridades::do_genesis(&config);
+        assert_eq!(autoridade_da_vez(1, &autoridades), Some(2));
+        assert_eq!(autoridade_da_vez(2, &autoridades), Some(1));
+        assert_eq!(autoridade_da_vez(3, &autoridades), Some(2));
+    }
+
+    #[test]
+    fn seguinte_no_rodizio_so_produz_depois_do_tempo_limite() {
+        let config = config_com_slot();
+        let autoridades = ConjuntoAutoridades::do_genesis(&config);
+        let anterior = cabecalho(1, 2, 0);
+
+        verificar_turno(&anterior, &cabecalho(2, 1, 1), &autoridades, &config).unwrap();
+        assert!(matches!(
+            verificar_turno(&anterior, &cabecalho(2, 2, 29), &autoridades, &config),
+            Err(BlocoErro::ForaDoTurno { id_autoridade: 2, indice: 2, esperada: 1 })
+        ));
+        verificar_turno(&anterior, &cabecalho(2, 2, 30), &autoridades, &config).unwrap();
+        assert!(matches!(
+            verificar_turno(&anterior, &cabecalho(2, 3, 60), &autoridades, &config),
+            Err(BlocoErro::AutoridadeDesconhecida)
+        ));
+    }
+
+    #[test]
+    fn espera_ate_o_inicio_do_slot() {
+        let config = config_com_slot();
+        let autoridades = ConjuntoAutoridades::do_genesis(&config);
+        let anterior = cabecalho(1, 2, 0);
+        let agora = anterior.timestamp + Duration::seconds(10);
+
+        assert_eq!(espera_para_produzir(&anterior, 1, agora, &autoridades, &config), Some(Duration::zero()));
+        assert_eq!(espera_para_produzir(&anterior, 2, agora, &autoridades, &config), Some(Duration::seconds(20)));
+        assert_eq!(espera_para_produzir(&anterior, 3, agora, &autoridades, &config), None);
+    }
+
+    #[test]
+    fn sem_tempo_limite_qualquer_autoridade_produz() {
+        let config = testes::config();
+        let autoridades = ConjuntoAutoridades::do_genesis(&config);
+        let anterior = cabecalho(1, 2, 0);
+
+        verificar_turno(&anterior, &cabecalho(2, 2, 0), &autoridades, &config).unwrap();
+        assert_eq!(espera_para_produzir(&anterior, 2, anterior.timestamp, &autoridades, &config), Some(Duration::zero()));
+    }
+}
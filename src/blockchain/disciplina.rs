@@ -1,15 +1,31 @@
 use serde::{Serialize, Deserialize};
+use chrono::NaiveDate;
 
+use crate::utils::erros::TransacaoErro;
+
+use super::codificacao::{CodificacaoCanonica, Codificador};
+use super::avaliacao::{valores_conferem, PoliticaAvaliacao, SituacaoDisciplina};
 use super::Nota;
 
+/// Presença do estudante em uma aula da disciplina.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RegistroAula {
+    pub data: NaiveDate,
+    pub presente: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Disciplina {
     pub id_disciplina: u32,
     pub nome: String,
     pub codigo: String,
     pub notas: Vec<Nota>,
+    #[serde(default)]
+    pub aulas: Vec<RegistroAula>,
     pub media: f32,
     pub frequencia: f32,
+    #[serde(default)]
+    pub situacao: SituacaoDisciplina,
 }
 
 impl Disciplina {
@@ -19,12 +35,157 @@ impl Disciplina {
             nome: nome.to_string(),
             codigo: codigo.to_string(),
             notas: Vec::new(),
+            aulas: Vec::new(),
             media: 0.0,
             frequencia: 0.0,
+            situacao: SituacaoDisciplina::Cursando,
         }
     }
 
     pub fn adicionar_nota(&mut self, nota: Nota) {
         self.notas.push(nota);
     }
+
+    pub fn registrar_aula(&mut self, data: NaiveDate, presente: bool) {
+        self.aulas.push(RegistroAula { data, presente });
+    }
+
+    /// Percentual (0 a 100) de aulas com presença; zero se nenhuma aula foi
+    /// registrada.
+    pub fn calcular_frequencia(&self) -> f32 {
+        if self.aulas.is_empty() {
+            return 0.0;
+        }
+        let presencas = self.aulas.iter().filter(|aula| aula.presente).count();
+        presencas as f32 * 100.0 / self.aulas.len() as f32
+    }
+
+    /// Atualiza `media` e `frequencia` a partir das notas e das aulas.
+    pub fn recalcular(&mut self, politica: &PoliticaAvaliacao) {
+        self.media = politica.calcular_media(&self.notas);
+        self.frequencia = self.calcular_frequencia();
+    }
+
+    /// Encerra a disciplina com o resultado que a política dá para a média e
+    /// a frequência atuais.
+    pub fn encerrar(&mut self, politica: &PoliticaAvaliacao) {
+        self.situacao = politica.resultado_final(self.media, self.frequencia, !self.aulas.is_empty());
+    }
+
+    /// Confere se `media`, `frequencia` e `situacao` gravados são os que a
+    /// política produz para as notas e aulas registradas. `Cursando`,
+    /// `Trancado` e `Dispensado` não dependem das notas e são sempre aceitas.
+    pub fn conferir_valores(&self, politica: &PoliticaAvaliacao) -> Result<(), TransacaoErro> {
+        let media = politica.calcular_media(&self.notas);
+        if !valores_conferem(self.media, media) {
+            return Err(TransacaoErro::MediaInconsistente {
+                codigo: self.codigo.clone(),
+                gravada: self.media,
+                calculada: media,
+            });
+        }
+        let frequencia = self.calcular_frequencia();
+        if !valores_conferem(self.frequencia, frequencia) {
+            return Err(TransacaoErro::FrequenciaInconsistente {
+                codigo: self.codigo.clone(),
+                gravada: self.frequencia,
+                calculada: frequencia,
+            });
+        }
+        if self.situacao.eh_resultado_final() {
+            let esperada = politica.resultado_final(media, frequencia, !self.aulas.is_empty());
+            if self.situacao != esperada {
+                return Err(TransacaoErro::SituacaoContraditoria {
+                    codigo: self.codigo.clone(),
+                    gravada: self.situacao,
+                    esperada,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CodificacaoCanonica for RegistroAula {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida.data(&self.data).u8(self.presente as u8);
+    }
+}
+
+impl CodificacaoCanonica for Disciplina {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .u32(self.id_disciplina)
+            .texto(&self.nome)
+            .texto(&self.codigo)
+            .lista(&self.notas)
+            .lista(&self.aulas)
+            .f32(self.media)
+            .f32(self.frequencia)
+            .u8(self.situacao.codigo());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disciplina_encerrada(politica: &PoliticaAvaliacao) -> Disciplina {
+        let mut disciplina = Disciplina::nova_disciplina(1, "Cálculo I", "MAT101");
+        let data = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        disciplina.adicionar_nota(Nota::nova_nota(1, 7.0, "Prova", data));
+        disciplina.adicionar_nota(Nota::nova_nota(2, 8.0, "Prova", data));
+        disciplina.registrar_aula(data, true);
+        disciplina.registrar_aula(data, true);
+        disciplina.registrar_aula(data, true);
+        disciplina.registrar_aula(data, false);
+        disciplina.recalcular(politica);
+        disciplina.encerrar(politica);
+        disciplina
+    }
+
+    #[test]
+    fn conferir_valores_aceita_o_que_a_politica_produz() {
+        let politica = PoliticaAvaliacao::default();
+        let disciplina = disciplina_encerrada(&politica);
+        assert_eq!(disciplina.media, 7.5);
+        assert_eq!(disciplina.frequencia, 75.0);
+        assert_eq!(disciplina.situacao, SituacaoDisciplina::Aprovado);
+        disciplina.conferir_valores(&politica).unwrap();
+
+        let mut ruido = disciplina.clone();
+        ruido.media += 1e-5;
+        ruido.conferir_valores(&politica).unwrap();
+    }
+
+    #[test]
+    fn conferir_valores_recusa_media_frequencia_ou_situacao_divergentes() {
+        let politica = PoliticaAvaliacao::default();
+        let disciplina = disciplina_encerrada(&politica);
+
+        let mut media = disciplina.clone();
+        media.media = 8.0;
+        assert!(matches!(media.conferir_valores(&politica), Err(TransacaoErro::MediaInconsistente { .. })));
+
+        let mut frequencia = disciplina.clone();
+        frequencia.frequencia = 100.0;
+        assert!(matches!(frequencia.conferir_valores(&politica), Err(TransacaoErro::FrequenciaInconsistente { .. })));
+
+        let mut situacao = disciplina.clone();
+        situacao.situacao = SituacaoDisciplina::ReprovadoPorNota;
+        assert!(matches!(
+            situacao.conferir_valores(&politica),
+            Err(TransacaoErro::SituacaoContraditoria { esperada: SituacaoDisciplina::Aprovado, .. })
+        ));
+    }
+
+    #[test]
+    fn conferir_valores_aceita_situacoes_que_nao_dependem_das_notas() {
+        let politica = PoliticaAvaliacao::default();
+        let mut disciplina = disciplina_encerrada(&politica);
+        for situacao in [SituacaoDisciplina::Cursando, SituacaoDisciplina::Trancado, SituacaoDisciplina::Dispensado] {
+            disciplina.situacao = situacao;
+            disciplina.conferir_valores(&politica).unwrap();
+        }
+    }
 }
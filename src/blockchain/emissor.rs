@@ -0,0 +1,97 @@
+use std::collections::{BTreeSet, HashSet};
+
+use rsa::pkcs8::EncodePublicKey;
+use rsa::RsaPublicKey;
+
+use crate::utils::erros::TransacaoErro;
+
+use super::codificacao::{CodificacaoCanonica, Codificador};
+use super::TipoTransacao;
+
+/// Papel de quem emite transações acadêmicas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Papel {
+    /// Secretaria acadêmica: matrículas, períodos, encerramentos e
+    /// alterações de autoridades.
+    Secretaria,
+    /// Professor: notas, correções, retratações, frequência e encerramento,
+    /// apenas nas disciplinas que leciona.
+    Professor,
+}
+
+/// Professor ou secretaria com chave registrada na especificação do gênesis.
+/// Toda transação carrega o id do emissor e é assinada com a chave dele.
+#[derive(Debug, Clone)]
+pub struct Emissor {
+    pub chave_publica: RsaPublicKey,
+    pub papel: Papel,
+    /// Códigos de disciplina (`Disciplina::codigo`) em que um professor pode
+    /// lançar notas e frequência.
+    pub disciplinas: HashSet<String>,
+}
+
+impl Emissor {
+    /// Confere se o papel do emissor permite emitir uma transação do tipo
+    /// informado.
+    pub fn autorizar(&self, id_emissor: u32, tipo: &TipoTransacao) -> Result<(), TransacaoErro> {
+        let nao_autorizado = || TransacaoErro::EmissorNaoAutorizado {
+            id_emissor,
+            operacao: tipo.nome(),
+        };
+
+        match tipo {
+            TipoTransacao::MatriculaEstudante { .. }
+            | TipoTransacao::RegistroPeriodo { .. }
+            | TipoTransacao::MatriculaDisciplina { .. }
+            | TipoTransacao::AlteracaoAutoridades { .. } => {
+                if self.papel != Papel::Secretaria {
+                    return Err(nao_autorizado());
+                }
+            }
+            TipoTransacao::LancamentoNota { codigo_disciplina, .. }
+            | TipoTransacao::CorrecaoNota { codigo_disciplina, .. }
+            | TipoTransacao::RetratacaoNota { codigo_disciplina, .. }
+            | TipoTransacao::LancamentoFrequencia { codigo_disciplina, .. } => {
+                if self.papel != Papel::Professor {
+                    return Err(nao_autorizado());
+                }
+                self.exigir_disciplina(id_emissor, codigo_disciplina)?;
+            }
+            TipoTransacao::EncerramentoDisciplina { codigo_disciplina, .. } => {
+                if self.papel == Papel::Professor {
+                    self.exigir_disciplina(id_emissor, codigo_disciplina)?;
+                }
+            }
+            // A prova de equivocação se sustenta sozinha; qualquer emissor
+            // pode submetê-la
+            TipoTransacao::SuspensaoAutoridade { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn exigir_disciplina(&self, id_emissor: u32, codigo: &str) -> Result<(), TransacaoErro> {
+        if !self.disciplinas.contains(codigo) {
+            return Err(TransacaoErro::DisciplinaNaoAtribuida {
+                id_emissor,
+                codigo: codigo.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Disciplinas em ordem alfabética, já que o conjunto não tem ordem própria.
+impl CodificacaoCanonica for Emissor {
+    fn codificar(&self, saida: &mut Codificador) {
+        let der = self.chave_publica.to_public_key_der().expect("chave pública sem codificação DER");
+        let papel = match self.papel {
+            Papel::Secretaria => 0,
+            Papel::Professor => 1,
+        };
+        let disciplinas: BTreeSet<&String> = self.disciplinas.iter().collect();
+        saida.bytes(der.as_bytes()).u8(papel).u32(disciplinas.len() as u32);
+        for codigo in disciplinas {
+            saida.texto(codigo);
+        }
+    }
+}
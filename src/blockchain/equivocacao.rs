@@ -0,0 +1,211 @@
+//! Detecção de equivocação: uma autoridade que assina dois blocos diferentes
+//! na mesma altura, ou dois votos diferentes na mesma etapa de uma rodada,
+//! deixa uma prova que qualquer nó verifica com as chaves das autoridades em
+//! vigor naquela altura. A prova é difundida pela rede e pode ser submetida em uma transação
+//! `SuspensaoAutoridade`, que tira a autoridade da produção de blocos e da
+//! votação de finalidade.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::config::Config;
+use crate::utils::erros::TransacaoErro;
+
+use super::codificacao::{CodificacaoCanonica, Codificador};
+use super::{Blockchain, Bloco, CabecalhoBloco, ConjuntoAutoridades, Voto};
+
+/// Cabeçalho com a assinatura da autoridade que o produziu; basta para
+/// provar que ela assinou o bloco, sem as transações.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CabecalhoAssinado {
+    pub cabecalho: CabecalhoBloco,
+    pub assinatura: String,
+}
+
+impl CabecalhoAssinado {
+    fn do_bloco(bloco: &Bloco) -> Self {
+        CabecalhoAssinado {
+            cabecalho: bloco.cabecalho.clone(),
+            assinatura: bloco.assinatura_autoridade.clone(),
+        }
+    }
+}
+
+/// Duas mensagens conflitantes assinadas pela mesma autoridade.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ProvaEquivocacao {
+    /// Dois blocos diferentes com o mesmo índice.
+    BlocosConflitantes(CabecalhoAssinado, CabecalhoAssinado),
+    /// Dois votos em blocos diferentes na mesma altura, rodada e etapa.
+    VotosConflitantes(Voto, Voto),
+}
+
+impl ProvaEquivocacao {
+    pub fn id_autoridade(&self) -> u32 {
+        match self {
+            ProvaEquivocacao::BlocosConflitantes(a, _) => a.cabecalho.id_autoridade,
+            ProvaEquivocacao::VotosConflitantes(a, _) => a.id_autoridade,
+        }
+    }
+
+    pub fn altura(&self) -> u32 {
+        match self {
+            ProvaEquivocacao::BlocosConflitantes(a, _) => a.cabecalho.indice,
+            ProvaEquivocacao::VotosConflitantes(a, _) => a.altura,
+        }
+    }
+
+    /// Confere que as duas mensagens são da mesma autoridade, estão
+    /// assinadas por ela com a chave em vigor na altura e de fato conflitam.
+    /// Devolve o id da autoridade.
+    pub fn verificar(&self, autoridades: &ConjuntoAutoridades, config: &Config) -> Result<u32, TransacaoErro> {
+        match self {
+            ProvaEquivocacao::BlocosConflitantes(a, b) => {
+                if a.cabecalho.id_autoridade != b.cabecalho.id_autoridade {
+                    return Err(TransacaoErro::ProvaInvalida("blocos de autoridades diferentes"));
+                }
+                if a.cabecalho.indice != b.cabecalho.indice {
+                    return Err(TransacaoErro::ProvaInvalida("blocos em alturas diferentes"));
+                }
+                if a.cabecalho.id_cadeia != config.genesis.id_cadeia || b.cabecalho.id_cadeia != config.genesis.id_cadeia {
+                    return Err(TransacaoErro::ProvaInvalida("bloco de outra cadeia"));
+                }
+                let (hash_a, hash_b) = (a.cabecalho.calcular_hash(), b.cabecalho.calcular_hash());
+                if hash_a == hash_b {
+                    return Err(TransacaoErro::ProvaInvalida("os dois blocos são o mesmo"));
+                }
+                for (assinado, hash) in [(a, hash_a), (b, hash_b)] {
+                    assinado
+                        .cabecalho
+                        .verificar_assinatura(&hash, &assinado.assinatura, autoridades)
+                        .map_err(|_| TransacaoErro::ProvaInvalida("assinatura de bloco inválida"))?;
+                }
+            }
+            ProvaEquivocacao::VotosConflitantes(a, b) => {
+                if a.id_autoridade != b.id_autoridade {
+                    return Err(TransacaoErro::ProvaInvalida("votos de autoridades diferentes"));
+                }
+                if (a.altura, a.rodada, a.etapa) != (b.altura, b.rodada, b.etapa) {
+                    return Err(TransacaoErro::ProvaInvalida("votos em etapas diferentes"));
+                }
+                if a.hash_bloco == b.hash_bloco {
+                    return Err(TransacaoErro::ProvaInvalida("os dois votos são no mesmo bloco"));
+                }
+                for voto in [a, b] {
+                    voto.verificar(autoridades, config)
+                        .map_err(|_| TransacaoErro::ProvaInvalida("assinatura de voto inválida"))?;
+                }
+            }
+        }
+        Ok(self.id_autoridade())
+    }
+}
+
+/// Guarda o primeiro cabeçalho assinado visto de cada autoridade em cada
+/// altura ainda não final e as provas de equivocação já conhecidas. Nas
+/// alturas finais, a comparação com o bloco local basta.
+#[derive(Debug, Default)]
+pub struct DetectorEquivocacao {
+    cabecalhos: HashMap<(u32, u32), CabecalhoAssinado>,
+    provas: Vec<ProvaEquivocacao>,
+}
+
+impl DetectorEquivocacao {
+    /// Compara um bloco recebido da rede com o bloco local da mesma altura e
+    /// com os já vistos. Devolve a prova se ele revelar uma equivocação
+    /// ainda não conhecida. Blocos com assinatura inválida são ignorados,
+    /// para que ninguém forje uma prova contra outra autoridade.
+    pub fn observar_bloco(&mut self, bloco: &Bloco, blockchain: &Blockchain, config: &Config) -> Option<ProvaEquivocacao> {
+        let finalizada = blockchain.altura_finalizada();
+        self.cabecalhos.retain(|(_, altura), _| *altura > finalizada);
+
+        let local = blockchain.bloco(bloco.cabecalho.indice);
+        if local.is_some_and(|local| local.hash_atual == bloco.hash_atual) {
+            return None;
+        }
+        let autoridades = blockchain.autoridades_em(bloco.cabecalho.indice, config);
+        if bloco.verificar_assinatura(&autoridades).is_err() {
+            return None;
+        }
+
+        let recebido = CabecalhoAssinado::do_bloco(bloco);
+        let chave = (bloco.cabecalho.id_autoridade, bloco.cabecalho.indice);
+        let anterior = match local {
+            Some(local) if local.cabecalho.id_autoridade == bloco.cabecalho.id_autoridade => {
+                CabecalhoAssinado::do_bloco(local)
+            }
+            _ => match self.cabecalhos.get(&chave) {
+                Some(visto) if *visto != recebido => visto.clone(),
+                Some(_) => return None,
+                None => {
+                    if bloco.cabecalho.indice > finalizada {
+                        self.cabecalhos.insert(chave, recebido);
+                    }
+                    return None;
+                }
+            },
+        };
+
+        let prova = ProvaEquivocacao::BlocosConflitantes(anterior, recebido);
+        self.registrar(prova.clone()).then_some(prova)
+    }
+
+    /// Guarda uma prova já verificada. Devolve `false` se a equivocação da
+    /// autoridade naquela altura já era conhecida.
+    pub fn registrar(&mut self, prova: ProvaEquivocacao) -> bool {
+        let conhecida = self
+            .provas
+            .iter()
+            .any(|p| p.id_autoridade() == prova.id_autoridade() && p.altura() == prova.altura());
+        if !conhecida {
+            self.provas.push(prova);
+        }
+        !conhecida
+    }
+
+    /// Provas conhecidas, na ordem em que foram obtidas.
+    pub fn provas(&self) -> &[ProvaEquivocacao] {
+        &self.provas
+    }
+}
+
+impl CodificacaoCanonica for CabecalhoAssinado {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida.valor(&self.cabecalho).texto(&self.assinatura);
+    }
+}
+
+impl CodificacaoCanonica for ProvaEquivocacao {
+    fn codificar(&self, saida: &mut Codificador) {
+        match self {
+            ProvaEquivocacao::BlocosConflitantes(a, b) => saida.u8(0).valor(a).valor(b),
+            ProvaEquivocacao::VotosConflitantes(a, b) => saida.u8(1).valor(a).valor(b),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes::{self, matricula};
+
+    #[test]
+    fn cabecalhos_de_alturas_finais_sao_descartados() {
+        let config = testes::config();
+        let mut local = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut local, vec![matricula(1, 1)], 1, &config);
+        let mut outra = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut outra, vec![matricula(2, 2)], 1, &config);
+        let bloco_2 = testes::produzir(&mut outra, vec![matricula(3, 3)], 2, &config);
+
+        let mut detector = DetectorEquivocacao::default();
+        assert!(detector.observar_bloco(&bloco_2, &local, &config).is_none());
+        assert_eq!(detector.cabecalhos.len(), 1);
+
+        testes::produzir(&mut local, vec![matricula(4, 4)], 2, &config);
+        testes::certificar(&mut local, 2, &config);
+        assert!(detector.observar_bloco(&outra.cadeia[1], &local, &config).is_some());
+        assert!(detector.cabecalhos.is_empty());
+    }
+}
@@ -0,0 +1,719 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::config::Config;
+use crate::utils::erros::{BlocoErro, CadeiaErro, TransacaoErro};
+
+use super::avaliacao::PoliticaAvaliacao;
+use super::codificacao::{self, CodificacaoCanonica, Codificador};
+use super::governanca;
+use super::transacao::TipoTransacao;
+use super::{Bloco, ConjuntoAutoridades, Disciplina, Estudante, Nota, PeriodoLetivo, SituacaoDisciplina, Transacao};
+
+/// Identifica uma nota: estudante, período, código da disciplina e `id_nota`.
+type ChaveNota = (u32, u32, String, u32);
+
+/// Uma versão de uma nota no seu histórico de alterações.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlteracaoNota {
+    pub id_transacao: u32,
+    pub timestamp: DateTime<Utc>,
+    /// Valor que passou a valer; `None` quando a nota foi anulada.
+    pub nota: Option<Nota>,
+    /// Motivo da alteração; ausente no lançamento original.
+    pub motivo: Option<String>,
+    /// Emissor que assinou a transação e responde pela alteração.
+    pub id_emissor: u32,
+}
+
+/// Estado acadêmico corrente: estudantes com seus períodos, disciplinas e
+/// notas, mais o histórico de alterações de cada nota. É obtido aplicando,
+/// em ordem, as transações de cada bloco a partir do gênesis, e por isso é o
+/// mesmo em todos os nós; o cabeçalho de cada bloco grava a raiz do estado
+/// resultante.
+///
+/// Também serve de contexto para validar uma transação nova: notas só podem
+/// ser lançadas em disciplinas matriculadas, encerramentos precisam conferir
+/// com as notas e aulas já lançadas e assim por diante.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EstadoAcademico {
+    estudantes: BTreeMap<u32, Estudante>,
+    /// Todas as versões de cada nota, da mais antiga para a vigente.
+    #[serde(with = "como_pares")]
+    alteracoes: BTreeMap<ChaveNota, Vec<AlteracaoNota>>,
+    /// Nota afetada por cada lançamento, correção ou retratação; refeito a
+    /// partir de `alteracoes` ao ler um estado serializado.
+    #[serde(skip)]
+    notas_por_transacao: BTreeMap<u32, ChaveNota>,
+    /// `id_transacao` de todas as transações aplicadas.
+    ids_transacao: BTreeSet<u32>,
+    /// Autoridades suspensas por equivocação, que não produzem mais blocos
+    /// nem votam.
+    autoridades_suspensas: BTreeSet<u32>,
+    /// Alterações do conjunto de autoridades aprovadas na cadeia, por altura
+    /// de vigência: a nova chave em PEM, ou `None` para remover.
+    alteracoes_autoridades: BTreeMap<u32, BTreeMap<u32, Option<String>>>,
+    /// Altura do último bloco aplicado; as transações aplicadas por cima
+    /// entram no bloco seguinte.
+    altura: u32,
+}
+
+impl EstadoAcademico {
+    pub fn estudante(&self, id_estudante: u32) -> Option<&Estudante> {
+        self.estudantes.get(&id_estudante)
+    }
+
+    pub fn disciplina(&self, id_estudante: u32, id_periodo: u32, codigo: &str) -> Option<&Disciplina> {
+        self.estudante(id_estudante)?
+            .periodos_letivos
+            .iter()
+            .find(|p| p.id_periodo == id_periodo)?
+            .disciplinas
+            .iter()
+            .find(|d| d.codigo == codigo)
+    }
+
+    pub fn autoridade_suspensa(&self, id_autoridade: u32) -> bool {
+        self.autoridades_suspensas.contains(&id_autoridade)
+    }
+
+    /// Autoridades em vigor na altura: as do gênesis com as alterações
+    /// aprovadas até então e vigentes até ela.
+    pub fn autoridades_em(&self, altura: u32, config: &Config) -> ConjuntoAutoridades {
+        let mut autoridades = ConjuntoAutoridades::do_genesis(config);
+        for alteracoes in self.alteracoes_autoridades.range(..=altura).map(|(_, a)| a) {
+            for (id_autoridade, chave_publica_pem) in alteracoes {
+                autoridades.alterar(*id_autoridade, chave_publica_pem.as_deref());
+            }
+        }
+        autoridades
+    }
+
+    /// Alterações aprovadas que só vigoram depois de `altura`, como
+    /// (altura de vigência, id da autoridade, se a chave é incluída ou trocada).
+    pub fn alteracoes_agendadas(&self, altura: u32) -> Vec<(u32, u32, bool)> {
+        self.alteracoes_autoridades
+            .range(altura + 1..)
+            .flat_map(|(vigencia, alteracoes)| {
+                alteracoes
+                    .iter()
+                    .map(move |(id_autoridade, pem)| (*vigencia, *id_autoridade, pem.is_some()))
+            })
+            .collect()
+    }
+
+    /// Versões da nota, da mais antiga para a vigente; vazio se a nota nunca
+    /// foi lançada.
+    pub fn alteracoes_nota(&self, id_estudante: u32, id_periodo: u32, codigo: &str, id_nota: u32) -> &[AlteracaoNota] {
+        self.alteracoes
+            .get(&(id_estudante, id_periodo, codigo.to_string(), id_nota))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Uma correção ou retratação precisa apontar para a versão vigente da
+    /// mesma nota, o que mantém o histórico de alterações linear.
+    fn verificar_referencia(&self, id_transacao_corrigida: u32, chave: &ChaveNota) -> Result<(), TransacaoErro> {
+        let referenciada = self
+            .notas_por_transacao
+            .get(&id_transacao_corrigida)
+            .ok_or(TransacaoErro::ReferenciaDesconhecida(id_transacao_corrigida))?;
+        if referenciada != chave {
+            return Err(TransacaoErro::ReferenciaIncompativel(id_transacao_corrigida));
+        }
+        let vigente = self.alteracoes[chave].last().map(|a| a.id_transacao).unwrap_or_default();
+        if vigente != id_transacao_corrigida {
+            return Err(TransacaoErro::CorrecaoDesatualizada {
+                referenciada: id_transacao_corrigida,
+                vigente,
+            });
+        }
+        Ok(())
+    }
+
+    fn registrar_alteracao(&mut self, chave: ChaveNota, alteracao: AlteracaoNota) {
+        self.notas_por_transacao.insert(alteracao.id_transacao, chave.clone());
+        self.alteracoes.entry(chave).or_default().push(alteracao);
+    }
+
+    fn periodo(&self, id_estudante: u32, id_periodo: u32) -> Result<&PeriodoLetivo, TransacaoErro> {
+        self.estudante(id_estudante)
+            .ok_or(TransacaoErro::EstudanteDesconhecido(id_estudante))?
+            .periodos_letivos
+            .iter()
+            .find(|p| p.id_periodo == id_periodo)
+            .ok_or(TransacaoErro::PeriodoDesconhecido { id_estudante, id_periodo })
+    }
+
+    /// Notas e aulas precisam estar datadas dentro do período a que
+    /// pertencem.
+    fn verificar_data(&self, config: &Config, id_estudante: u32, id_periodo: u32, data: NaiveDate) -> Result<(), TransacaoErro> {
+        let periodo = self.periodo(id_estudante, id_periodo)?;
+        config
+            .genesis
+            .regras_validacao
+            .verificar_data_no_periodo(data, periodo.ano, periodo.semestre)
+    }
+
+    fn estudante_mut(&mut self, id_estudante: u32) -> Result<&mut Estudante, TransacaoErro> {
+        self.estudantes
+            .get_mut(&id_estudante)
+            .ok_or(TransacaoErro::EstudanteDesconhecido(id_estudante))
+    }
+
+    fn periodo_mut(&mut self, id_estudante: u32, id_periodo: u32) -> Result<&mut PeriodoLetivo, TransacaoErro> {
+        self.estudante_mut(id_estudante)?
+            .periodos_letivos
+            .iter_mut()
+            .find(|p| p.id_periodo == id_periodo)
+            .ok_or(TransacaoErro::PeriodoDesconhecido { id_estudante, id_periodo })
+    }
+
+    fn disciplina_mut(
+        &mut self,
+        id_estudante: u32,
+        id_periodo: u32,
+        codigo: &str,
+    ) -> Result<&mut Disciplina, TransacaoErro> {
+        self.periodo_mut(id_estudante, id_periodo)?
+            .disciplinas
+            .iter_mut()
+            .find(|d| d.codigo == codigo)
+            .ok_or_else(|| TransacaoErro::DisciplinaDesconhecida { id_periodo, codigo: codigo.to_string() })
+    }
+
+    /// Valida a transação e, se for aceita, aplica o seu efeito. Nada é
+    /// alterado quando a transação é rejeitada. A validação segue sempre a
+    /// mesma ordem: estrutura do tipo, assinatura e papel do emissor,
+    /// limites de `RegrasValidacao`,
+    /// unicidade do `id_transacao` e, por fim, as regras que dependem do
+    /// estado (referências, datas dentro do período, política de avaliação).
+    pub fn aplicar(&mut self, transacao: &Transacao, config: &Config) -> Result<(), TransacaoErro> {
+        transacao.validar_estrutura()?;
+        transacao.verificar_assinatura(config)?;
+        config.genesis.regras_validacao.verificar(transacao)?;
+        if self.ids_transacao.contains(&transacao.id_transacao) {
+            return Err(TransacaoErro::TransacaoDuplicada(transacao.id_transacao));
+        }
+
+        self.aplicar_tipo(transacao, config)?;
+        self.ids_transacao.insert(transacao.id_transacao);
+        Ok(())
+    }
+
+    fn aplicar_tipo(&mut self, transacao: &Transacao, config: &Config) -> Result<(), TransacaoErro> {
+        let politica = &config.genesis.politica_avaliacao;
+
+        match &transacao.tipo {
+            TipoTransacao::MatriculaEstudante { id_estudante, nome, data_nascimento } => {
+                if self.estudantes.contains_key(id_estudante) {
+                    return Err(TransacaoErro::EstudanteJaMatriculado(*id_estudante));
+                }
+                self.estudantes
+                    .insert(*id_estudante, Estudante::novo_estudante(*id_estudante, nome, *data_nascimento));
+            }
+            TipoTransacao::RegistroPeriodo { id_estudante, id_periodo, ano, semestre } => {
+                let estudante = self.estudante_mut(*id_estudante)?;
+                if estudante.periodos_letivos.iter().any(|p| p.id_periodo == *id_periodo) {
+                    return Err(TransacaoErro::PeriodoJaRegistrado {
+                        id_estudante: *id_estudante,
+                        id_periodo: *id_periodo,
+                    });
+                }
+                estudante.adicionar_periodo_letivo(PeriodoLetivo::novo_periodo(*id_periodo, *ano, *semestre));
+            }
+            TipoTransacao::MatriculaDisciplina { id_estudante, id_periodo, id_disciplina, nome, codigo } => {
+                let periodo = self.periodo_mut(*id_estudante, *id_periodo)?;
+                if periodo.disciplinas.iter().any(|d| d.codigo == *codigo) {
+                    return Err(TransacaoErro::DisciplinaJaMatriculada {
+                        id_periodo: *id_periodo,
+                        codigo: codigo.clone(),
+                    });
+                }
+                periodo.adicionar_disciplina(Disciplina::nova_disciplina(*id_disciplina, nome, codigo));
+            }
+            TipoTransacao::LancamentoNota { id_estudante, id_periodo, codigo_disciplina, nota } => {
+                let chave = (*id_estudante, *id_periodo, codigo_disciplina.clone(), nota.id_nota);
+                // Uma nota anulada continua ocupando o seu id no histórico
+                if self.alteracoes.contains_key(&chave) {
+                    return Err(TransacaoErro::NotaDuplicada {
+                        codigo: codigo_disciplina.clone(),
+                        id_nota: nota.id_nota,
+                    });
+                }
+                self.verificar_data(config, *id_estudante, *id_periodo, nota.data)?;
+                let disciplina = self.disciplina_mut(*id_estudante, *id_periodo, codigo_disciplina)?;
+                exigir_em_curso(disciplina)?;
+                disciplina.adicionar_nota(nota.clone());
+                disciplina.recalcular(politica);
+                self.registrar_alteracao(
+                    chave,
+                    AlteracaoNota {
+                        id_transacao: transacao.id_transacao,
+                        timestamp: transacao.timestamp,
+                        nota: Some(nota.clone()),
+                        motivo: None,
+                        id_emissor: transacao.id_emissor,
+                    },
+                );
+            }
+            TipoTransacao::CorrecaoNota {
+                id_estudante,
+                id_periodo,
+                codigo_disciplina,
+                id_transacao_corrigida,
+                nota,
+                motivo,
+            } => {
+                let chave = (*id_estudante, *id_periodo, codigo_disciplina.clone(), nota.id_nota);
+                self.verificar_referencia(*id_transacao_corrigida, &chave)?;
+                self.verificar_data(config, *id_estudante, *id_periodo, nota.data)?;
+                let disciplina = self.disciplina_mut(*id_estudante, *id_periodo, codigo_disciplina)?;
+                // Corrigir uma retratação volta a nota para a disciplina
+                match disciplina.notas.iter_mut().find(|n| n.id_nota == nota.id_nota) {
+                    Some(vigente) => *vigente = nota.clone(),
+                    None => disciplina.adicionar_nota(nota.clone()),
+                }
+                refazer_resultado(disciplina, politica);
+                self.registrar_alteracao(
+                    chave,
+                    AlteracaoNota {
+                        id_transacao: transacao.id_transacao,
+                        timestamp: transacao.timestamp,
+                        nota: Some(nota.clone()),
+                        motivo: Some(motivo.clone()),
+                        id_emissor: transacao.id_emissor,
+                    },
+                );
+            }
+            TipoTransacao::RetratacaoNota {
+                id_estudante,
+                id_periodo,
+                codigo_disciplina,
+                id_transacao_corrigida,
+                id_nota,
+                motivo,
+            } => {
+                let chave = (*id_estudante, *id_periodo, codigo_disciplina.clone(), *id_nota);
+                self.verificar_referencia(*id_transacao_corrigida, &chave)?;
+                let disciplina = self.disciplina_mut(*id_estudante, *id_periodo, codigo_disciplina)?;
+                let posicao = disciplina
+                    .notas
+                    .iter()
+                    .position(|n| n.id_nota == *id_nota)
+                    .ok_or_else(|| TransacaoErro::NotaAnulada {
+                        codigo: codigo_disciplina.clone(),
+                        id_nota: *id_nota,
+                    })?;
+                disciplina.notas.remove(posicao);
+                refazer_resultado(disciplina, politica);
+                self.registrar_alteracao(
+                    chave,
+                    AlteracaoNota {
+                        id_transacao: transacao.id_transacao,
+                        timestamp: transacao.timestamp,
+                        nota: None,
+                        motivo: Some(motivo.clone()),
+                        id_emissor: transacao.id_emissor,
+                    },
+                );
+            }
+            TipoTransacao::LancamentoFrequencia { id_estudante, id_periodo, codigo_disciplina, aula } => {
+                self.verificar_data(config, *id_estudante, *id_periodo, aula.data)?;
+                let disciplina = self.disciplina_mut(*id_estudante, *id_periodo, codigo_disciplina)?;
+                exigir_em_curso(disciplina)?;
+                disciplina.registrar_aula(aula.data, aula.presente);
+                disciplina.recalcular(politica);
+            }
+            TipoTransacao::EncerramentoDisciplina {
+                id_estudante,
+                id_periodo,
+                codigo_disciplina,
+                media,
+                frequencia,
+                situacao,
+            } => {
+                let disciplina = self.disciplina_mut(*id_estudante, *id_periodo, codigo_disciplina)?;
+                exigir_em_curso(disciplina)?;
+                let mut encerrada = disciplina.clone();
+                encerrada.media = *media;
+                encerrada.frequencia = *frequencia;
+                encerrada.situacao = *situacao;
+                encerrada.conferir_valores(politica)?;
+                *disciplina = encerrada;
+            }
+            TipoTransacao::AlteracaoAutoridades { id_autoridade, chave_publica_pem, altura_vigencia, assinaturas } => {
+                // Aprovada pelas autoridades em vigor no bloco que a inclui,
+                // vale só a partir de um bloco posterior
+                let altura_inclusao = self.altura + 1;
+                if *altura_vigencia <= altura_inclusao {
+                    return Err(TransacaoErro::VigenciaInvalida {
+                        altura_vigencia: *altura_vigencia,
+                        minima: altura_inclusao + 1,
+                    });
+                }
+                let hash = governanca::hash_alteracao(
+                    &config.genesis.id_cadeia,
+                    *id_autoridade,
+                    chave_publica_pem.as_deref(),
+                    *altura_vigencia,
+                );
+                governanca::verificar_quorum(&hash, assinaturas, &self.autoridades_em(altura_inclusao, config), config)?;
+
+                let mut resultante = self.autoridades_em(*altura_vigencia, config);
+                if chave_publica_pem.is_none() && resultante.chave(*id_autoridade).is_none() {
+                    return Err(TransacaoErro::AutoridadeDesconhecida(*id_autoridade));
+                }
+                resultante.alterar(*id_autoridade, chave_publica_pem.as_deref());
+                if resultante.is_empty() {
+                    return Err(TransacaoErro::SemAutoridades);
+                }
+                self.alteracoes_autoridades
+                    .entry(*altura_vigencia)
+                    .or_default()
+                    .insert(*id_autoridade, chave_publica_pem.clone());
+            }
+            TipoTransacao::SuspensaoAutoridade { prova } => {
+                let id_autoridade = prova.verificar(&self.autoridades_em(prova.altura(), config), config)?;
+                if !self.autoridades_suspensas.insert(id_autoridade) {
+                    return Err(TransacaoErro::AutoridadeJaSuspensa(id_autoridade));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Aplica todas as transações de um bloco ou nenhuma. O bloco também é
+    /// rejeitado se a raiz de estado do seu cabeçalho não for a do estado
+    /// resultante ou se a autoridade que o produziu estiver suspensa.
+    pub fn aplicar_bloco(&mut self, bloco: &Bloco, config: &Config) -> Result<(), BlocoErro> {
+        if self.autoridade_suspensa(bloco.cabecalho.id_autoridade) {
+            return Err(BlocoErro::AutoridadeSuspensa(bloco.cabecalho.id_autoridade));
+        }
+        let mut estado = self.clone();
+        for transacao in &bloco.transacoes {
+            estado
+                .aplicar(transacao, config)
+                .map_err(|erro| BlocoErro::TransacaoInvalida {
+                    id_transacao: transacao.id_transacao,
+                    erro,
+                })?;
+        }
+        if estado.raiz() != bloco.cabecalho.raiz_estado {
+            return Err(BlocoErro::RaizEstadoInvalida);
+        }
+        estado.concluir_bloco(bloco.cabecalho.indice);
+        *self = estado;
+        Ok(())
+    }
+
+    /// Marca o estado como resultante do bloco `altura`; as transações
+    /// aplicadas a seguir entram no bloco seguinte.
+    pub fn concluir_bloco(&mut self, altura: u32) {
+        self.altura = altura;
+    }
+
+    /// Altura do último bloco aplicado.
+    pub fn altura(&self) -> u32 {
+        self.altura
+    }
+
+    /// SHA-256, em hexadecimal, da codificação canônica do estado.
+    pub fn raiz(&self) -> String {
+        codificacao::hash_hex(&codificacao::codificar(self))
+    }
+
+    /// Refaz `notas_por_transacao`, que não é serializado, a partir do
+    /// histórico de alterações.
+    pub fn reconstruir_derivados(&mut self) {
+        self.notas_por_transacao = self
+            .alteracoes
+            .iter()
+            .flat_map(|(chave, versoes)| versoes.iter().map(move |v| (v.id_transacao, chave.clone())))
+            .collect();
+    }
+}
+
+/// Serializa um mapa como lista de pares `[chave, valor]`, já que o JSON só
+/// aceita texto como chave de objeto e as chaves de nota são tuplas.
+mod como_pares {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S, K, V>(mapa: &BTreeMap<K, V>, serializador: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        K: Serialize,
+        V: Serialize,
+    {
+        serializador.collect_seq(mapa)
+    }
+
+    pub fn deserialize<'de, D, K, V>(desserializador: D) -> Result<BTreeMap<K, V>, D::Error>
+    where
+        D: Deserializer<'de>,
+        K: Deserialize<'de> + Ord,
+        V: Deserialize<'de>,
+    {
+        Vec::<(K, V)>::deserialize(desserializador).map(|pares| pares.into_iter().collect())
+    }
+}
+
+fn exigir_em_curso(disciplina: &Disciplina) -> Result<(), TransacaoErro> {
+    if disciplina.situacao != SituacaoDisciplina::Cursando {
+        return Err(TransacaoErro::DisciplinaEncerrada(disciplina.codigo.clone()));
+    }
+    Ok(())
+}
+
+/// Os mapas são percorridos em ordem de chave, de modo que a codificação (e
+/// a raiz) só depende do conteúdo. `notas_por_transacao` não entra por ser
+/// derivado de `alteracoes`, nem `altura`, que vem da própria cadeia.
+impl CodificacaoCanonica for EstadoAcademico {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida.u32(self.estudantes.len() as u32);
+        for estudante in self.estudantes.values() {
+            saida.valor(estudante);
+        }
+        saida.u32(self.alteracoes.len() as u32);
+        for ((id_estudante, id_periodo, codigo, id_nota), versoes) in &self.alteracoes {
+            saida
+                .u32(*id_estudante)
+                .u32(*id_periodo)
+                .texto(codigo)
+                .u32(*id_nota)
+                .lista(versoes);
+        }
+        saida.u32(self.ids_transacao.len() as u32);
+        for id_transacao in &self.ids_transacao {
+            saida.u32(*id_transacao);
+        }
+        saida.u32(self.autoridades_suspensas.len() as u32);
+        for id_autoridade in &self.autoridades_suspensas {
+            saida.u32(*id_autoridade);
+        }
+        saida.u32(self.alteracoes_autoridades.len() as u32);
+        for (altura_vigencia, alteracoes) in &self.alteracoes_autoridades {
+            saida.u32(*altura_vigencia).u32(alteracoes.len() as u32);
+            for (id_autoridade, chave_publica_pem) in alteracoes {
+                saida.u32(*id_autoridade);
+                match chave_publica_pem {
+                    Some(pem) => saida.u8(1).texto(pem),
+                    None => saida.u8(0),
+                };
+            }
+        }
+    }
+}
+
+impl CodificacaoCanonica for AlteracaoNota {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida.u32(self.id_transacao).data_hora(&self.timestamp);
+        match &self.nota {
+            Some(nota) => saida.u8(1).valor(nota),
+            None => saida.u8(0),
+        };
+        match &self.motivo {
+            Some(motivo) => saida.u8(1).texto(motivo),
+            None => saida.u8(0),
+        };
+        saida.u32(self.id_emissor);
+    }
+}
+
+/// De quantos em quantos blocos um instantâneo do estado é guardado.
+const INTERVALO_INSTANTANEOS: usize = 32;
+
+/// Instantâneos periódicos do estado, usados para voltar o estado a uma
+/// altura anterior quando um ramo é abandonado: parte-se do instantâneo mais
+/// próximo abaixo da altura e reaplicam-se os blocos seguintes.
+///
+/// O estado do primeiro bloco da cadeia local (o gênesis, ou o checkpoint
+/// de um nó iniciado por instantâneo) é sempre guardado: abaixo dele não há
+/// blocos para reaplicar.
+#[derive(Debug, Clone, Default)]
+pub struct InstantaneosEstado {
+    estados: BTreeMap<usize, EstadoAcademico>,
+}
+
+impl InstantaneosEstado {
+    /// Parte do estado resultante do bloco em `altura`, o primeiro da cadeia
+    /// local.
+    pub fn com_base(altura: usize, estado: EstadoAcademico) -> Self {
+        InstantaneosEstado { estados: BTreeMap::from([(altura, estado)]) }
+    }
+
+    /// Só o instantâneo do primeiro bloco, para reaplicar a cadeia local
+    /// inteira.
+    pub fn apenas_base(&self) -> Self {
+        InstantaneosEstado {
+            estados: self
+                .estados
+                .first_key_value()
+                .map(|(altura, estado)| (*altura, estado.clone()))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Guarda o estado resultante do bloco em `altura`, se ela cair no
+    /// intervalo de instantâneos.
+    pub fn registrar(&mut self, altura: usize, estado: &EstadoAcademico) {
+        if altura.is_multiple_of(INTERVALO_INSTANTANEOS) {
+            self.estados.insert(altura, estado.clone());
+        }
+    }
+
+    /// Esquece os instantâneos de `altura` em diante, menos o do primeiro
+    /// bloco.
+    pub fn descartar_a_partir(&mut self, altura: usize) {
+        let altura = self.estados.keys().next().map_or(altura, |base| altura.max(base + 1));
+        self.estados.split_off(&altura);
+    }
+
+    /// Estado resultante do bloco `altura` de `cadeia`, cujo primeiro bloco
+    /// pode não ser o gênesis. Sem instantâneo anterior, reaplica a cadeia
+    /// desde o gênesis.
+    pub fn restaurar(&self, cadeia: &[Bloco], altura: usize, config: &Config) -> Result<EstadoAcademico, CadeiaErro> {
+        let (base, mut estado) = self
+            .estados
+            .range((Bound::Unbounded, Bound::Included(altura)))
+            .next_back()
+            .map(|(base, estado)| (*base, estado.clone()))
+            .unwrap_or_default();
+        let inicio = cadeia.first().map_or(0, |bloco| bloco.cabecalho.indice as usize);
+        for bloco in cadeia.iter().take(altura + 1 - inicio).skip(base + 1 - inicio) {
+            estado
+                .aplicar_bloco(bloco, config)
+                .map_err(|erro| CadeiaErro::BlocoInvalido {
+                    indice: bloco.cabecalho.indice as usize,
+                    hash: bloco.hash_atual.clone(),
+                    erro,
+                })?;
+        }
+        Ok(estado)
+    }
+}
+
+/// Recalcula média e frequência depois de uma alteração de nota; se a
+/// disciplina já estava encerrada com um resultado, ele também é refeito.
+fn refazer_resultado(disciplina: &mut Disciplina, politica: &PoliticaAvaliacao) {
+    disciplina.recalcular(politica);
+    if disciplina.situacao.eh_resultado_final() {
+        disciplina.encerrar(politica);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use crate::blockchain::testes::{self, chaves, lancamento_nota, matricula_em_disciplina, DISCIPLINA, PROFESSOR, SECRETARIA};
+
+    fn correcao(id_transacao: u32, id_transacao_corrigida: u32, valor: f32) -> TipoTransacao {
+        TipoTransacao::CorrecaoNota {
+            id_estudante: 1,
+            id_periodo: 1,
+            codigo_disciplina: DISCIPLINA.into(),
+            id_transacao_corrigida,
+            nota: Nota::nova_nota(1, valor, "Prova", NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()),
+            motivo: format!("revisão {}", id_transacao),
+        }
+    }
+
+    fn estado_com_nota(config: &Config) -> EstadoAcademico {
+        let mut estado = EstadoAcademico::default();
+        for transacao in matricula_em_disciplina(1, 1) {
+            estado.aplicar(&transacao, config).unwrap();
+        }
+        estado.aplicar(&lancamento_nota(4, 1, 5.0), config).unwrap();
+        estado
+    }
+
+    #[test]
+    fn alteracoes_registram_o_emissor_que_assinou() {
+        let config = testes::config();
+        let mut estado = estado_com_nota(&config);
+        estado.aplicar(&testes::transacao(5, correcao(5, 4, 7.0)), &config).unwrap();
+
+        let alteracoes = estado.alteracoes_nota(1, 1, DISCIPLINA, 1);
+        assert_eq!(alteracoes.len(), 2);
+        assert_eq!(alteracoes[0].id_emissor, PROFESSOR);
+        assert!(alteracoes[0].motivo.is_none());
+        assert_eq!(alteracoes[1].id_emissor, PROFESSOR);
+        assert_eq!(alteracoes[1].nota.as_ref().unwrap().valor, 7.0);
+    }
+
+    #[test]
+    fn correcao_exige_professor_da_disciplina() {
+        let config = testes::config();
+        let mut estado = estado_com_nota(&config);
+        let mut transacao = Transacao::nova_transacao(5, correcao(5, 4, 7.0));
+        transacao.assinar(&chaves().secretaria, SECRETARIA);
+
+        assert!(matches!(
+            estado.aplicar(&transacao, &config),
+            Err(TransacaoErro::EmissorNaoAutorizado { id_emissor: SECRETARIA, .. })
+        ));
+        assert_eq!(estado.alteracoes_nota(1, 1, DISCIPLINA, 1).len(), 1);
+    }
+
+    #[test]
+    fn correcao_precisa_apontar_a_versao_vigente() {
+        let config = testes::config();
+        let mut estado = estado_com_nota(&config);
+        estado.aplicar(&testes::transacao(5, correcao(5, 4, 7.0)), &config).unwrap();
+
+        assert!(estado.aplicar(&testes::transacao(6, correcao(6, 4, 8.0)), &config).is_err());
+        estado.aplicar(&testes::transacao(6, correcao(6, 5, 8.0)), &config).unwrap();
+    }
+
+    /// Cadeia que passa do primeiro intervalo de instantâneos, com uma
+    /// matrícula por bloco alternando as autoridades.
+    fn cadeia_longa(config: &Config) -> Blockchain {
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        for altura in 1..=INTERVALO_INSTANTANEOS as u32 + 2 {
+            testes::produzir(&mut blockchain, vec![testes::matricula(altura, altura)], altura % 2 + 1, config);
+        }
+        blockchain
+    }
+
+    #[test]
+    fn reproduzir_do_genesis_coincide_com_o_estado() {
+        let config = testes::config();
+        let blockchain = cadeia_longa(&config);
+
+        let reproduzido = blockchain.reproduzir_do_genesis(&config).unwrap();
+        assert_eq!(reproduzido.raiz(), blockchain.estado().raiz());
+        assert_eq!(reproduzido.raiz(), blockchain.cadeia.last().unwrap().cabecalho.raiz_estado);
+    }
+
+    #[test]
+    fn restaurar_reproduz_a_raiz_de_cada_altura_anterior() {
+        let config = testes::config();
+        let blockchain = cadeia_longa(&config);
+        let mut instantaneos = InstantaneosEstado::com_base(0, EstadoAcademico::default());
+        let mut estado = EstadoAcademico::default();
+        for bloco in &blockchain.cadeia[1..] {
+            estado.aplicar_bloco(bloco, &config).unwrap();
+            instantaneos.registrar(bloco.cabecalho.indice as usize, &estado);
+        }
+        assert_eq!(instantaneos.estados.len(), 2);
+
+        let ponta = blockchain.cadeia.len() - 1;
+        for altura in [0, 1, INTERVALO_INSTANTANEOS - 1, INTERVALO_INSTANTANEOS, ponta] {
+            let restaurado = instantaneos.restaurar(&blockchain.cadeia, altura, &config).unwrap();
+            assert_eq!(restaurado.raiz(), blockchain.cadeia[altura].cabecalho.raiz_estado, "altura {}", altura);
+        }
+
+        // Sem o instantâneo descartado, a altura dele é refeita desde o gênesis
+        instantaneos.descartar_a_partir(INTERVALO_INSTANTANEOS);
+        assert_eq!(instantaneos.estados.len(), 1);
+        let restaurado = instantaneos.restaurar(&blockchain.cadeia, INTERVALO_INSTANTANEOS + 1, &config).unwrap();
+        assert_eq!(restaurado.raiz(), blockchain.cadeia[INTERVALO_INSTANTANEOS + 1].cabecalho.raiz_estado);
+    }
+}
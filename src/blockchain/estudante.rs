@@ -2,6 +2,7 @@
 use serde::{Serialize, Deserialize};
 use chrono::NaiveDate;
 
+use super::codificacao::{CodificacaoCanonica, Codificador};
 use super::PeriodoLetivo;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -13,11 +14,11 @@ pub struct Estudante {
 }
 
 impl Estudante {
-    pub fn novo_estudante(id: u32, nome: &str, ano: i32, mes: u32, dia: u32) -> Self {
+    pub fn novo_estudante(id: u32, nome: &str, data_nascimento: NaiveDate) -> Self {
         Estudante {
             id_estudante: id,
             nome: nome.to_string(),
-            data_nascimento: NaiveDate::from_ymd_opt(ano, mes, dia).expect("Data inválida"),
+            data_nascimento,
             periodos_letivos: Vec::new(),
         }
     }
@@ -26,3 +27,13 @@ impl Estudante {
         self.periodos_letivos.push(periodo);
     }
 }
+
+impl CodificacaoCanonica for Estudante {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .u32(self.id_estudante)
+            .texto(&self.nome)
+            .data(&self.data_nascimento)
+            .lista(&self.periodos_letivos);
+    }
+}
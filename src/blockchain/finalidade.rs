@@ -0,0 +1,561 @@
+//! Finalidade tolerante a falhas bizantinas entre as autoridades. Para cada
+//! altura, as autoridades trocam votos em duas etapas (prevoto e precommit)
+//! sobre o hash do bloco, em rodadas numeradas. Um bloco é final quando
+//! reúne precommits de um quórum de `2f + 1` autoridades em uma mesma
+//! rodada; o conjunto desses votos forma um `CertificadoQuorum`, que qualquer
+//! nó verifica apenas com o `Config`.
+//!
+//! Uma autoridade que dá precommit em um hash fica travada nele para aquela
+//! altura e, nas rodadas seguintes, só volta a prevotar nesse hash. Isso
+//! impede dois certificados para blocos diferentes na mesma altura enquanto
+//! no máximo `f` autoridades forem desonestas. A trava só é solta quando um
+//! quórum de prevotos para outro hash aparece em uma rodada posterior à dela:
+//! se a rodada da trava tivesse produzido certificado, autoridades honestas
+//! suficientes estariam travadas nele para impedir esse quórum, e sem a
+//! liberação autoridades travadas em hashes diferentes nunca mais formariam
+//! quórum. Os votos da autoridade são
+//! gravados antes de difundidos e recarregados na partida, para que votos e
+//! travas sobrevivam a um reinício.
+
+use std::collections::BTreeMap;
+
+use base64::{decode, encode};
+use chrono::{DateTime, Duration, Utc};
+use rsa::pkcs1v15::{Signature as RsaSignature, SigningKey, VerifyingKey};
+use rsa::RsaPrivateKey;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use signature::{Signer, Verifier};
+
+use crate::utils::config::Config;
+use crate::utils::erros::FinalidadeErro;
+use crate::utils::helpers::de_hex;
+
+use super::codificacao::{self, CodificacaoCanonica, Codificador};
+use super::{Blockchain, ConjuntoAutoridades, ProvaEquivocacao};
+
+/// Quantas alturas à frente da ponta local um voto recebido ainda é
+/// guardado. Votos para o próximo bloco podem chegar antes dele; os de
+/// alturas mais distantes só ocupariam memória.
+const ALTURAS_A_FRENTE: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct ConfigFinalidade {
+    /// Tempo sem certificado após o qual a rodada da altura avança.
+    pub tempo_limite_rodada: Duration,
+}
+
+impl Default for ConfigFinalidade {
+    fn default() -> Self {
+        ConfigFinalidade {
+            tempo_limite_rodada: Duration::seconds(10),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Etapa {
+    Prevoto,
+    Precommit,
+}
+
+/// Voto assinado de uma autoridade sobre o bloco de uma altura.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Voto {
+    pub etapa: Etapa,
+    pub id_cadeia: String,
+    pub altura: u32,
+    pub rodada: u32,
+    pub hash_bloco: String,
+    pub id_autoridade: u32,
+    pub assinatura: String,
+}
+
+impl Voto {
+    pub fn novo(
+        etapa: Etapa,
+        id_cadeia: &str,
+        altura: u32,
+        rodada: u32,
+        hash_bloco: &str,
+        chave_privada: &RsaPrivateKey,
+        id_autoridade: u32,
+    ) -> Self {
+        let mut voto = Voto {
+            etapa,
+            id_cadeia: id_cadeia.to_string(),
+            altura,
+            rodada,
+            hash_bloco: hash_bloco.to_string(),
+            id_autoridade,
+            assinatura: String::new(),
+        };
+        let hash_bytes = de_hex(&voto.hash_conteudo()).unwrap();
+        let signing_key = SigningKey::<Sha256>::new(chave_privada.clone());
+        voto.assinatura = encode(signing_key.sign(&hash_bytes).as_ref());
+        voto
+    }
+
+    /// Hash da codificação canônica do voto, sem a assinatura.
+    pub fn hash_conteudo(&self) -> String {
+        codificacao::hash_hex(&conteudo_voto(self))
+    }
+
+    /// Verifica a cadeia do voto e a assinatura com a chave que a autoridade
+    /// tem no conjunto em vigor na altura votada.
+    pub fn verificar(&self, autoridades: &ConjuntoAutoridades, config: &Config) -> Result<(), FinalidadeErro> {
+        if self.id_cadeia != config.genesis.id_cadeia {
+            return Err(FinalidadeErro::CadeiaIncorreta);
+        }
+        let chave_publica = autoridades
+            .chave(self.id_autoridade)
+            .ok_or(FinalidadeErro::AutoridadeDesconhecida(self.id_autoridade))?;
+
+        let invalida = || FinalidadeErro::AssinaturaInvalida(self.id_autoridade);
+        let hash_bytes = de_hex(&self.hash_conteudo()).ok_or_else(invalida)?;
+        let assinatura_bytes = decode(&self.assinatura).map_err(|_| invalida())?;
+        let assinatura = RsaSignature::from(assinatura_bytes.into_boxed_slice());
+
+        VerifyingKey::<Sha256>::new(chave_publica.clone())
+            .verify(&hash_bytes, &assinatura)
+            .map_err(|_| invalida())
+    }
+}
+
+/// Quantos votos formam um quórum entre `n` autoridades: `2f + 1`, com
+/// `f = (n - 1) / 3` falhas toleradas.
+pub fn quorum(n: usize) -> usize {
+    n * 2 / 3 + 1
+}
+
+/// Precommits de um quórum de autoridades para o mesmo bloco, na mesma
+/// rodada.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CertificadoQuorum {
+    pub altura: u32,
+    pub rodada: u32,
+    pub hash_bloco: String,
+    pub precommits: Vec<Voto>,
+}
+
+impl CertificadoQuorum {
+    /// Verifica os precommits com as autoridades em vigor na altura
+    /// certificada.
+    pub fn verificar(&self, autoridades: &ConjuntoAutoridades, config: &Config) -> Result<(), FinalidadeErro> {
+        let mut signatarias = Vec::new();
+        for voto in &self.precommits {
+            if voto.etapa != Etapa::Precommit
+                || voto.altura != self.altura
+                || voto.rodada != self.rodada
+                || voto.hash_bloco != self.hash_bloco
+            {
+                return Err(FinalidadeErro::VotoIncompativel(voto.id_autoridade));
+            }
+            if signatarias.contains(&voto.id_autoridade) {
+                return Err(FinalidadeErro::VotoRepetido(voto.id_autoridade));
+            }
+            voto.verificar(autoridades, config)?;
+            signatarias.push(voto.id_autoridade);
+        }
+
+        let necessario = quorum(autoridades.len());
+        if signatarias.len() < necessario {
+            return Err(FinalidadeErro::QuorumInsuficiente {
+                votos: signatarias.len(),
+                quorum: necessario,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// O que a rodada de votação produziu: votos deste nó a difundir, o
+/// certificado resultante se uma altura reuniu quórum de precommits e a
+/// prova de equivocação se um voto recebido conflitou com outro da mesma
+/// autoridade.
+#[derive(Debug, Default)]
+pub struct Progresso {
+    pub votos: Vec<Voto>,
+    pub certificado: Option<CertificadoQuorum>,
+    pub evidencia: Option<ProvaEquivocacao>,
+}
+
+#[derive(Debug, Clone)]
+struct Rodada {
+    numero: u32,
+    inicio: DateTime<Utc>,
+}
+
+/// Hash em que a autoridade deu precommit e a rodada em que o fez.
+#[derive(Debug, Clone)]
+struct Trava {
+    hash: String,
+    rodada: u32,
+}
+
+/// Estado local da votação. Nós que não são autoridade apenas acompanham os
+/// votos e montam certificados; autoridades também votam.
+#[derive(Debug)]
+pub struct MotorFinalidade {
+    autoridade: Option<(u32, RsaPrivateKey)>,
+    votos: BTreeMap<(u32, u32, Etapa), BTreeMap<u32, Voto>>,
+    rodadas: BTreeMap<u32, Rodada>,
+    travas: BTreeMap<u32, Trava>,
+}
+
+impl MotorFinalidade {
+    pub fn novo(autoridade: Option<(u32, RsaPrivateKey)>) -> Self {
+        MotorFinalidade {
+            autoridade,
+            votos: BTreeMap::new(),
+            rodadas: BTreeMap::new(),
+            travas: BTreeMap::new(),
+        }
+    }
+
+    /// Recoloca os votos que esta autoridade gravou em uma execução anterior,
+    /// antes de ela votar de novo: a votação de cada altura ainda não final
+    /// retoma da última rodada em que votou, e cada precommit volta a travar
+    /// a altura no seu hash.
+    pub fn restaurar_votos(&mut self, votos: Vec<Voto>, blockchain: &Blockchain, agora: DateTime<Utc>) {
+        let Some(id_autoridade) = self.autoridade.as_ref().map(|(id, _)| *id) else {
+            return;
+        };
+        for voto in votos {
+            if voto.id_autoridade != id_autoridade || voto.altura <= blockchain.altura_finalizada() {
+                continue;
+            }
+            let rodada = self.rodadas.entry(voto.altura).or_insert(Rodada { numero: 0, inicio: agora });
+            rodada.numero = rodada.numero.max(voto.rodada);
+            if voto.etapa == Etapa::Precommit && self.travas.get(&voto.altura).is_none_or(|t| t.rodada <= voto.rodada) {
+                self.travas.insert(voto.altura, Trava { hash: voto.hash_bloco.clone(), rodada: voto.rodada });
+            }
+            self.registrar(voto);
+        }
+    }
+
+    /// Chamado quando a ponta da cadeia muda: prevota no bloco da ponta, se
+    /// ele ainda não for final.
+    pub fn ao_mudar_ponta(&mut self, blockchain: &Blockchain, config: &Config, agora: DateTime<Utc>) -> Progresso {
+        let altura = blockchain.altura();
+        if altura <= blockchain.altura_finalizada() {
+            return Progresso::default();
+        }
+        self.rodadas.entry(altura).or_insert(Rodada { numero: 0, inicio: agora });
+        let mut progresso = Progresso::default();
+        progresso.votos.extend(self.prevotar(altura, blockchain, config));
+        self.avaliar(altura, blockchain, config, &mut progresso);
+        progresso
+    }
+
+    /// Registra um voto recebido da rede e avança a votação da altura. Votos
+    /// de autoridades suspensas ou para alturas muito à frente da ponta são
+    /// rejeitados; um voto que conflita com outro da mesma autoridade não é
+    /// contado e vira prova de equivocação.
+    pub fn ao_receber_voto(&mut self, voto: Voto, blockchain: &Blockchain, config: &Config) -> Result<Progresso, FinalidadeErro> {
+        let ponta = blockchain.altura();
+        if voto.altura > ponta.saturating_add(ALTURAS_A_FRENTE) {
+            return Err(FinalidadeErro::VotoMuitoAFrente { altura: voto.altura, ponta });
+        }
+        voto.verificar(&blockchain.autoridades_em(voto.altura, config), config)?;
+        if blockchain.estado().autoridade_suspensa(voto.id_autoridade) {
+            return Err(FinalidadeErro::AutoridadeSuspensa(voto.id_autoridade));
+        }
+        let mut progresso = Progresso::default();
+        if voto.altura <= blockchain.altura_finalizada() {
+            return Ok(progresso);
+        }
+        let altura = voto.altura;
+        if let Some(prova) = self.registrar(voto) {
+            progresso.evidencia = Some(prova);
+            return Ok(progresso);
+        }
+        self.avaliar(altura, blockchain, config, &mut progresso);
+        Ok(progresso)
+    }
+
+    /// Avança a rodada da ponta se ela passou do tempo limite sem certificado.
+    pub fn ao_expirar(&mut self, blockchain: &Blockchain, config: &Config, agora: DateTime<Utc>) -> Progresso {
+        let altura = blockchain.altura();
+        let mut progresso = Progresso::default();
+        let Some(rodada) = self.rodadas.get_mut(&altura) else {
+            return progresso;
+        };
+        if altura <= blockchain.altura_finalizada() || agora - rodada.inicio < config.finalidade.tempo_limite_rodada {
+            return progresso;
+        }
+        rodada.numero += 1;
+        rodada.inicio = agora;
+        progresso.votos.extend(self.prevotar(altura, blockchain, config));
+        self.avaliar(altura, blockchain, config, &mut progresso);
+        progresso
+    }
+
+    /// Descarta votos, rodadas e travas até `altura`, já finalizada.
+    pub fn descartar_ate(&mut self, altura: u32) {
+        self.votos.retain(|(a, _, _), _| *a > altura);
+        self.rodadas.retain(|a, _| *a > altura);
+        self.travas.retain(|a, _| *a > altura);
+    }
+
+    /// Rodada em andamento da altura.
+    pub fn rodada(&self, altura: u32) -> Option<u32> {
+        self.rodadas.get(&altura).map(|r| r.numero)
+    }
+
+    /// Guarda o voto, a menos que a autoridade já tenha votado em outro
+    /// bloco na mesma etapa da rodada; nesse caso devolve a prova.
+    fn registrar(&mut self, voto: Voto) -> Option<ProvaEquivocacao> {
+        let votos = self.votos.entry((voto.altura, voto.rodada, voto.etapa)).or_default();
+        match votos.get(&voto.id_autoridade) {
+            Some(anterior) if anterior.hash_bloco != voto.hash_bloco => {
+                Some(ProvaEquivocacao::VotosConflitantes(anterior.clone(), voto))
+            }
+            Some(_) => None,
+            None => {
+                votos.insert(voto.id_autoridade, voto);
+                None
+            }
+        }
+    }
+
+    /// Cria e registra o voto desta autoridade, se ela não estiver suspensa
+    /// e ainda não tiver votado nesta etapa da rodada.
+    fn votar(
+        &mut self,
+        etapa: Etapa,
+        altura: u32,
+        rodada: u32,
+        hash_bloco: &str,
+        blockchain: &Blockchain,
+        config: &Config,
+    ) -> Option<Voto> {
+        let (id_autoridade, chave_privada) = self.autoridade.as_ref()?;
+        if blockchain.estado().autoridade_suspensa(*id_autoridade) {
+            return None;
+        }
+        let ja_votou = self
+            .votos
+            .get(&(altura, rodada, etapa))
+            .is_some_and(|votos| votos.contains_key(id_autoridade));
+        if ja_votou {
+            return None;
+        }
+        let voto = Voto::novo(etapa, &config.genesis.id_cadeia, altura, rodada, hash_bloco, chave_privada, *id_autoridade);
+        self.registrar(voto.clone());
+        Some(voto)
+    }
+
+    /// Prevoto da rodada atual: no hash travado, se houver, ou no bloco
+    /// local daquela altura.
+    fn prevotar(&mut self, altura: u32, blockchain: &Blockchain, config: &Config) -> Option<Voto> {
+        let rodada = self.rodadas.get(&altura)?.numero;
+        let hash = match self.travas.get(&altura) {
+            Some(trava) => trava.hash.clone(),
+            None => blockchain.bloco(altura)?.hash_atual.clone(),
+        };
+        self.votar(Etapa::Prevoto, altura, rodada, &hash, blockchain, config)
+    }
+
+    /// Hash com quórum de votos na etapa da rodada, se houver.
+    fn hash_com_quorum(
+        &self,
+        altura: u32,
+        rodada: u32,
+        etapa: Etapa,
+        blockchain: &Blockchain,
+        config: &Config,
+    ) -> Option<String> {
+        let votos = self.votos.get(&(altura, rodada, etapa))?;
+        let necessario = quorum(blockchain.autoridades_em(altura, config).len());
+        let mut contagem: BTreeMap<&str, usize> = BTreeMap::new();
+        for voto in votos.values() {
+            *contagem.entry(&voto.hash_bloco).or_default() += 1;
+        }
+        contagem
+            .into_iter()
+            .find(|(_, votos)| *votos >= necessario)
+            .map(|(hash, _)| hash.to_string())
+    }
+
+    fn avaliar(&mut self, altura: u32, blockchain: &Blockchain, config: &Config, progresso: &mut Progresso) {
+        let rodadas: Vec<u32> = self
+            .votos
+            .keys()
+            .filter(|(a, _, _)| *a == altura)
+            .map(|(_, r, _)| *r)
+            .collect();
+
+        for rodada in rodadas {
+            // Um quórum de prevotos para outro hash, em rodada posterior à da
+            // trava, solta a trava. Precommit só no bloco que está na cadeia
+            // local e é compatível com a trava desta autoridade.
+            if let Some(hash) = self.hash_com_quorum(altura, rodada, Etapa::Prevoto, blockchain, config) {
+                if self.travas.get(&altura).is_some_and(|t| t.hash != hash && t.rodada < rodada) {
+                    self.travas.remove(&altura);
+                }
+                let local = blockchain.bloco(altura).map(|b| &b.hash_atual);
+                let travado = self.travas.get(&altura);
+                if local == Some(&hash) && travado.is_none_or(|t| t.hash == hash) {
+                    if let Some(voto) = self.votar(Etapa::Precommit, altura, rodada, &hash, blockchain, config) {
+                        self.travas.insert(altura, Trava { hash: hash.clone(), rodada });
+                        progresso.votos.push(voto);
+                    }
+                }
+            }
+
+            if let Some(hash) = self.hash_com_quorum(altura, rodada, Etapa::Precommit, blockchain, config) {
+                let precommits = self.votos[&(altura, rodada, Etapa::Precommit)]
+                    .values()
+                    .filter(|voto| voto.hash_bloco == hash)
+                    .cloned()
+                    .collect();
+                progresso.certificado = Some(CertificadoQuorum {
+                    altura,
+                    rodada,
+                    hash_bloco: hash,
+                    precommits,
+                });
+                return;
+            }
+        }
+    }
+}
+
+impl CodificacaoCanonica for Voto {
+    fn codificar(&self, saida: &mut Codificador) {
+        codificar_conteudo_voto(self, saida);
+        saida.texto(&self.assinatura);
+    }
+}
+
+fn codificar_conteudo_voto(voto: &Voto, saida: &mut Codificador) {
+    saida
+        .u8(match voto.etapa {
+            Etapa::Prevoto => 0,
+            Etapa::Precommit => 1,
+        })
+        .texto(&voto.id_cadeia)
+        .u32(voto.altura)
+        .u32(voto.rodada)
+        .texto(&voto.hash_bloco)
+        .u32(voto.id_autoridade);
+}
+
+/// Bytes canônicos assinados pela autoridade que vota.
+fn conteudo_voto(voto: &Voto) -> Vec<u8> {
+    let mut codificador = Codificador::novo();
+    codificar_conteudo_voto(voto, &mut codificador);
+    codificador.finalizar()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::RsaPublicKey;
+
+    use crate::blockchain::testes::{self, matricula};
+
+    /// Duas cadeias com blocos diferentes na altura 1.
+    fn cadeias_divergentes(config: &Config) -> (Blockchain, Blockchain) {
+        let mut a = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut a, vec![matricula(1, 1)], 1, config);
+        let mut b = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut b, vec![matricula(2, 2)], 1, config);
+        (a, b)
+    }
+
+    fn motor() -> MotorFinalidade {
+        MotorFinalidade::novo(Some((1, testes::chave_autoridade(1).clone())))
+    }
+
+    #[test]
+    fn votos_restaurados_impedem_votar_em_outro_bloco_na_mesma_rodada() {
+        let config = testes::config();
+        let (a, b) = cadeias_divergentes(&config);
+        let agora = Utc::now();
+        let votos = motor().ao_mudar_ponta(&a, &config, agora).votos;
+        assert_eq!(votos.len(), 1);
+        assert_eq!(motor().ao_mudar_ponta(&b, &config, agora).votos.len(), 1);
+
+        let mut reiniciado = motor();
+        reiniciado.restaurar_votos(votos, &b, agora);
+        assert!(reiniciado.ao_mudar_ponta(&b, &config, agora).votos.is_empty());
+    }
+
+    #[test]
+    fn precommit_restaurado_trava_a_altura() {
+        let config = testes::config();
+        let (a, b) = cadeias_divergentes(&config);
+        let hash_travado = a.cadeia[1].hash_atual.clone();
+        let precommit = Voto::novo(Etapa::Precommit, &config.genesis.id_cadeia, 1, 0, &hash_travado, testes::chave_autoridade(1), 1);
+        let agora = Utc::now();
+
+        let mut reiniciado = motor();
+        reiniciado.restaurar_votos(vec![precommit], &b, agora);
+        let depois_do_prazo = agora + config.finalidade.tempo_limite_rodada + Duration::seconds(1);
+        let votos = reiniciado.ao_expirar(&b, &config, depois_do_prazo).votos;
+
+        assert_eq!(votos.len(), 1);
+        assert_eq!((votos[0].etapa, votos[0].rodada), (Etapa::Prevoto, 1));
+        assert_eq!(votos[0].hash_bloco, hash_travado);
+    }
+
+    #[test]
+    fn quorum_de_prevotos_em_rodada_posterior_solta_a_trava() {
+        // Quatro autoridades (quórum 3), para que o quórum se forme sem o
+        // voto da autoridade travada
+        let mut config = testes::config();
+        let chaves = testes::chaves();
+        config.genesis.autoridades.insert(3, RsaPublicKey::from(&chaves.secretaria));
+        config.genesis.autoridades.insert(4, RsaPublicKey::from(&chaves.professor));
+        let chave = |id| match id {
+            3 => &chaves.secretaria,
+            4 => &chaves.professor,
+            _ => testes::chave_autoridade(id),
+        };
+        let (a, b) = cadeias_divergentes(&config);
+        let hash_travado = a.cadeia[1].hash_atual.clone();
+        let hash_local = b.cadeia[1].hash_atual.clone();
+        let voto = |etapa, rodada, hash: &str, id| Voto::novo(etapa, &config.genesis.id_cadeia, 1, rodada, hash, chave(id), id);
+        let agora = Utc::now();
+
+        let mut motor = motor();
+        motor.restaurar_votos(vec![voto(Etapa::Precommit, 0, &hash_travado, 1)], &b, agora);
+
+        // Um quórum na própria rodada da trava não a solta
+        for id in 2..=4 {
+            assert!(motor.ao_receber_voto(voto(Etapa::Prevoto, 0, &hash_local, id), &b, &config).unwrap().votos.is_empty());
+        }
+        let depois_do_prazo = agora + config.finalidade.tempo_limite_rodada + Duration::seconds(1);
+        let votos = motor.ao_expirar(&b, &config, depois_do_prazo).votos;
+        assert_eq!(votos.len(), 1);
+        assert_eq!((votos[0].etapa, votos[0].rodada), (Etapa::Prevoto, 1));
+        assert_eq!(votos[0].hash_bloco, hash_travado);
+
+        // Na rodada 1, o quórum para o bloco local solta a trava e leva ao precommit
+        let mut precommits = Vec::new();
+        for id in 2..=4 {
+            precommits.extend(motor.ao_receber_voto(voto(Etapa::Prevoto, 1, &hash_local, id), &b, &config).unwrap().votos);
+        }
+        assert_eq!(precommits.len(), 1);
+        assert_eq!((precommits[0].etapa, precommits[0].rodada), (Etapa::Precommit, 1));
+        assert_eq!(precommits[0].hash_bloco, hash_local);
+        assert_eq!(motor.travas[&1].hash, hash_local);
+    }
+
+    #[test]
+    fn voto_muito_a_frente_da_ponta_e_recusado() {
+        let config = testes::config();
+        let (a, _) = cadeias_divergentes(&config);
+        let votar = |altura| Voto::novo(Etapa::Prevoto, &config.genesis.id_cadeia, altura, 0, "ab", testes::chave_autoridade(2), 2);
+        let mut motor = motor();
+
+        motor.ao_receber_voto(votar(1 + ALTURAS_A_FRENTE), &a, &config).unwrap();
+        assert!(matches!(
+            motor.ao_receber_voto(votar(2 + ALTURAS_A_FRENTE), &a, &config),
+            Err(FinalidadeErro::VotoMuitoAFrente { ponta: 1, .. })
+        ));
+        assert!(motor.votos.keys().all(|(altura, _, _)| *altura <= 1 + ALTURAS_A_FRENTE));
+    }
+}
@@ -0,0 +1,126 @@
+//! Especificação do gênesis: a identidade da cadeia e tudo o que os nós
+//! precisam combinar antes do primeiro bloco (instituição, autoridades
+//! iniciais, parâmetros de consenso, emissores, política de avaliação e
+//! regras de validação). O bloco gênesis leva o hash da
+//! especificação no lugar do hash anterior, de modo que o hash do gênesis
+//! compromete a especificação inteira: redes com especificações diferentes
+//! têm gêneses diferentes e recusam os blocos uma da outra.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, TimeZone, Utc};
+use rsa::pkcs8::EncodePublicKey;
+use rsa::RsaPublicKey;
+
+use super::codificacao::{self, CodificacaoCanonica, Codificador};
+use super::{ConfigConsenso, ConfigGovernanca, Emissor, ParametrosCadeia, PoliticaAvaliacao, RegrasValidacao};
+
+#[derive(Debug, Clone)]
+pub struct EspecificacaoGenesis {
+    pub id_cadeia: String,
+    pub instituicao: String,
+    /// Timestamp do bloco gênesis.
+    pub timestamp: DateTime<Utc>,
+    /// Conjunto inicial de autoridades; depois dele, o conjunto é governado
+    /// por transações na cadeia.
+    pub autoridades: BTreeMap<u32, RsaPublicKey>,
+    /// Peso de cada autoridade na escolha de bifurcação (padrão 1).
+    pub pesos_autoridades: BTreeMap<u32, u64>,
+    pub parametros_cadeia: ParametrosCadeia,
+    pub consenso: ConfigConsenso,
+    pub governanca: ConfigGovernanca,
+    /// Professores e secretarias autorizados a emitir transações.
+    pub emissores: BTreeMap<u32, Emissor>,
+    pub politica_avaliacao: PoliticaAvaliacao,
+    pub regras_validacao: RegrasValidacao,
+}
+
+impl Default for EspecificacaoGenesis {
+    fn default() -> Self {
+        EspecificacaoGenesis {
+            id_cadeia: String::new(),
+            instituicao: String::new(),
+            timestamp: Utc.timestamp_opt(0, 0).unwrap(),
+            autoridades: BTreeMap::new(),
+            pesos_autoridades: BTreeMap::new(),
+            parametros_cadeia: ParametrosCadeia::default(),
+            consenso: ConfigConsenso::default(),
+            governanca: ConfigGovernanca::default(),
+            emissores: BTreeMap::new(),
+            politica_avaliacao: PoliticaAvaliacao::default(),
+            regras_validacao: RegrasValidacao::default(),
+        }
+    }
+}
+
+impl EspecificacaoGenesis {
+    /// Hash da codificação canônica da especificação.
+    pub fn hash(&self) -> String {
+        codificacao::hash_hex(&codificacao::codificar(self))
+    }
+
+    pub fn obter_emissor(&self, id_emissor: u32) -> Option<&Emissor> {
+        self.emissores.get(&id_emissor)
+    }
+
+    /// Peso da autoridade na escolha de bifurcação; autoridades sem peso
+    /// definido valem 1.
+    pub fn peso_autoridade(&self, id_autoridade: u32) -> u64 {
+        self.pesos_autoridades.get(&id_autoridade).copied().unwrap_or(1)
+    }
+}
+
+/// As chaves das autoridades e dos emissores entram pelo DER, que não
+/// depende da formatação do PEM de onde foram lidas.
+impl CodificacaoCanonica for EspecificacaoGenesis {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .texto(&self.id_cadeia)
+            .texto(&self.instituicao)
+            .data_hora(&self.timestamp)
+            .u32(self.autoridades.len() as u32);
+        for (id_autoridade, chave) in &self.autoridades {
+            let der = chave.to_public_key_der().expect("chave pública sem codificação DER");
+            saida.u32(*id_autoridade).bytes(der.as_bytes());
+        }
+        saida.u32(self.pesos_autoridades.len() as u32);
+        for (id_autoridade, peso) in &self.pesos_autoridades {
+            saida.u32(*id_autoridade).i64(*peso as i64);
+        }
+        saida
+            .valor(&self.parametros_cadeia)
+            .i64(self.consenso.tempo_limite_slot.num_seconds())
+            .u32(self.governanca.quorum_percentual)
+            .u32(self.emissores.len() as u32);
+        for (id_emissor, emissor) in &self.emissores {
+            saida.u32(*id_emissor).valor(emissor);
+        }
+        saida
+            .valor(&self.politica_avaliacao)
+            .valor(&self.regras_validacao);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blockchain::testes;
+
+    #[test]
+    fn hash_compromete_emissores_e_regras_academicas() {
+        let especificacao = testes::config().genesis;
+        let original = especificacao.hash();
+
+        let mut sem_professor = especificacao.clone();
+        sem_professor.emissores.remove(&testes::PROFESSOR);
+        let mut outra_disciplina = especificacao.clone();
+        outra_disciplina.emissores.get_mut(&testes::PROFESSOR).unwrap().disciplinas.insert("FIS201".into());
+        let mut outra_media = especificacao.clone();
+        outra_media.politica_avaliacao.media_minima += 1.0;
+        let mut outra_escala = especificacao.clone();
+        outra_escala.regras_validacao.nota_maxima = 100.0;
+
+        for alterada in [sem_professor, outra_disciplina, outra_media, outra_escala] {
+            assert_ne!(alterada.hash(), original);
+        }
+    }
+}
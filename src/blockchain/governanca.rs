@@ -0,0 +1,264 @@
+//! Governança do conjunto de autoridades. O conjunto do gênesis vem da
+//! especificação do gênesis (`EspecificacaoGenesis::autoridades`, lida da
+//! tabela `[autoridades]` do `genesis.toml`); a partir dele, transações
+//! `AlteracaoAutoridades` incluem, trocam ou removem a chave de uma
+//! autoridade a partir de uma altura. Cada alteração precisa das assinaturas
+//! de um quórum das autoridades em vigor na altura do bloco que a inclui, e
+//! toda assinatura de bloco ou voto é verificada com o conjunto em vigor na
+//! altura dele.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use base64::{decode, encode};
+use rsa::pkcs1v15::{Signature as RsaSignature, SigningKey, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use signature::{Signer, Verifier};
+
+use crate::utils::config::Config;
+use crate::utils::erros::TransacaoErro;
+use crate::utils::helpers::de_hex;
+
+use super::codificacao::{self, CodificacaoCanonica, Codificador};
+
+#[derive(Debug, Clone)]
+pub struct ConfigGovernanca {
+    /// Percentual mínimo das autoridades em vigor que precisa assinar uma
+    /// alteração do conjunto, arredondado para cima.
+    pub quorum_percentual: u32,
+}
+
+impl Default for ConfigGovernanca {
+    fn default() -> Self {
+        ConfigGovernanca { quorum_percentual: 67 }
+    }
+}
+
+impl ConfigGovernanca {
+    /// Quantas assinaturas aprovam uma alteração entre `n` autoridades.
+    pub fn quorum(&self, n: usize) -> usize {
+        (n * self.quorum_percentual as usize).div_ceil(100).max(1)
+    }
+}
+
+/// Chaves das autoridades em vigor em uma altura.
+#[derive(Debug, Clone, Default)]
+pub struct ConjuntoAutoridades {
+    chaves: BTreeMap<u32, RsaPublicKey>,
+}
+
+impl ConjuntoAutoridades {
+    /// Conjunto do gênesis, lido da especificação do gênesis.
+    pub fn do_genesis(config: &Config) -> Self {
+        ConjuntoAutoridades {
+            chaves: config.genesis.autoridades.clone(),
+        }
+    }
+
+    pub fn chave(&self, id_autoridade: u32) -> Option<&RsaPublicKey> {
+        self.chaves.get(&id_autoridade)
+    }
+
+    /// Ids das autoridades, em ordem crescente.
+    pub fn ids(&self) -> Vec<u32> {
+        self.chaves.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chaves.is_empty()
+    }
+
+    /// Inclui ou troca (`Some`) ou remove (`None`) a chave de uma
+    /// autoridade. O PEM já foi validado quando a transação entrou na cadeia.
+    pub fn alterar(&mut self, id_autoridade: u32, chave_publica_pem: Option<&str>) {
+        match chave_publica_pem.and_then(|pem| RsaPublicKey::from_public_key_pem(pem).ok()) {
+            Some(chave) => self.chaves.insert(id_autoridade, chave),
+            None => self.chaves.remove(&id_autoridade),
+        };
+    }
+}
+
+/// Assinatura de uma autoridade aprovando uma alteração do conjunto.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AssinaturaAutoridade {
+    pub id_autoridade: u32,
+    pub assinatura: String,
+}
+
+/// Hash do que as autoridades assinam para aprovar uma alteração. Inclui o
+/// id da cadeia, para que a aprovação não valha em outra rede.
+pub fn hash_alteracao(
+    id_cadeia: &str,
+    id_autoridade: u32,
+    chave_publica_pem: Option<&str>,
+    altura_vigencia: u32,
+) -> String {
+    let mut saida = Codificador::novo();
+    saida.texto(id_cadeia).u32(id_autoridade);
+    match chave_publica_pem {
+        Some(pem) => saida.u8(1).texto(pem),
+        None => saida.u8(0),
+    };
+    saida.u32(altura_vigencia);
+    codificacao::hash_hex(&saida.finalizar())
+}
+
+impl AssinaturaAutoridade {
+    pub fn nova(hash: &str, chave_privada: &RsaPrivateKey, id_autoridade: u32) -> Self {
+        let hash_bytes = de_hex(hash).unwrap();
+        let signing_key = SigningKey::<Sha256>::new(chave_privada.clone());
+        AssinaturaAutoridade {
+            id_autoridade,
+            assinatura: encode(signing_key.sign(&hash_bytes).as_ref()),
+        }
+    }
+
+    fn verificar(&self, hash: &str, autoridades: &ConjuntoAutoridades) -> Result<(), TransacaoErro> {
+        let chave_publica = autoridades
+            .chave(self.id_autoridade)
+            .ok_or(TransacaoErro::AutoridadeDesconhecida(self.id_autoridade))?;
+
+        let invalida = || TransacaoErro::AssinaturaAutoridadeInvalida(self.id_autoridade);
+        let hash_bytes = de_hex(hash).ok_or_else(invalida)?;
+        let assinatura_bytes = decode(&self.assinatura).map_err(|_| invalida())?;
+        let assinatura = RsaSignature::from(assinatura_bytes.into_boxed_slice());
+
+        VerifyingKey::<Sha256>::new(chave_publica.clone())
+            .verify(&hash_bytes, &assinatura)
+            .map_err(|_| invalida())
+    }
+}
+
+/// Confere se as assinaturas são de autoridades distintas do conjunto, são
+/// válidas sobre `hash` e atingem o quórum de `[governanca]`.
+pub fn verificar_quorum(
+    hash: &str,
+    assinaturas: &[AssinaturaAutoridade],
+    autoridades: &ConjuntoAutoridades,
+    config: &Config,
+) -> Result<(), TransacaoErro> {
+    let mut signatarias = BTreeSet::new();
+    for assinatura in assinaturas {
+        if !signatarias.insert(assinatura.id_autoridade) {
+            return Err(TransacaoErro::AssinaturaAutoridadeRepetida(assinatura.id_autoridade));
+        }
+        assinatura.verificar(hash, autoridades)?;
+    }
+
+    let quorum = config.genesis.governanca.quorum(autoridades.len());
+    if signatarias.len() < quorum {
+        return Err(TransacaoErro::QuorumAutoridadesInsuficiente {
+            assinaturas: signatarias.len(),
+            quorum,
+        });
+    }
+    Ok(())
+}
+
+impl CodificacaoCanonica for AssinaturaAutoridade {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida.u32(self.id_autoridade).texto(&self.assinatura);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::estado::EstadoAcademico;
+    use crate::blockchain::testes;
+    use crate::blockchain::{TipoTransacao, Transacao};
+
+    fn assinaturas(hash: &str, signatarias: &[u32]) -> Vec<AssinaturaAutoridade> {
+        signatarias
+            .iter()
+            .map(|&id| AssinaturaAutoridade::nova(hash, testes::chave_autoridade(id), id))
+            .collect()
+    }
+
+    /// Remoção da autoridade 2 a partir de `altura_vigencia`.
+    fn remover_autoridade_2(id_transacao: u32, altura_vigencia: u32, signatarias: &[u32], config: &Config) -> Transacao {
+        let hash = hash_alteracao(&config.genesis.id_cadeia, 2, None, altura_vigencia);
+        testes::transacao(
+            id_transacao,
+            TipoTransacao::AlteracaoAutoridades {
+                id_autoridade: 2,
+                chave_publica_pem: None,
+                altura_vigencia,
+                assinaturas: assinaturas(&hash, signatarias),
+            },
+        )
+    }
+
+    #[test]
+    fn quorum_arredonda_para_cima() {
+        let governanca = ConfigGovernanca::default();
+        assert_eq!(governanca.quorum(1), 1);
+        assert_eq!(governanca.quorum(2), 2);
+        assert_eq!(governanca.quorum(4), 3);
+        assert_eq!(ConfigGovernanca { quorum_percentual: 0 }.quorum(4), 1);
+    }
+
+    #[test]
+    fn quorum_conta_cada_autoridade_uma_vez() {
+        let config = testes::config();
+        let autoridades = ConjuntoAutoridades::do_genesis(&config);
+        let hash = hash_alteracao(&config.genesis.id_cadeia, 2, None, 5);
+
+        assert!(matches!(
+            verificar_quorum(&hash, &assinaturas(&hash, &[1]), &autoridades, &config),
+            Err(TransacaoErro::QuorumAutoridadesInsuficiente { assinaturas: 1, quorum: 2 })
+        ));
+        assert!(matches!(
+            verificar_quorum(&hash, &assinaturas(&hash, &[1, 1]), &autoridades, &config),
+            Err(TransacaoErro::AssinaturaAutoridadeRepetida(1))
+        ));
+        let outra = hash_alteracao(&config.genesis.id_cadeia, 2, None, 6);
+        assert!(matches!(
+            verificar_quorum(&hash, &assinaturas(&outra, &[1, 2]), &autoridades, &config),
+            Err(TransacaoErro::AssinaturaAutoridadeInvalida(1))
+        ));
+        verificar_quorum(&hash, &assinaturas(&hash, &[1, 2]), &autoridades, &config).unwrap();
+    }
+
+    #[test]
+    fn alteracao_vigora_so_depois_do_bloco_que_a_inclui() {
+        let config = testes::config();
+        let mut estado = EstadoAcademico::default();
+
+        assert!(matches!(
+            estado.aplicar(&remover_autoridade_2(1, 1, &[1, 2], &config), &config),
+            Err(TransacaoErro::VigenciaInvalida { altura_vigencia: 1, minima: 2 })
+        ));
+        estado.aplicar(&remover_autoridade_2(2, 3, &[1, 2], &config), &config).unwrap();
+        assert_eq!(estado.autoridades_em(2, &config).ids(), [1, 2]);
+        assert_eq!(estado.autoridades_em(3, &config).ids(), [1]);
+    }
+
+    #[test]
+    fn alteracao_nao_pode_esvaziar_o_conjunto() {
+        let config = testes::config();
+        let mut estado = EstadoAcademico::default();
+        estado.aplicar(&remover_autoridade_2(1, 3, &[1, 2], &config), &config).unwrap();
+
+        let hash = hash_alteracao(&config.genesis.id_cadeia, 1, None, 4);
+        let remover_autoridade_1 = testes::transacao(
+            2,
+            TipoTransacao::AlteracaoAutoridades {
+                id_autoridade: 1,
+                chave_publica_pem: None,
+                altura_vigencia: 4,
+                assinaturas: assinaturas(&hash, &[1, 2]),
+            },
+        );
+        assert!(matches!(
+            estado.aplicar(&remover_autoridade_1, &config),
+            Err(TransacaoErro::SemAutoridades)
+        ));
+    }
+}
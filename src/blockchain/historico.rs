@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use super::indices::RegistroIndexado;
+use super::{Estudante, Nota, SituacaoDisciplina};
+
+/// Bloco e transação de onde veio uma linha do histórico.
+#[derive(Serialize, Debug, Clone)]
+pub struct OrigemRegistro {
+    pub altura: usize,
+    pub hash_bloco: String,
+    pub id_transacao: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DisciplinaHistorico {
+    pub codigo: String,
+    pub nome: String,
+    pub notas: Vec<Nota>,
+    pub media: f32,
+    pub frequencia: f32,
+    pub situacao: SituacaoDisciplina,
+    pub origem: OrigemRegistro,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PeriodoHistorico {
+    pub ano: u32,
+    pub semestre: u8,
+    pub disciplinas: Vec<DisciplinaHistorico>,
+    pub media_periodo: Option<f32>,
+    pub media_acumulada: Option<f32>,
+}
+
+/// Histórico escolar consolidado de um estudante, montado a partir dos seus
+/// registros acadêmicos e das transações da cadeia que o citam.
+#[derive(Serialize, Debug, Clone)]
+pub struct HistoricoEscolar {
+    pub id_estudante: u32,
+    pub nome: String,
+    pub data_nascimento: NaiveDate,
+    pub periodos: Vec<PeriodoHistorico>,
+    pub media_geral: Option<f32>,
+}
+
+fn media(valores: &[f32]) -> Option<f32> {
+    if valores.is_empty() {
+        None
+    } else {
+        Some(valores.iter().sum::<f32>() / valores.len() as f32)
+    }
+}
+
+fn formatar_media(valor: Option<f32>) -> String {
+    valor.map(|m| format!("{:.2}", m)).unwrap_or_else(|| String::from("-"))
+}
+
+fn campo_csv(valor: &str) -> String {
+    if valor.contains([',', '"', '\n']) {
+        format!("\"{}\"", valor.replace('"', "\"\""))
+    } else {
+        valor.to_string()
+    }
+}
+
+impl HistoricoEscolar {
+    /// Monta o histórico a partir dos registros acadêmicos do estudante. Os
+    /// registros indexados, na ordem da cadeia, dão a origem de cada
+    /// disciplina: a última transação que a alterou.
+    pub fn consolidar(estudante: &Estudante, registros: &[RegistroIndexado]) -> Self {
+        let mut origens: HashMap<(u32, &str), OrigemRegistro> = HashMap::new();
+        for registro in registros {
+            let tipo = &registro.transacao.tipo;
+            if let (Some(id_periodo), Some(codigo)) = (tipo.id_periodo(), tipo.codigo_disciplina()) {
+                origens.insert(
+                    (id_periodo, codigo),
+                    OrigemRegistro {
+                        altura: registro.altura,
+                        hash_bloco: registro.hash_bloco.to_string(),
+                        id_transacao: registro.transacao.id_transacao,
+                    },
+                );
+            }
+        }
+
+        let mut periodos: BTreeMap<(u32, u8), BTreeMap<String, DisciplinaHistorico>> = BTreeMap::new();
+        for periodo in &estudante.periodos_letivos {
+            let disciplinas = periodos.entry((periodo.ano, periodo.semestre)).or_default();
+            for disciplina in &periodo.disciplinas {
+                let Some(origem) = origens.get(&(periodo.id_periodo, disciplina.codigo.as_str())) else {
+                    continue;
+                };
+                disciplinas.insert(
+                    disciplina.codigo.clone(),
+                    DisciplinaHistorico {
+                        codigo: disciplina.codigo.clone(),
+                        nome: disciplina.nome.clone(),
+                        notas: disciplina.notas.clone(),
+                        media: disciplina.media,
+                        frequencia: disciplina.frequencia,
+                        situacao: disciplina.situacao,
+                        origem: origem.clone(),
+                    },
+                );
+            }
+        }
+
+        let mut medias_acumuladas = Vec::new();
+        let periodos = periodos
+            .into_iter()
+            .map(|((ano, semestre), disciplinas)| {
+                let disciplinas: Vec<DisciplinaHistorico> = disciplinas.into_values().collect();
+                // Disciplinas em curso, trancadas ou dispensadas não entram nas médias
+                let medias: Vec<f32> = disciplinas
+                    .iter()
+                    .filter(|d| d.situacao.eh_resultado_final())
+                    .map(|d| d.media)
+                    .collect();
+                medias_acumuladas.extend_from_slice(&medias);
+                PeriodoHistorico {
+                    ano,
+                    semestre,
+                    media_periodo: media(&medias),
+                    media_acumulada: media(&medias_acumuladas),
+                    disciplinas,
+                }
+            })
+            .collect();
+
+        HistoricoEscolar {
+            id_estudante: estudante.id_estudante,
+            nome: estudante.nome.clone(),
+            data_nascimento: estudante.data_nascimento,
+            periodos,
+            media_geral: media(&medias_acumuladas),
+        }
+    }
+
+    pub fn para_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn para_csv(&self) -> String {
+        let mut saida = String::from(
+            "ano,semestre,codigo,disciplina,media,frequencia,situacao,media_periodo,media_acumulada,altura_bloco,hash_bloco,id_transacao\n",
+        );
+        for periodo in &self.periodos {
+            for disciplina in &periodo.disciplinas {
+                let _ = writeln!(
+                    saida,
+                    "{},{},{},{},{:.2},{:.2},{},{},{},{},{},{}",
+                    periodo.ano,
+                    periodo.semestre,
+                    campo_csv(&disciplina.codigo),
+                    campo_csv(&disciplina.nome),
+                    disciplina.media,
+                    disciplina.frequencia,
+                    campo_csv(&disciplina.situacao.to_string()),
+                    formatar_media(periodo.media_periodo),
+                    formatar_media(periodo.media_acumulada),
+                    disciplina.origem.altura,
+                    disciplina.origem.hash_bloco,
+                    disciplina.origem.id_transacao,
+                );
+            }
+        }
+        saida
+    }
+
+    pub fn para_texto(&self) -> String {
+        let mut saida = String::new();
+        let _ = writeln!(saida, "HISTÓRICO ESCOLAR");
+        let _ = writeln!(saida, "Estudante: {} (ID {})", self.nome, self.id_estudante);
+        let _ = writeln!(saida, "Nascimento: {}", self.data_nascimento.format("%d/%m/%Y"));
+        for periodo in &self.periodos {
+            let _ = writeln!(saida);
+            let _ = writeln!(saida, "Período {}/{}", periodo.ano, periodo.semestre);
+            for disciplina in &periodo.disciplinas {
+                let _ = writeln!(
+                    saida,
+                    "  {:<10} {:<30} média {:>5.2}  freq. {:>6.2}%  {:<19}  [bloco {} {}, transação {}]",
+                    disciplina.codigo,
+                    disciplina.nome,
+                    disciplina.media,
+                    disciplina.frequencia,
+                    disciplina.situacao.to_string(),
+                    disciplina.origem.altura,
+                    disciplina.origem.hash_bloco,
+                    disciplina.origem.id_transacao,
+                );
+            }
+            let _ = writeln!(
+                saida,
+                "  Média do período: {}  Média acumulada: {}",
+                formatar_media(periodo.media_periodo),
+                formatar_media(periodo.media_acumulada),
+            );
+        }
+        let _ = writeln!(saida);
+        let _ = writeln!(saida, "Média geral: {}", formatar_media(self.media_geral));
+        saida
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes::{self, lancamento_nota, matricula_em_disciplina, DISCIPLINA};
+    use crate::blockchain::{Blockchain, TipoTransacao, Transacao};
+
+    fn historico(transacoes: Vec<Transacao>) -> HistoricoEscolar {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, transacoes, 1, &config);
+        blockchain.historico_escolar(1).unwrap()
+    }
+
+    #[test]
+    fn correcao_substitui_a_nota_e_vira_a_origem_da_disciplina() {
+        let mut transacoes = matricula_em_disciplina(1, 1);
+        transacoes.push(lancamento_nota(4, 1, 5.0));
+        transacoes.push(testes::transacao(
+            5,
+            TipoTransacao::CorrecaoNota {
+                id_estudante: 1,
+                id_periodo: 1,
+                codigo_disciplina: DISCIPLINA.into(),
+                id_transacao_corrigida: 4,
+                nota: Nota::nova_nota(1, 7.0, "Prova", NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()),
+                motivo: "erro de digitação".into(),
+            },
+        ));
+        let historico = historico(transacoes);
+
+        let disciplina = &historico.periodos[0].disciplinas[0];
+        assert_eq!(disciplina.notas.len(), 1);
+        assert_eq!(disciplina.notas[0].valor, 7.0);
+        assert_eq!(disciplina.media, 7.0);
+        assert_eq!(disciplina.origem.id_transacao, 5);
+        assert_eq!(disciplina.origem.altura, 1);
+    }
+
+    #[test]
+    fn json_traz_periodos_disciplinas_e_origem() {
+        let mut transacoes = matricula_em_disciplina(1, 1);
+        transacoes.push(lancamento_nota(4, 1, 8.0));
+        let historico = historico(transacoes);
+
+        let json: serde_json::Value = serde_json::from_str(&historico.para_json()).unwrap();
+        assert_eq!(json["id_estudante"], 1);
+        assert_eq!(json["nome"], "Estudante 1");
+        assert_eq!(json["media_geral"], serde_json::Value::Null);
+        let disciplina = &json["periodos"][0]["disciplinas"][0];
+        assert_eq!(disciplina["codigo"], DISCIPLINA);
+        assert_eq!(disciplina["notas"][0]["valor"], 8.0);
+        assert_eq!(disciplina["origem"]["id_transacao"], 4);
+        assert_eq!(disciplina["origem"]["hash_bloco"], historico.periodos[0].disciplinas[0].origem.hash_bloco);
+    }
+
+    #[test]
+    fn csv_escapa_virgulas_e_aspas() {
+        let mut transacoes = matricula_em_disciplina(1, 1);
+        transacoes[2] = testes::transacao(
+            3,
+            TipoTransacao::MatriculaDisciplina {
+                id_estudante: 1,
+                id_periodo: 1,
+                id_disciplina: 1,
+                nome: "Cálculo \"A\", turma 1".into(),
+                codigo: DISCIPLINA.into(),
+            },
+        );
+        let historico = historico(transacoes);
+        let origem = &historico.periodos[0].disciplinas[0].origem;
+
+        let csv = historico.para_csv();
+        let linhas: Vec<&str> = csv.lines().collect();
+        assert_eq!(linhas.len(), 2);
+        assert!(linhas[0].starts_with("ano,semestre,codigo,disciplina,"));
+        assert_eq!(
+            linhas[1],
+            format!(
+                "2024,1,{},\"Cálculo \"\"A\"\", turma 1\",0.00,0.00,{},-,-,1,{},3",
+                DISCIPLINA,
+                SituacaoDisciplina::Cursando,
+                origem.hash_bloco
+            )
+        );
+    }
+
+    #[test]
+    fn texto_lista_periodos_disciplinas_e_medias() {
+        let historico = historico(matricula_em_disciplina(1, 1));
+        let texto = historico.para_texto();
+
+        assert!(texto.starts_with("HISTÓRICO ESCOLAR\nEstudante: Estudante 1 (ID 1)\nNascimento: 01/01/2000\n"));
+        assert!(texto.contains("\nPeríodo 2024/1\n"));
+        assert!(texto.contains(&format!("  {:<10} {:<30}", DISCIPLINA, "Cálculo I")));
+        assert!(texto.contains("  Média do período: -  Média acumulada: -\n"));
+        assert!(texto.ends_with("\nMédia geral: -\n"));
+    }
+}
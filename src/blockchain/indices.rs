@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use super::transacao::TipoTransacao;
+use super::{Bloco, SituacaoDisciplina, Transacao};
+
+/// Posição de uma transação na cadeia.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Localizacao {
+    pub altura: usize,
+    pub posicao: usize,
+}
+
+/// Resultado de uma consulta: a transação e o bloco em que ela está.
+#[derive(Debug, Clone)]
+pub struct RegistroIndexado<'a> {
+    pub altura: usize,
+    pub hash_bloco: &'a str,
+    pub transacao: &'a Transacao,
+    /// Situação atual do estudante na disciplina citada pela transação;
+    /// `None` se a transação não cita uma disciplina.
+    pub situacao: Option<SituacaoDisciplina>,
+}
+
+/// Índices secundários sobre as transações da cadeia. São mantidos em memória
+/// à medida que blocos são anexados e podem ser reconstruídos a partir da
+/// cadeia a qualquer momento.
+#[derive(Debug, Default, PartialEq)]
+pub struct IndicesBlockchain {
+    por_estudante: HashMap<u32, Vec<Localizacao>>,
+    por_periodo: HashMap<(u32, u8), Vec<Localizacao>>,
+    por_disciplina: HashMap<String, Vec<Localizacao>>,
+    por_transacao: HashMap<u32, Localizacao>,
+    /// Ano e semestre de cada período registrado, por (estudante, período).
+    periodos: HashMap<(u32, u32), (u32, u8)>,
+}
+
+impl IndicesBlockchain {
+    pub fn reconstruir(cadeia: &[Bloco]) -> Self {
+        let mut indices = IndicesBlockchain::default();
+        for bloco in cadeia {
+            indices.indexar_bloco(bloco.cabecalho.indice as usize, bloco);
+        }
+        indices
+    }
+
+    pub fn indexar_bloco(&mut self, altura: usize, bloco: &Bloco) {
+        for (posicao, transacao) in bloco.transacoes.iter().enumerate() {
+            let localizacao = Localizacao { altura, posicao };
+            let tipo = &transacao.tipo;
+
+            self.por_transacao.insert(transacao.id_transacao, localizacao);
+
+            if let TipoTransacao::RegistroPeriodo { id_estudante, id_periodo, ano, semestre } = tipo {
+                self.periodos.insert((*id_estudante, *id_periodo), (*ano, *semestre));
+            }
+            if let Some(id_estudante) = tipo.id_estudante() {
+                self.por_estudante.entry(id_estudante).or_default().push(localizacao);
+
+                // As transações citam o período pelo id; o ano e o semestre
+                // vêm do registro do período, indexado antes delas.
+                let chave_periodo = tipo
+                    .id_periodo()
+                    .and_then(|id_periodo| self.periodos.get(&(id_estudante, id_periodo)));
+                if let Some(chave) = chave_periodo {
+                    self.por_periodo.entry(*chave).or_default().push(localizacao);
+                }
+            }
+            if let Some(codigo) = tipo.codigo_disciplina() {
+                self.por_disciplina.entry(codigo.to_string()).or_default().push(localizacao);
+            }
+        }
+    }
+
+    pub fn por_estudante(&self, id_estudante: u32) -> &[Localizacao] {
+        self.por_estudante.get(&id_estudante).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn por_periodo(&self, ano: u32, semestre: u8) -> &[Localizacao] {
+        self.por_periodo.get(&(ano, semestre)).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn por_disciplina(&self, codigo: &str) -> &[Localizacao] {
+        self.por_disciplina.get(codigo).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn por_transacao(&self, id_transacao: u32) -> Option<Localizacao> {
+        self.por_transacao.get(&id_transacao).copied()
+    }
+}
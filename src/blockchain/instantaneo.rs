@@ -0,0 +1,234 @@
+//! Instantâneos assinados do estado acadêmico. Uma autoridade exporta o
+//! estado resultante de um bloco junto com o próprio bloco; um nó novo parte
+//! dele, em vez de reaplicar a cadeia desde o gênesis, e valida apenas os
+//! blocos seguintes.
+//!
+//! A confiança vem do hash do bloco (o checkpoint), combinado fora da rede e
+//! informado no `config.toml`: o hash compromete a raiz de estado do
+//! cabeçalho, e o estado do instantâneo precisa ter exatamente essa raiz. A
+//! assinatura identifica a autoridade que exportou o instantâneo.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use base64::{decode, encode};
+use rsa::pkcs1v15::{Signature as RsaSignature, SigningKey, VerifyingKey};
+use rsa::RsaPrivateKey;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use signature::{Signer, Verifier};
+
+use crate::utils::config::Config;
+use crate::utils::erros::{BlocoErro, InstantaneoErro};
+use crate::utils::helpers::de_hex;
+
+use super::codificacao::{self, Codificador};
+use super::estado::EstadoAcademico;
+use super::Bloco;
+
+/// De onde partir quando o armazenamento de blocos está vazio.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigInstantaneo {
+    /// Arquivo do instantâneo exportado por uma autoridade.
+    pub arquivo: Option<PathBuf>,
+    /// Hash do bloco do instantâneo, obtido por um canal confiável.
+    pub hash_checkpoint: Option<String>,
+}
+
+impl ConfigInstantaneo {
+    /// Arquivo e checkpoint, se os dois estiverem configurados.
+    pub fn origem(&self) -> Option<(&Path, &str)> {
+        Some((self.arquivo.as_deref()?, self.hash_checkpoint.as_deref()?))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InstantaneoAssinado {
+    /// Bloco cujo estado resultante é `estado`.
+    pub bloco: Bloco,
+    pub estado: EstadoAcademico,
+    pub id_autoridade: u32,
+    pub assinatura: String,
+}
+
+impl InstantaneoAssinado {
+    pub fn novo(bloco: Bloco, estado: EstadoAcademico, chave_privada: &RsaPrivateKey, id_autoridade: u32) -> Self {
+        let mut instantaneo = InstantaneoAssinado {
+            bloco,
+            estado,
+            id_autoridade,
+            assinatura: String::new(),
+        };
+        let hash_bytes = de_hex(&instantaneo.hash_conteudo()).unwrap();
+        let signing_key = SigningKey::<Sha256>::new(chave_privada.clone());
+        instantaneo.assinatura = encode(signing_key.sign(&hash_bytes).as_ref());
+        instantaneo
+    }
+
+    /// Altura do bloco do instantâneo.
+    pub fn altura(&self) -> u32 {
+        self.bloco.cabecalho.indice
+    }
+
+    /// Hash do que a autoridade assina: o bloco e a raiz do estado, com um
+    /// prefixo que impede confundir a assinatura com a de um bloco ou voto.
+    fn hash_conteudo(&self) -> String {
+        let mut saida = Codificador::novo();
+        saida
+            .texto("instantaneo")
+            .texto(&self.bloco.hash_atual)
+            .texto(&self.estado.raiz());
+        codificacao::hash_hex(&saida.finalizar())
+    }
+
+    /// Confere o instantâneo contra o checkpoint: o bloco precisa ter esse
+    /// hash e ser desta cadeia, o estado precisa ter a raiz gravada no
+    /// cabeçalho e a assinatura precisa ser de uma autoridade em vigor e não
+    /// suspensa na altura do bloco.
+    pub fn verificar(&self, hash_checkpoint: &str, config: &Config) -> Result<(), InstantaneoErro> {
+        let bloco = &self.bloco;
+        if bloco.hash_atual != hash_checkpoint {
+            return Err(InstantaneoErro::CheckpointDivergente(bloco.hash_atual.clone()));
+        }
+        bloco.cabecalho.verificar_versao()?;
+        if bloco.cabecalho.id_cadeia != config.genesis.id_cadeia {
+            return Err(BlocoErro::CadeiaIncorreta.into());
+        }
+        if bloco.hash_atual != bloco.calcular_hash() {
+            return Err(BlocoErro::HashInvalido.into());
+        }
+        bloco.verificar_raiz_merkle()?;
+        if self.estado.altura() != bloco.cabecalho.indice {
+            return Err(InstantaneoErro::AlturaDivergente {
+                estado: self.estado.altura(),
+                bloco: bloco.cabecalho.indice,
+            });
+        }
+        if self.estado.raiz() != bloco.cabecalho.raiz_estado {
+            return Err(BlocoErro::RaizEstadoInvalida.into());
+        }
+
+        let autoridades = self.estado.autoridades_em(bloco.cabecalho.indice, config);
+        if bloco.cabecalho.indice > 0 {
+            bloco.verificar_assinatura(&autoridades)?;
+        }
+        let chave_publica = autoridades
+            .chave(self.id_autoridade)
+            .filter(|_| !self.estado.autoridade_suspensa(self.id_autoridade))
+            .ok_or(InstantaneoErro::AutoridadeDesconhecida(self.id_autoridade))?;
+        let hash_bytes = de_hex(&self.hash_conteudo()).ok_or(InstantaneoErro::AssinaturaInvalida)?;
+        let assinatura_bytes = decode(&self.assinatura).map_err(|_| InstantaneoErro::AssinaturaInvalida)?;
+        let assinatura = RsaSignature::from(assinatura_bytes.into_boxed_slice());
+        VerifyingKey::<Sha256>::new(chave_publica.clone())
+            .verify(&hash_bytes, &assinatura)
+            .map_err(|_| InstantaneoErro::AssinaturaInvalida)
+    }
+
+    pub fn salvar(&self, caminho: &Path) -> io::Result<()> {
+        let dados = serde_json::to_vec(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let temporario = caminho.with_extension("tmp");
+        fs::write(&temporario, dados)?;
+        fs::rename(temporario, caminho)
+    }
+
+    /// Lê o instantâneo e refaz os índices do estado que não são gravados.
+    pub fn ler(caminho: &Path) -> io::Result<Self> {
+        let dados = fs::read(caminho)?;
+        let mut instantaneo: InstantaneoAssinado = serde_json::from_slice(&dados)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        instantaneo.estado.reconstruir_derivados();
+        Ok(instantaneo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes::{self, matricula};
+    use crate::blockchain::Blockchain;
+
+    /// Gênesis e um bloco com a matrícula do estudante 1.
+    fn origem(config: &Config) -> Blockchain {
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, config);
+        blockchain
+    }
+
+    #[test]
+    fn no_iniciado_por_instantaneo_valida_so_os_blocos_seguintes() {
+        let config = testes::config();
+        let mut origem = origem(&config);
+        let instantaneo = origem.exportar_instantaneo(1, testes::chave_autoridade(1), 1, &config).unwrap();
+        instantaneo.verificar(&origem.cadeia[1].hash_atual, &config).unwrap();
+        let bloco_2 = testes::produzir(&mut origem, vec![matricula(2, 2)], 2, &config);
+
+        let mut novo = Blockchain::do_instantaneo(instantaneo);
+        novo.adicionar_bloco_externo(bloco_2, &config).unwrap();
+
+        assert_eq!(novo.altura(), 2);
+        assert!(novo.bloco(0).is_none());
+        assert_eq!(novo.estado().raiz(), origem.estado().raiz());
+        assert!(novo.estado().estudante(1).is_some());
+    }
+
+    #[test]
+    fn instantaneo_de_outro_bloco_ou_com_outro_estado_e_recusado() {
+        let config = testes::config();
+        let origem = origem(&config);
+        let checkpoint = origem.cadeia[1].hash_atual.clone();
+        let instantaneo = origem.exportar_instantaneo(1, testes::chave_autoridade(1), 1, &config).unwrap();
+
+        assert!(matches!(
+            instantaneo.verificar(&origem.cadeia[0].hash_atual, &config),
+            Err(InstantaneoErro::CheckpointDivergente(_))
+        ));
+
+        let do_genesis = origem.exportar_instantaneo(0, testes::chave_autoridade(1), 1, &config).unwrap();
+        let outra_altura = InstantaneoAssinado { estado: do_genesis.estado, ..instantaneo.clone() };
+        assert!(matches!(
+            outra_altura.verificar(&checkpoint, &config),
+            Err(InstantaneoErro::AlturaDivergente { estado: 0, bloco: 1 })
+        ));
+
+        let mut outro_estado = instantaneo;
+        outro_estado.estado.aplicar(&matricula(2, 2), &config).unwrap();
+        assert!(matches!(
+            outro_estado.verificar(&checkpoint, &config),
+            Err(InstantaneoErro::BlocoInvalido(BlocoErro::RaizEstadoInvalida))
+        ));
+    }
+
+    #[test]
+    fn assinatura_precisa_ser_da_autoridade_declarada() {
+        let config = testes::config();
+        let origem = origem(&config);
+        let checkpoint = origem.cadeia[1].hash_atual.clone();
+
+        let trocada = origem.exportar_instantaneo(1, testes::chave_autoridade(1), 2, &config).unwrap();
+        assert!(matches!(trocada.verificar(&checkpoint, &config), Err(InstantaneoErro::AssinaturaInvalida)));
+
+        let desconhecida = origem.exportar_instantaneo(1, testes::chave_autoridade(1), 3, &config).unwrap();
+        assert!(matches!(
+            desconhecida.verificar(&checkpoint, &config),
+            Err(InstantaneoErro::AutoridadeDesconhecida(3))
+        ));
+    }
+
+    #[test]
+    fn instantaneo_lido_do_disco_refaz_os_indices_do_estado() {
+        let config = testes::config();
+        let origem = origem(&config);
+        let instantaneo = origem.exportar_instantaneo(1, testes::chave_autoridade(1), 1, &config).unwrap();
+        let caminho = std::env::temp_dir().join(format!("instantaneo-{}.json", std::process::id()));
+
+        instantaneo.salvar(&caminho).unwrap();
+        let lido = InstantaneoAssinado::ler(&caminho).unwrap();
+        fs::remove_file(&caminho).unwrap();
+
+        lido.verificar(&origem.cadeia[1].hash_atual, &config).unwrap();
+        assert_eq!(lido.estado.raiz(), instantaneo.estado.raiz());
+        assert!(lido.estado.estudante(1).is_some());
+    }
+}
@@ -0,0 +1,277 @@
+//! Pool de transações pendentes, indexado pelo hash canônico de cada
+//! transação. Mantém a ordem de chegada, que é a ordem em que as transações
+//! são aplicadas ao estado e incluídas no próximo bloco.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::erros::TransacaoErro;
+
+use super::Transacao;
+
+/// O que fazer quando uma transação válida chega com o pool cheio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoliticaDespejo {
+    /// Recusa a transação recebida.
+    Recusar,
+    /// Descarta a transação há mais tempo no pool. As que dependiam dela
+    /// deixam de ser válidas e são descartadas na criação do próximo bloco.
+    DescartarMaisAntiga,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigMempool {
+    pub capacidade: usize,
+    pub politica_despejo: PoliticaDespejo,
+    /// Tempo máximo no pool antes de a transação expirar.
+    pub validade: Duration,
+}
+
+impl Default for ConfigMempool {
+    fn default() -> Self {
+        ConfigMempool {
+            capacidade: 10_000,
+            politica_despejo: PoliticaDespejo::Recusar,
+            validade: Duration::hours(72),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntradaMempool {
+    pub hash: String,
+    pub transacao: Transacao,
+    /// Momento em que a transação entrou no pool deste nó; a expiração conta
+    /// a partir daqui, e não do `timestamp` da transação.
+    pub recebida_em: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+pub struct Mempool {
+    entradas: VecDeque<EntradaMempool>,
+    hashes: HashSet<String>,
+}
+
+impl Mempool {
+    pub fn len(&self) -> usize {
+        self.entradas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entradas.is_empty()
+    }
+
+    pub fn contem(&self, hash: &str) -> bool {
+        self.hashes.contains(hash)
+    }
+
+    /// Entradas em ordem de chegada.
+    pub fn entradas(&self) -> impl Iterator<Item = &EntradaMempool> {
+        self.entradas.iter()
+    }
+
+    pub fn transacoes(&self) -> impl Iterator<Item = &Transacao> {
+        self.entradas.iter().map(|e| &e.transacao)
+    }
+
+    /// Insere uma transação já validada contra o estado. Com o pool cheio,
+    /// aplica a política de despejo e devolve a transação descartada, se
+    /// houver.
+    pub fn inserir(
+        &mut self,
+        transacao: Transacao,
+        config: &ConfigMempool,
+        agora: DateTime<Utc>,
+    ) -> Result<Option<Transacao>, TransacaoErro> {
+        let hash = transacao.hash();
+        if self.contem(&hash) {
+            return Err(TransacaoErro::JaPendente(hash));
+        }
+
+        let mut despejada = None;
+        if self.entradas.len() >= config.capacidade {
+            match config.politica_despejo {
+                PoliticaDespejo::Recusar => return Err(TransacaoErro::PoolCheio(config.capacidade)),
+                PoliticaDespejo::DescartarMaisAntiga => {
+                    despejada = self.remover_primeira().map(|e| e.transacao);
+                }
+            }
+        }
+
+        self.hashes.insert(hash.clone());
+        self.entradas.push_back(EntradaMempool {
+            hash,
+            transacao,
+            recebida_em: agora,
+        });
+        Ok(despejada)
+    }
+
+    /// Descarta as transações que estão no pool há mais tempo que a
+    /// validade configurada e devolve quantas foram descartadas.
+    pub fn expirar(&mut self, config: &ConfigMempool, agora: DateTime<Utc>) -> usize {
+        let antes = self.entradas.len();
+        self.reter(|e| agora - e.recebida_em <= config.validade);
+        antes - self.entradas.len()
+    }
+
+    /// Remove as transações incluídas em um bloco.
+    pub fn remover_incluidas(&mut self, transacoes: &[Transacao]) -> usize {
+        let incluidas: HashSet<String> = transacoes.iter().map(Transacao::hash).collect();
+        let antes = self.entradas.len();
+        self.reter(|e| !incluidas.contains(&e.hash));
+        antes - self.entradas.len()
+    }
+
+    /// Tira do pool a transação há mais tempo nele.
+    pub fn retirar(&mut self) -> Option<Transacao> {
+        self.remover_primeira().map(|e| e.transacao)
+    }
+
+    /// Substitui o conteúdo do pool, mantendo a ordem recebida e descartando
+    /// hashes repetidos.
+    pub fn substituir(&mut self, entradas: impl IntoIterator<Item = EntradaMempool>) {
+        self.entradas.clear();
+        self.hashes.clear();
+        for entrada in entradas {
+            if self.hashes.insert(entrada.hash.clone()) {
+                self.entradas.push_back(entrada);
+            }
+        }
+    }
+
+    /// Grava o pool em JSON. O arquivo é escrito ao lado e renomeado, para
+    /// que uma interrupção não deixe um pool pela metade.
+    pub fn salvar(&self, caminho: &Path) -> io::Result<()> {
+        let dados = serde_json::to_vec(&self.entradas)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let temporario = caminho.with_extension("tmp");
+        fs::write(&temporario, dados)?;
+        fs::rename(temporario, caminho)
+    }
+
+    /// Lê as entradas gravadas por `salvar`. Um arquivo inexistente equivale
+    /// a um pool vazio.
+    pub fn ler_entradas(caminho: &Path) -> io::Result<Vec<EntradaMempool>> {
+        match fs::read(caminho) {
+            Ok(dados) => serde_json::from_slice(&dados)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remover_primeira(&mut self) -> Option<EntradaMempool> {
+        let entrada = self.entradas.pop_front()?;
+        self.hashes.remove(&entrada.hash);
+        Some(entrada)
+    }
+
+    fn reter(&mut self, mut manter: impl FnMut(&EntradaMempool) -> bool) {
+        let hashes = &mut self.hashes;
+        self.entradas.retain(|e| {
+            let mantida = manter(e);
+            if !mantida {
+                hashes.remove(&e.hash);
+            }
+            mantida
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes::matricula;
+
+    fn hashes(mempool: &Mempool) -> Vec<String> {
+        mempool.entradas().map(|e| e.hash.clone()).collect()
+    }
+
+    #[test]
+    fn recusar_mantem_o_pool_cheio_intacto() {
+        let config = ConfigMempool { capacidade: 2, ..Default::default() };
+        let agora = Utc::now();
+        let primeira = matricula(1, 1);
+        let mut mempool = Mempool::default();
+        mempool.inserir(primeira.clone(), &config, agora).unwrap();
+        mempool.inserir(matricula(2, 2), &config, agora).unwrap();
+        let antes = hashes(&mempool);
+
+        assert!(matches!(mempool.inserir(matricula(3, 3), &config, agora), Err(TransacaoErro::PoolCheio(2))));
+        assert!(matches!(mempool.inserir(primeira, &config, agora), Err(TransacaoErro::JaPendente(_))));
+        assert_eq!(hashes(&mempool), antes);
+
+        let config = ConfigMempool { politica_despejo: PoliticaDespejo::DescartarMaisAntiga, ..config };
+        let despejada = mempool.inserir(matricula(3, 3), &config, agora).unwrap().unwrap();
+        assert_eq!(despejada.id_transacao, 1);
+        assert!(!mempool.contem(&antes[0]));
+    }
+
+    #[test]
+    fn expirar_conta_a_partir_da_chegada_ao_pool() {
+        let config = ConfigMempool { validade: Duration::hours(1), ..Default::default() };
+        let agora = Utc::now();
+        let mut mempool = Mempool::default();
+        mempool.inserir(matricula(1, 1), &config, agora - Duration::hours(2)).unwrap();
+        mempool.inserir(matricula(2, 2), &config, agora - Duration::hours(1)).unwrap();
+        mempool.inserir(matricula(3, 3), &config, agora).unwrap();
+        let expirada = hashes(&mempool)[0].clone();
+
+        assert_eq!(mempool.expirar(&config, agora), 1);
+        assert_eq!(mempool.transacoes().map(|t| t.id_transacao).collect::<Vec<_>>(), [2, 3]);
+        assert!(!mempool.contem(&expirada));
+        assert_eq!(mempool.expirar(&config, agora), 0);
+    }
+
+    #[test]
+    fn remover_incluidas_tira_so_as_do_bloco() {
+        let config = ConfigMempool::default();
+        let agora = Utc::now();
+        let transacoes: Vec<Transacao> = (1..=3).map(|id| matricula(id, id)).collect();
+        let mut mempool = Mempool::default();
+        for transacao in &transacoes {
+            mempool.inserir(transacao.clone(), &config, agora).unwrap();
+        }
+
+        assert_eq!(mempool.remover_incluidas(&[transacoes[1].clone(), matricula(9, 9)]), 1);
+        assert_eq!(mempool.transacoes().map(|t| t.id_transacao).collect::<Vec<_>>(), [1, 3]);
+        // Mesmo id com outro conteúdo é outra transação
+        let mut alterada = transacoes[2].clone();
+        alterada.assinatura.clear();
+        assert_eq!(mempool.remover_incluidas(&[alterada]), 0);
+        assert_eq!(mempool.len(), 2);
+    }
+
+    #[test]
+    fn salvar_e_ler_entradas_preservam_ordem_e_chegada() {
+        let diretorio = std::env::temp_dir().join(format!("uniblockchain-mempool-{}", std::process::id()));
+        fs::create_dir_all(&diretorio).unwrap();
+        let caminho = diretorio.join("mempool.json");
+        assert!(Mempool::ler_entradas(&caminho).unwrap().is_empty());
+
+        let config = ConfigMempool::default();
+        let agora = Utc::now();
+        let mut mempool = Mempool::default();
+        mempool.inserir(matricula(2, 2), &config, agora - Duration::minutes(5)).unwrap();
+        mempool.inserir(matricula(1, 1), &config, agora).unwrap();
+        mempool.salvar(&caminho).unwrap();
+        assert!(!caminho.with_extension("tmp").exists());
+
+        let entradas = Mempool::ler_entradas(&caminho).unwrap();
+        assert_eq!(entradas.iter().map(|e| e.hash.clone()).collect::<Vec<_>>(), hashes(&mempool));
+        assert_eq!(entradas[0].recebida_em, agora - Duration::minutes(5));
+        let mut lido = Mempool::default();
+        lido.substituir(entradas);
+        assert_eq!(hashes(&lido), hashes(&mempool));
+
+        fs::write(&caminho, b"{}").unwrap();
+        assert_eq!(Mempool::ler_entradas(&caminho).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_dir_all(&diretorio).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::utils::helpers::{de_hex, para_hex};
+
+use super::{codificacao, Bloco, CabecalhoBloco, Transacao};
+
+// Prefixos de domínio que impedem uma folha de se passar por nó interno.
+const PREFIXO_FOLHA: u8 = 0x00;
+const PREFIXO_NO: u8 = 0x01;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lado {
+    Esquerda,
+    Direita,
+}
+
+/// Um irmão no caminho da folha até a raiz.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PassoProva {
+    pub hash: String,
+    pub lado: Lado,
+}
+
+/// Prova de que uma transação está incluída em um bloco. Além do caminho de
+/// Merkle, carrega o cabeçalho do bloco, para recalcular o hash do bloco sem
+/// conhecer as demais transações.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProvaInclusao {
+    pub transacao: Transacao,
+    pub caminho: Vec<PassoProva>,
+    pub cabecalho: CabecalhoBloco,
+}
+
+fn hash_folha(transacao: &Transacao) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([PREFIXO_FOLHA]);
+    hasher.update(codificacao::codificar(transacao));
+    hasher.finalize().into()
+}
+
+fn hash_no(esquerda: &[u8; 32], direita: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([PREFIXO_NO]);
+    hasher.update(esquerda);
+    hasher.update(direita);
+    hasher.finalize().into()
+}
+
+/// Sobe um nível da árvore. Um nó sem par é promovido sem alteração, em vez
+/// de duplicado, para que listas diferentes nunca produzam a mesma raiz.
+fn proximo_nivel(nivel: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    nivel
+        .chunks(2)
+        .map(|par| match par {
+            [esquerda, direita] => hash_no(esquerda, direita),
+            [unico] => *unico,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Calcula a raiz de Merkle das transações de um bloco. Um bloco sem
+/// transações tem como raiz o SHA-256 da entrada vazia.
+pub fn raiz_merkle(transacoes: &[Transacao]) -> String {
+    if transacoes.is_empty() {
+        return para_hex(&Sha256::digest([]));
+    }
+    let mut nivel: Vec<[u8; 32]> = transacoes.iter().map(hash_folha).collect();
+    while nivel.len() > 1 {
+        nivel = proximo_nivel(&nivel);
+    }
+    para_hex(&nivel[0])
+}
+
+impl ProvaInclusao {
+    /// Gera a prova de inclusão da transação `id_transacao` no bloco.
+    pub fn gerar(bloco: &Bloco, id_transacao: u32) -> Option<Self> {
+        let indice_transacao = bloco
+            .transacoes
+            .iter()
+            .position(|t| t.id_transacao == id_transacao)?;
+        let mut posicao = indice_transacao;
+
+        let mut nivel: Vec<[u8; 32]> = bloco.transacoes.iter().map(hash_folha).collect();
+        let mut caminho = Vec::new();
+        while nivel.len() > 1 {
+            let irmao = posicao ^ 1;
+            if irmao < nivel.len() {
+                let lado = if irmao < posicao { Lado::Esquerda } else { Lado::Direita };
+                caminho.push(PassoProva { hash: para_hex(&nivel[irmao]), lado });
+            }
+            nivel = proximo_nivel(&nivel);
+            posicao /= 2;
+        }
+
+        Some(ProvaInclusao {
+            transacao: bloco.transacoes[indice_transacao].clone(),
+            caminho,
+            cabecalho: bloco.cabecalho.clone(),
+        })
+    }
+
+    /// Verifica a prova contra o hash de um bloco: a transação deve levar à
+    /// raiz de Merkle e o cabeçalho com essa raiz deve ter o hash informado.
+    pub fn verificar(&self, hash_bloco: &str) -> bool {
+        let mut atual = hash_folha(&self.transacao);
+        for passo in &self.caminho {
+            let irmao: [u8; 32] = match de_hex(&passo.hash).and_then(|b| b.try_into().ok()) {
+                Some(irmao) => irmao,
+                None => return false,
+            };
+            atual = match passo.lado {
+                Lado::Esquerda => hash_no(&irmao, &atual),
+                Lado::Direita => hash_no(&atual, &irmao),
+            };
+        }
+
+        para_hex(&atual) == self.cabecalho.raiz_merkle
+            && self.cabecalho.calcular_hash() == hash_bloco
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::testes;
+
+    fn bloco_com(quantidade: u32) -> Bloco {
+        let transacoes = (1..=quantidade).map(|id| testes::matricula(id, id)).collect();
+        let mut bloco = Bloco::novo_bloco("uniblockchain", 1, "0".repeat(64), transacoes, String::new(), None);
+        bloco.hash_atual = bloco.calcular_hash();
+        bloco
+    }
+
+    #[test]
+    fn toda_transacao_tem_prova_valida_e_a_raiz_e_a_do_cabecalho() {
+        for quantidade in [1, 2, 3, 5] {
+            let bloco = bloco_com(quantidade);
+            assert_eq!(raiz_merkle(&bloco.transacoes), bloco.cabecalho.raiz_merkle);
+            for id in 1..=quantidade {
+                let prova = ProvaInclusao::gerar(&bloco, id).unwrap();
+                assert!(prova.verificar(&bloco.hash_atual), "{} folhas, transação {}", quantidade, id);
+            }
+            assert!(ProvaInclusao::gerar(&bloco, quantidade + 1).is_none());
+        }
+    }
+
+    #[test]
+    fn folha_unica_e_a_propria_raiz() {
+        let bloco = bloco_com(1);
+        let prova = ProvaInclusao::gerar(&bloco, 1).unwrap();
+        assert!(prova.caminho.is_empty());
+        assert_eq!(para_hex(&hash_folha(&bloco.transacoes[0])), bloco.cabecalho.raiz_merkle);
+    }
+
+    #[test]
+    fn no_sem_par_e_promovido_sem_duplicacao() {
+        let bloco = bloco_com(3);
+        let folhas: Vec<[u8; 32]> = bloco.transacoes.iter().map(hash_folha).collect();
+        let esperada = hash_no(&hash_no(&folhas[0], &folhas[1]), &folhas[2]);
+        assert_eq!(para_hex(&esperada), bloco.cabecalho.raiz_merkle);
+        assert_ne!(
+            raiz_merkle(&bloco.transacoes),
+            raiz_merkle(&[bloco.transacoes.clone(), vec![bloco.transacoes[2].clone()]].concat())
+        );
+
+        // Com 5 folhas a última sobe dois níveis sozinha e só tem um irmão.
+        let bloco = bloco_com(5);
+        let prova = ProvaInclusao::gerar(&bloco, 5).unwrap();
+        assert_eq!(prova.caminho.len(), 1);
+        assert_eq!(prova.caminho[0].lado, Lado::Esquerda);
+        assert_eq!(ProvaInclusao::gerar(&bloco, 1).unwrap().caminho.len(), 3);
+    }
+
+    #[test]
+    fn prova_adulterada_nao_verifica() {
+        let bloco = bloco_com(5);
+        let prova = ProvaInclusao::gerar(&bloco, 2).unwrap();
+
+        let mut irmao_trocado = prova.clone();
+        irmao_trocado.caminho[1].hash = para_hex(&[0u8; 32]);
+        assert!(!irmao_trocado.verificar(&bloco.hash_atual));
+
+        let mut hash_malformado = prova.clone();
+        hash_malformado.caminho[0].hash.pop();
+        assert!(!hash_malformado.verificar(&bloco.hash_atual));
+
+        let mut lado_trocado = prova.clone();
+        lado_trocado.caminho[0].lado = Lado::Direita;
+        assert!(!lado_trocado.verificar(&bloco.hash_atual));
+
+        let mut outra_transacao = prova.clone();
+        outra_transacao.transacao = bloco.transacoes[0].clone();
+        assert!(!outra_transacao.verificar(&bloco.hash_atual));
+
+        let mut raiz_forjada = prova.clone();
+        raiz_forjada.transacao = testes::matricula(9, 9);
+        raiz_forjada.cabecalho.raiz_merkle = para_hex(&hash_folha(&raiz_forjada.transacao));
+        raiz_forjada.caminho.clear();
+        assert!(!raiz_forjada.verificar(&bloco.hash_atual));
+
+        assert!(!prova.verificar(&"0".repeat(64)));
+    }
+}
@@ -1,17 +1,52 @@
 // src/blockchain/mod.rs
 
 mod bloco;
+mod cabecalho;
+#[allow(clippy::module_inception)]
 mod blockchain;
 mod transacao;
 mod estudante;
 mod periodo_letivo;
 mod disciplina;
 mod nota;
+mod bifurcacao;
+mod merkle;
+mod codificacao;
+mod indices;
+mod historico;
+mod avaliacao;
+mod estado;
+mod validacao;
+mod mempool;
+mod emissor;
+mod consenso;
+mod finalidade;
+mod equivocacao;
+mod governanca;
+mod parametros;
+mod genesis;
+mod instantaneo;
+#[cfg(test)]
+pub(crate) mod testes;
 
 pub use bloco::Bloco;
+pub use cabecalho::CabecalhoBloco;
 pub use blockchain::Blockchain;
-pub use transacao::Transacao;
+pub use transacao::{TipoTransacao, Transacao};
 pub use estudante::Estudante;
 pub use periodo_letivo::PeriodoLetivo;
-pub use disciplina::Disciplina;
+pub use disciplina::{Disciplina, RegistroAula};
 pub use nota::Nota;
+pub use merkle::ProvaInclusao;
+pub use indices::RegistroIndexado;
+pub use avaliacao::{Arredondamento, PoliticaAvaliacao, RegraRecuperacao, SituacaoDisciplina, TipoMedia};
+pub use validacao::RegrasValidacao;
+pub use mempool::{ConfigMempool, Mempool, PoliticaDespejo};
+pub use emissor::{Emissor, Papel};
+pub use consenso::{espera_para_produzir, ConfigConsenso, ConfigProducao};
+pub use finalidade::{CertificadoQuorum, ConfigFinalidade, MotorFinalidade, Progresso, Voto};
+pub use equivocacao::{DetectorEquivocacao, ProvaEquivocacao};
+pub use governanca::{hash_alteracao, AssinaturaAutoridade, ConfigGovernanca, ConjuntoAutoridades};
+pub use parametros::ParametrosCadeia;
+pub use genesis::EspecificacaoGenesis;
+pub use instantaneo::{ConfigInstantaneo, InstantaneoAssinado};
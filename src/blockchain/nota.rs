@@ -2,6 +2,8 @@
 use serde::{Serialize, Deserialize};
 use chrono::NaiveDate;
 
+use super::codificacao::{CodificacaoCanonica, Codificador};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Nota {
     pub id_nota: u32,
@@ -11,12 +13,22 @@ pub struct Nota {
 }
 
 impl Nota {
-    pub fn nova_nota(id: u32, valor: f32, tipo: &str, ano: i32, mes: u32, dia: u32) -> Self {
+    pub fn nova_nota(id: u32, valor: f32, tipo: &str, data: NaiveDate) -> Self {
         Nota {
             id_nota: id,
             valor,
             tipo: tipo.to_string(),
-            data: NaiveDate::from_ymd_opt(ano, mes, dia).expect("Data inválida"),
+            data,
         }
     }
 }
+
+impl CodificacaoCanonica for Nota {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .u32(self.id_nota)
+            .f32(self.valor)
+            .texto(&self.tipo)
+            .data(&self.data);
+    }
+}
@@ -0,0 +1,176 @@
+//! Parâmetros da cadeia: limites que todo bloco precisa respeitar para ser
+//! aceito. Fazem parte das regras de consenso, então todos os nós da rede
+//! devem usar os mesmos valores.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::utils::erros::{BlocoErro, TransacaoErro};
+
+use super::codificacao::{self, CodificacaoCanonica, Codificador};
+use super::{Bloco, Transacao};
+
+#[derive(Debug, Clone)]
+pub struct ParametrosCadeia {
+    /// Tamanho máximo do bloco na codificação canônica, em bytes.
+    pub tamanho_maximo_bloco: usize,
+    /// Número máximo de transações em um bloco.
+    pub max_transacoes_bloco: usize,
+    /// Quanto o timestamp de um bloco pode estar à frente do relógio local,
+    /// e o de uma transação à frente do bloco que a inclui.
+    pub deriva_maxima_relogio: Duration,
+}
+
+impl Default for ParametrosCadeia {
+    fn default() -> Self {
+        ParametrosCadeia {
+            tamanho_maximo_bloco: 1_048_576,
+            max_transacoes_bloco: 1_000,
+            deriva_maxima_relogio: Duration::seconds(15),
+        }
+    }
+}
+
+impl ParametrosCadeia {
+    /// Confere os limites de um bloco que ocuparia a posição `altura` da
+    /// cadeia, com o relógio local marcando `agora`.
+    pub fn verificar_bloco(&self, bloco: &Bloco, altura: u32, agora: DateTime<Utc>) -> Result<(), BlocoErro> {
+        if bloco.cabecalho.indice != altura {
+            return Err(BlocoErro::AlturaIncorreta {
+                altura,
+                indice: bloco.cabecalho.indice,
+            });
+        }
+        if bloco.transacoes.len() > self.max_transacoes_bloco {
+            return Err(BlocoErro::TransacoesDemais {
+                quantidade: bloco.transacoes.len(),
+                maximo: self.max_transacoes_bloco,
+            });
+        }
+        for transacao in &bloco.transacoes {
+            self.verificar_timestamp_transacao(transacao, bloco.cabecalho.timestamp)
+                .map_err(|erro| BlocoErro::TransacaoInvalida {
+                    id_transacao: transacao.id_transacao,
+                    erro,
+                })?;
+        }
+        let tamanho = codificacao::codificar(bloco).len();
+        if tamanho > self.tamanho_maximo_bloco {
+            return Err(BlocoErro::TamanhoExcedido {
+                tamanho,
+                maximo: self.tamanho_maximo_bloco,
+            });
+        }
+        if bloco.cabecalho.timestamp > agora + self.deriva_maxima_relogio {
+            return Err(BlocoErro::TimestampFuturo(bloco.cabecalho.timestamp));
+        }
+        Ok(())
+    }
+
+    /// O timestamp de uma transação, que o emissor escolhe, limita as datas
+    /// que ela pode registrar; por isso ele não pode passar de `referencia`
+    /// (o timestamp do bloco que a inclui, ou o relógio local quando ela
+    /// entra no pool) mais a deriva permitida.
+    pub fn verificar_timestamp_transacao(&self, transacao: &Transacao, referencia: DateTime<Utc>) -> Result<(), TransacaoErro> {
+        if transacao.timestamp > referencia + self.deriva_maxima_relogio {
+            return Err(TransacaoErro::TimestampFuturo(transacao.timestamp));
+        }
+        Ok(())
+    }
+}
+
+impl CodificacaoCanonica for ParametrosCadeia {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .u32(self.tamanho_maximo_bloco as u32)
+            .u32(self.max_transacoes_bloco as u32)
+            .i64(self.deriva_maxima_relogio.num_seconds());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::blockchain::testes::{self, chaves, matricula, SECRETARIA};
+    use crate::blockchain::Blockchain;
+
+    fn matricula_em_2099() -> Transacao {
+        let mut transacao = matricula(1, 1);
+        transacao.timestamp = Utc.with_ymd_and_hms(2099, 1, 1, 0, 0, 0).unwrap();
+        transacao.assinar(&chaves().secretaria, SECRETARIA);
+        transacao
+    }
+
+    #[test]
+    fn pool_recusa_transacao_datada_no_futuro() {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+
+        assert!(matches!(
+            blockchain.adicionar_transacao(matricula_em_2099(), &config),
+            Err(TransacaoErro::TimestampFuturo(_))
+        ));
+        assert_eq!(blockchain.mempool().len(), 0);
+    }
+
+    #[test]
+    fn bloco_recusa_transacao_posterior_ao_seu_timestamp() {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        let mut bloco = testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, &config);
+        let parametros = &config.genesis.parametros_cadeia;
+        let agora = Utc::now();
+        parametros.verificar_bloco(&bloco, 1, agora).unwrap();
+
+        bloco.transacoes[0].timestamp = bloco.cabecalho.timestamp + parametros.deriva_maxima_relogio;
+        parametros.verificar_bloco(&bloco, 1, agora).unwrap();
+        bloco.transacoes[0] = matricula_em_2099();
+        assert!(matches!(
+            parametros.verificar_bloco(&bloco, 1, agora),
+            Err(BlocoErro::TransacaoInvalida { id_transacao: 1, erro: TransacaoErro::TimestampFuturo(_) })
+        ));
+    }
+
+    #[test]
+    fn bloco_fora_dos_limites_e_recusado() {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        let bloco = testes::produzir(&mut blockchain, vec![matricula(1, 1), matricula(2, 2)], 1, &config);
+        let tamanho = codificacao::codificar(&bloco).len();
+        let agora = Utc::now();
+
+        assert!(matches!(
+            config.genesis.parametros_cadeia.verificar_bloco(&bloco, 2, agora),
+            Err(BlocoErro::AlturaIncorreta { altura: 2, indice: 1 })
+        ));
+
+        let limite_de_transacoes = ParametrosCadeia { max_transacoes_bloco: 1, ..Default::default() };
+        assert!(matches!(
+            limite_de_transacoes.verificar_bloco(&bloco, 1, agora),
+            Err(BlocoErro::TransacoesDemais { quantidade: 2, maximo: 1 })
+        ));
+
+        let limite_de_tamanho = ParametrosCadeia { tamanho_maximo_bloco: tamanho - 1, ..Default::default() };
+        assert!(matches!(
+            limite_de_tamanho.verificar_bloco(&bloco, 1, agora),
+            Err(BlocoErro::TamanhoExcedido { maximo, .. }) if maximo == tamanho - 1
+        ));
+        ParametrosCadeia { tamanho_maximo_bloco: tamanho, ..Default::default() }.verificar_bloco(&bloco, 1, agora).unwrap();
+    }
+
+    #[test]
+    fn bloco_recusa_timestamp_alem_da_deriva_do_relogio_local() {
+        let config = testes::config();
+        let mut blockchain = Blockchain::nova_blockchain(&config.genesis);
+        let bloco = testes::produzir(&mut blockchain, vec![matricula(1, 1)], 1, &config);
+        let parametros = &config.genesis.parametros_cadeia;
+        let relogio_atrasado = bloco.cabecalho.timestamp - parametros.deriva_maxima_relogio;
+
+        parametros.verificar_bloco(&bloco, 1, relogio_atrasado).unwrap();
+        assert!(matches!(
+            parametros.verificar_bloco(&bloco, 1, relogio_atrasado - Duration::seconds(1)),
+            Err(BlocoErro::TimestampFuturo(timestamp)) if timestamp == bloco.cabecalho.timestamp
+        ));
+    }
+}
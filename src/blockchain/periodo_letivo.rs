@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 
+use super::codificacao::{CodificacaoCanonica, Codificador};
 use super::Disciplina;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -24,3 +25,13 @@ impl PeriodoLetivo {
         self.disciplinas.push(disciplina);
     }
 }
+
+impl CodificacaoCanonica for PeriodoLetivo {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .u32(self.id_periodo)
+            .u32(self.ano)
+            .u8(self.semestre)
+            .lista(&self.disciplinas);
+    }
+}
@@ -0,0 +1,164 @@
+//! Cenário comum aos testes: uma cadeia com duas autoridades de mesmo peso,
+//! sem rodízio por tempo, uma secretaria e um professor de `MAT101`. As
+//! chaves são geradas uma vez por execução.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::OnceLock;
+
+use chrono::NaiveDate;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+
+use crate::utils::config::Config;
+
+use super::finalidade::Etapa;
+use super::{Blockchain, Bloco, CertificadoQuorum, ConfigConsenso, Emissor, EspecificacaoGenesis, Nota, Papel, TipoTransacao, Transacao, Voto};
+
+pub const SECRETARIA: u32 = 10;
+pub const PROFESSOR: u32 = 20;
+pub const DISCIPLINA: &str = "MAT101";
+
+/// Chaves das autoridades 1 e 2, da secretaria e do professor.
+pub struct Chaves {
+    pub autoridades: [RsaPrivateKey; 2],
+    pub secretaria: RsaPrivateKey,
+    pub professor: RsaPrivateKey,
+}
+
+pub fn chaves() -> &'static Chaves {
+    static CHAVES: OnceLock<Chaves> = OnceLock::new();
+    CHAVES.get_or_init(|| {
+        let gerar = || RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        Chaves {
+            autoridades: [gerar(), gerar()],
+            secretaria: gerar(),
+            professor: gerar(),
+        }
+    })
+}
+
+pub fn chave_autoridade(id_autoridade: u32) -> &'static RsaPrivateKey {
+    &chaves().autoridades[id_autoridade as usize - 1]
+}
+
+pub fn config() -> Config {
+    let chaves = chaves();
+    let autoridades: BTreeMap<u32, RsaPublicKey> = [1, 2]
+        .into_iter()
+        .map(|id| (id, RsaPublicKey::from(chave_autoridade(id))))
+        .collect();
+    let emissores = BTreeMap::from([
+        (
+            SECRETARIA,
+            Emissor {
+                chave_publica: RsaPublicKey::from(&chaves.secretaria),
+                papel: Papel::Secretaria,
+                disciplinas: HashSet::new(),
+            },
+        ),
+        (
+            PROFESSOR,
+            Emissor {
+                chave_publica: RsaPublicKey::from(&chaves.professor),
+                papel: Papel::Professor,
+                disciplinas: HashSet::from([DISCIPLINA.to_string()]),
+            },
+        ),
+    ]);
+    Config {
+        genesis: EspecificacaoGenesis {
+            id_cadeia: "uniblockchain".into(),
+            instituicao: "Universidade Exemplo".into(),
+            autoridades,
+            consenso: ConfigConsenso { tempo_limite_slot: chrono::Duration::zero() },
+            emissores,
+            ..Default::default()
+        },
+        armazenamento: Default::default(),
+        mempool: Default::default(),
+        producao: Default::default(),
+        finalidade: Default::default(),
+        instantaneo: Default::default(),
+        sincronizacao: Default::default(),
+    }
+}
+
+/// Transação assinada pela secretaria ou, nos tipos de professor, pelo
+/// professor.
+pub fn transacao(id_transacao: u32, tipo: TipoTransacao) -> Transacao {
+    let (chave, id_emissor) = match tipo {
+        TipoTransacao::LancamentoNota { .. }
+        | TipoTransacao::CorrecaoNota { .. }
+        | TipoTransacao::RetratacaoNota { .. }
+        | TipoTransacao::LancamentoFrequencia { .. } => (&chaves().professor, PROFESSOR),
+        _ => (&chaves().secretaria, SECRETARIA),
+    };
+    let mut transacao = Transacao::nova_transacao(id_transacao, tipo);
+    transacao.assinar(chave, id_emissor);
+    transacao
+}
+
+pub fn matricula(id_transacao: u32, id_estudante: u32) -> Transacao {
+    transacao(
+        id_transacao,
+        TipoTransacao::MatriculaEstudante {
+            id_estudante,
+            nome: format!("Estudante {}", id_estudante),
+            data_nascimento: NaiveDate::from_ymd_opt(2000, 1, 1).unwrap(),
+        },
+    )
+}
+
+/// Matrícula, período 1 de 2024 e disciplina `DISCIPLINA`, com ids de
+/// transação a partir de `id_transacao`.
+pub fn matricula_em_disciplina(id_transacao: u32, id_estudante: u32) -> Vec<Transacao> {
+    vec![
+        matricula(id_transacao, id_estudante),
+        transacao(
+            id_transacao + 1,
+            TipoTransacao::RegistroPeriodo { id_estudante, id_periodo: 1, ano: 2024, semestre: 1 },
+        ),
+        transacao(
+            id_transacao + 2,
+            TipoTransacao::MatriculaDisciplina {
+                id_estudante,
+                id_periodo: 1,
+                id_disciplina: 1,
+                nome: "Cálculo I".into(),
+                codigo: DISCIPLINA.into(),
+            },
+        ),
+    ]
+}
+
+pub fn lancamento_nota(id_transacao: u32, id_estudante: u32, valor: f32) -> Transacao {
+    transacao(
+        id_transacao,
+        TipoTransacao::LancamentoNota {
+            id_estudante,
+            id_periodo: 1,
+            codigo_disciplina: DISCIPLINA.into(),
+            nota: Nota::nova_nota(1, valor, "Prova", NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()),
+        },
+    )
+}
+
+/// Coloca as transações no pool e cria o bloco seguinte com a autoridade.
+pub fn produzir(blockchain: &mut Blockchain, transacoes: Vec<Transacao>, id_autoridade: u32, config: &Config) -> Bloco {
+    for transacao in transacoes {
+        blockchain.adicionar_transacao(transacao, config).unwrap();
+    }
+    blockchain
+        .criar_e_adicionar_bloco(chave_autoridade(id_autoridade), id_autoridade, true, config)
+        .unwrap()
+}
+
+/// Finaliza o bloco local da altura com precommits das duas autoridades.
+pub fn certificar(blockchain: &mut Blockchain, altura: u32, config: &Config) {
+    let hash_bloco = blockchain.cadeia[altura as usize].hash_atual.clone();
+    let precommits = [1, 2]
+        .into_iter()
+        .map(|id| Voto::novo(Etapa::Precommit, &config.genesis.id_cadeia, altura, 0, &hash_bloco, chave_autoridade(id), id))
+        .collect();
+    let certificado = CertificadoQuorum { altura, rodada: 0, hash_bloco, precommits };
+    assert!(blockchain.registrar_certificado(certificado, config).unwrap());
+}
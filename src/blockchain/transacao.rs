@@ -1,29 +1,409 @@
+use base64::{decode, encode};
 use serde::{Serialize, Deserialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use rsa::pkcs1v15::{Signature as RsaSignature, SigningKey, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sha2::Sha256;
+use signature::{Signer, Verifier};
 
-use super::{Estudante, PeriodoLetivo};
+use crate::utils::config::Config;
+use crate::utils::erros::TransacaoErro;
+use crate::utils::helpers::de_hex;
+
+use super::codificacao::{self, CodificacaoCanonica, Codificador};
+use super::disciplina::RegistroAula;
+use super::{AssinaturaAutoridade, Nota, ProvaEquivocacao, SituacaoDisciplina};
+
+/// Conteúdo de uma transação. Cada tipo carrega apenas os próprios campos e
+/// referencia estudante, período e disciplina pelos seus identificadores.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TipoTransacao {
+    MatriculaEstudante {
+        id_estudante: u32,
+        nome: String,
+        data_nascimento: NaiveDate,
+    },
+    RegistroPeriodo {
+        id_estudante: u32,
+        id_periodo: u32,
+        ano: u32,
+        semestre: u8,
+    },
+    MatriculaDisciplina {
+        id_estudante: u32,
+        id_periodo: u32,
+        id_disciplina: u32,
+        nome: String,
+        codigo: String,
+    },
+    LancamentoNota {
+        id_estudante: u32,
+        id_periodo: u32,
+        codigo_disciplina: String,
+        nota: Nota,
+    },
+    /// Substitui o valor de uma nota. `id_transacao_corrigida` aponta para a
+    /// versão vigente da nota: o lançamento, a última correção ou a
+    /// retratação que ela desfaz.
+    CorrecaoNota {
+        id_estudante: u32,
+        id_periodo: u32,
+        codigo_disciplina: String,
+        id_transacao_corrigida: u32,
+        nota: Nota,
+        motivo: String,
+    },
+    /// Anula uma nota, que deixa de contar na média. A versão anulada continua
+    /// no histórico de alterações.
+    RetratacaoNota {
+        id_estudante: u32,
+        id_periodo: u32,
+        codigo_disciplina: String,
+        id_transacao_corrigida: u32,
+        id_nota: u32,
+        motivo: String,
+    },
+    LancamentoFrequencia {
+        id_estudante: u32,
+        id_periodo: u32,
+        codigo_disciplina: String,
+        aula: RegistroAula,
+    },
+    /// Fecha a disciplina com a média, a frequência e a situação calculadas
+    /// a partir das notas e aulas lançadas até aqui.
+    EncerramentoDisciplina {
+        id_estudante: u32,
+        id_periodo: u32,
+        codigo_disciplina: String,
+        media: f32,
+        frequencia: f32,
+        situacao: SituacaoDisciplina,
+    },
+    /// Inclui ou substitui (`Some`) ou remove (`None`) a chave pública de
+    /// uma autoridade a partir de `altura_vigencia`, com a aprovação de um
+    /// quórum das autoridades em vigor.
+    AlteracaoAutoridades {
+        id_autoridade: u32,
+        chave_publica_pem: Option<String>,
+        altura_vigencia: u32,
+        assinaturas: Vec<AssinaturaAutoridade>,
+    },
+    /// Suspende a autoridade que a prova mostra ter assinado mensagens
+    /// conflitantes.
+    SuspensaoAutoridade {
+        prova: ProvaEquivocacao,
+    },
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Transacao {
     pub id_transacao: u32,
-    pub estudante: Estudante,
-    pub periodo_letivo: PeriodoLetivo,
     pub timestamp: DateTime<Utc>,
+    pub tipo: TipoTransacao,
+    /// Professor ou secretaria que emitiu a transação.
+    pub id_emissor: u32,
+    /// Assinatura do emissor sobre `hash_conteudo`, em base64.
+    pub assinatura: String,
+}
+
+impl TipoTransacao {
+    pub fn id_estudante(&self) -> Option<u32> {
+        match self {
+            TipoTransacao::MatriculaEstudante { id_estudante, .. }
+            | TipoTransacao::RegistroPeriodo { id_estudante, .. }
+            | TipoTransacao::MatriculaDisciplina { id_estudante, .. }
+            | TipoTransacao::LancamentoNota { id_estudante, .. }
+            | TipoTransacao::CorrecaoNota { id_estudante, .. }
+            | TipoTransacao::RetratacaoNota { id_estudante, .. }
+            | TipoTransacao::LancamentoFrequencia { id_estudante, .. }
+            | TipoTransacao::EncerramentoDisciplina { id_estudante, .. } => Some(*id_estudante),
+            TipoTransacao::AlteracaoAutoridades { .. } | TipoTransacao::SuspensaoAutoridade { .. } => None,
+        }
+    }
+
+    pub fn id_periodo(&self) -> Option<u32> {
+        match self {
+            TipoTransacao::RegistroPeriodo { id_periodo, .. }
+            | TipoTransacao::MatriculaDisciplina { id_periodo, .. }
+            | TipoTransacao::LancamentoNota { id_periodo, .. }
+            | TipoTransacao::CorrecaoNota { id_periodo, .. }
+            | TipoTransacao::RetratacaoNota { id_periodo, .. }
+            | TipoTransacao::LancamentoFrequencia { id_periodo, .. }
+            | TipoTransacao::EncerramentoDisciplina { id_periodo, .. } => Some(*id_periodo),
+            _ => None,
+        }
+    }
+
+    pub fn codigo_disciplina(&self) -> Option<&str> {
+        match self {
+            TipoTransacao::MatriculaDisciplina { codigo, .. } => Some(codigo),
+            TipoTransacao::LancamentoNota { codigo_disciplina, .. }
+            | TipoTransacao::CorrecaoNota { codigo_disciplina, .. }
+            | TipoTransacao::RetratacaoNota { codigo_disciplina, .. }
+            | TipoTransacao::LancamentoFrequencia { codigo_disciplina, .. }
+            | TipoTransacao::EncerramentoDisciplina { codigo_disciplina, .. } => Some(codigo_disciplina),
+            _ => None,
+        }
+    }
+
+    pub fn nome(&self) -> &'static str {
+        match self {
+            TipoTransacao::MatriculaEstudante { .. } => "matrícula de estudante",
+            TipoTransacao::RegistroPeriodo { .. } => "registro de período",
+            TipoTransacao::MatriculaDisciplina { .. } => "matrícula em disciplina",
+            TipoTransacao::LancamentoNota { .. } => "lançamento de nota",
+            TipoTransacao::CorrecaoNota { .. } => "correção de nota",
+            TipoTransacao::RetratacaoNota { .. } => "retratação de nota",
+            TipoTransacao::LancamentoFrequencia { .. } => "lançamento de frequência",
+            TipoTransacao::EncerramentoDisciplina { .. } => "encerramento de disciplina",
+            TipoTransacao::AlteracaoAutoridades { .. } => "alteração de autoridades",
+            TipoTransacao::SuspensaoAutoridade { .. } => "suspensão de autoridade",
+        }
+    }
+}
+
+fn exigir_texto(campo: &'static str, valor: &str) -> Result<(), TransacaoErro> {
+    if valor.trim().is_empty() {
+        return Err(TransacaoErro::CampoVazio(campo));
+    }
+    Ok(())
+}
+
+fn exigir_numero(campo: &'static str, valor: f32) -> Result<(), TransacaoErro> {
+    if !valor.is_finite() {
+        return Err(TransacaoErro::ValorNaoNumerico(campo));
+    }
+    Ok(())
 }
 
 impl Transacao {
-    pub fn nova_transacao(
-        id_transacao: u32,
-        estudante: Estudante,
-        periodo_letivo: PeriodoLetivo,
-    ) -> Self {
+    pub fn nova_transacao(id_transacao: u32, tipo: TipoTransacao) -> Self {
         let timestamp = Utc::now();
 
         Transacao {
             id_transacao,
-            estudante,
-            periodo_letivo,
             timestamp,
+            tipo,
+            id_emissor: 0,
+            assinatura: String::new(),
         }
     }
+
+    /// Hash do que o emissor assina: a transação inteira, exceto a própria
+    /// assinatura.
+    pub fn hash_conteudo(&self) -> String {
+        codificacao::hash_hex(&conteudo_assinado(self))
+    }
+
+    /// Define o emissor e assina o conteúdo resultante.
+    pub fn assinar(&mut self, chave_privada: &RsaPrivateKey, id_emissor: u32) {
+        self.id_emissor = id_emissor;
+        let hash_bytes = de_hex(&self.hash_conteudo()).unwrap();
+
+        let signing_key = SigningKey::<Sha256>::new(chave_privada.clone());
+
+        let assinatura = signing_key.sign(&hash_bytes);
+        self.assinatura = encode(assinatura.as_ref());
+    }
+
+    /// Verifica a assinatura com a chave do emissor registrada na
+    /// especificação do gênesis e se o papel dele permite emitir esta
+    /// transação.
+    pub fn verificar_assinatura(&self, config: &Config) -> Result<(), TransacaoErro> {
+        let emissor = config
+            .genesis
+            .obter_emissor(self.id_emissor)
+            .ok_or(TransacaoErro::EmissorDesconhecido(self.id_emissor))?;
+
+        let hash_bytes = de_hex(&self.hash_conteudo()).ok_or(TransacaoErro::AssinaturaInvalida)?;
+        let assinatura_bytes = decode(&self.assinatura).map_err(|_| TransacaoErro::AssinaturaInvalida)?;
+        let assinatura = RsaSignature::from(assinatura_bytes.into_boxed_slice());
+
+        let verifying_key = VerifyingKey::<Sha256>::new(emissor.chave_publica.clone());
+
+        verifying_key
+            .verify(&hash_bytes, &assinatura)
+            .map_err(|_| TransacaoErro::AssinaturaInvalida)?;
+
+        emissor.autorizar(self.id_emissor, &self.tipo)
+    }
+
+    /// SHA-256 da codificação canônica; identifica a transação no pool e
+    /// nas folhas da árvore de Merkle.
+    pub fn hash(&self) -> String {
+        codificacao::hash_hex(&codificacao::codificar(self))
+    }
+
+    /// Validação própria de cada tipo, que não depende do estado da cadeia.
+    pub fn validar_estrutura(&self) -> Result<(), TransacaoErro> {
+        match &self.tipo {
+            TipoTransacao::MatriculaEstudante { nome, .. } => exigir_texto("nome", nome),
+            TipoTransacao::RegistroPeriodo { .. } => Ok(()),
+            TipoTransacao::MatriculaDisciplina { nome, codigo, .. } => {
+                exigir_texto("nome", nome)?;
+                exigir_texto("codigo", codigo)
+            }
+            TipoTransacao::LancamentoNota { codigo_disciplina, nota, .. } => {
+                exigir_texto("codigo_disciplina", codigo_disciplina)?;
+                exigir_texto("tipo", &nota.tipo)?;
+                exigir_numero("valor", nota.valor)
+            }
+            TipoTransacao::CorrecaoNota { codigo_disciplina, nota, motivo, .. } => {
+                exigir_texto("codigo_disciplina", codigo_disciplina)?;
+                exigir_texto("tipo", &nota.tipo)?;
+                exigir_numero("valor", nota.valor)?;
+                exigir_texto("motivo", motivo)
+            }
+            TipoTransacao::RetratacaoNota { codigo_disciplina, motivo, .. } => {
+                exigir_texto("codigo_disciplina", codigo_disciplina)?;
+                exigir_texto("motivo", motivo)
+            }
+            TipoTransacao::LancamentoFrequencia { codigo_disciplina, .. } => {
+                exigir_texto("codigo_disciplina", codigo_disciplina)
+            }
+            TipoTransacao::EncerramentoDisciplina { codigo_disciplina, media, frequencia, situacao, .. } => {
+                exigir_texto("codigo_disciplina", codigo_disciplina)?;
+                exigir_numero("media", *media)?;
+                exigir_numero("frequencia", *frequencia)?;
+                if *situacao == SituacaoDisciplina::Cursando {
+                    return Err(TransacaoErro::EncerramentoEmCurso(codigo_disciplina.clone()));
+                }
+                Ok(())
+            }
+            TipoTransacao::AlteracaoAutoridades { chave_publica_pem, .. } => match chave_publica_pem {
+                Some(pem) => RsaPublicKey::from_public_key_pem(pem)
+                    .map(|_| ())
+                    .map_err(|_| TransacaoErro::ChavePublicaInvalida),
+                None => Ok(()),
+            },
+            // A prova depende das chaves do `Config` e é verificada ao aplicar
+            TipoTransacao::SuspensaoAutoridade { .. } => Ok(()),
+        }
+    }
+}
+
+/// Cada tipo de transação é identificado por uma etiqueta (u8) gravada antes
+/// dos seus campos. Etiquetas nunca são reaproveitadas.
+impl CodificacaoCanonica for TipoTransacao {
+    fn codificar(&self, saida: &mut Codificador) {
+        match self {
+            TipoTransacao::MatriculaEstudante { id_estudante, nome, data_nascimento } => {
+                saida.u8(0).u32(*id_estudante).texto(nome).data(data_nascimento);
+            }
+            TipoTransacao::RegistroPeriodo { id_estudante, id_periodo, ano, semestre } => {
+                saida.u8(1).u32(*id_estudante).u32(*id_periodo).u32(*ano).u8(*semestre);
+            }
+            TipoTransacao::MatriculaDisciplina { id_estudante, id_periodo, id_disciplina, nome, codigo } => {
+                saida
+                    .u8(2)
+                    .u32(*id_estudante)
+                    .u32(*id_periodo)
+                    .u32(*id_disciplina)
+                    .texto(nome)
+                    .texto(codigo);
+            }
+            TipoTransacao::LancamentoNota { id_estudante, id_periodo, codigo_disciplina, nota } => {
+                saida
+                    .u8(3)
+                    .u32(*id_estudante)
+                    .u32(*id_periodo)
+                    .texto(codigo_disciplina)
+                    .valor(nota);
+            }
+            TipoTransacao::CorrecaoNota {
+                id_estudante,
+                id_periodo,
+                codigo_disciplina,
+                id_transacao_corrigida,
+                nota,
+                motivo,
+            } => {
+                saida
+                    .u8(4)
+                    .u32(*id_estudante)
+                    .u32(*id_periodo)
+                    .texto(codigo_disciplina)
+                    .u32(*id_transacao_corrigida)
+                    .valor(nota)
+                    .texto(motivo);
+            }
+            TipoTransacao::LancamentoFrequencia { id_estudante, id_periodo, codigo_disciplina, aula } => {
+                saida
+                    .u8(5)
+                    .u32(*id_estudante)
+                    .u32(*id_periodo)
+                    .texto(codigo_disciplina)
+                    .valor(aula);
+            }
+            TipoTransacao::EncerramentoDisciplina {
+                id_estudante,
+                id_periodo,
+                codigo_disciplina,
+                media,
+                frequencia,
+                situacao,
+            } => {
+                saida
+                    .u8(6)
+                    .u32(*id_estudante)
+                    .u32(*id_periodo)
+                    .texto(codigo_disciplina)
+                    .f32(*media)
+                    .f32(*frequencia)
+                    .u8(situacao.codigo());
+            }
+            TipoTransacao::AlteracaoAutoridades { id_autoridade, chave_publica_pem, altura_vigencia, assinaturas } => {
+                saida.u8(7).u32(*id_autoridade);
+                match chave_publica_pem {
+                    Some(pem) => saida.u8(1).texto(pem),
+                    None => saida.u8(0),
+                };
+                saida.u32(*altura_vigencia).lista(assinaturas);
+            }
+            TipoTransacao::RetratacaoNota {
+                id_estudante,
+                id_periodo,
+                codigo_disciplina,
+                id_transacao_corrigida,
+                id_nota,
+                motivo,
+            } => {
+                saida
+                    .u8(8)
+                    .u32(*id_estudante)
+                    .u32(*id_periodo)
+                    .texto(codigo_disciplina)
+                    .u32(*id_transacao_corrigida)
+                    .u32(*id_nota)
+                    .texto(motivo);
+            }
+            TipoTransacao::SuspensaoAutoridade { prova } => {
+                saida.u8(9).valor(prova);
+            }
+        }
+    }
+}
+
+impl CodificacaoCanonica for Transacao {
+    fn codificar(&self, saida: &mut Codificador) {
+        codificar_conteudo(self, saida);
+        saida.texto(&self.assinatura);
+    }
+}
+
+fn codificar_conteudo(transacao: &Transacao, saida: &mut Codificador) {
+    saida
+        .u32(transacao.id_transacao)
+        .valor(&transacao.tipo)
+        .data_hora(&transacao.timestamp)
+        .u32(transacao.id_emissor);
+}
+
+/// Bytes canônicos assinados pelo emissor da transação: tudo menos a
+/// própria assinatura.
+fn conteudo_assinado(transacao: &Transacao) -> Vec<u8> {
+    let mut codificador = Codificador::novo();
+    codificar_conteudo(transacao, &mut codificador);
+    codificador.finalizar()
 }
@@ -0,0 +1,135 @@
+use chrono::{Datelike, NaiveDate};
+
+use crate::utils::erros::TransacaoErro;
+
+use super::codificacao::{CodificacaoCanonica, Codificador};
+use super::transacao::TipoTransacao;
+use super::{Nota, Transacao};
+
+/// Limites semânticos aplicados a toda transação, tanto ao entrar no pool
+/// quanto ao importar um bloco. Todos os nós precisam usar os mesmos
+/// limites, já que um bloco aceito por um nó e recusado por outro divide a
+/// rede.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegrasValidacao {
+    /// Escala das notas, inclusive nos extremos.
+    pub nota_minima: f32,
+    pub nota_maxima: f32,
+    /// Quantos períodos letivos há por ano; `semestre` vai de 1 até este
+    /// valor e o ano é dividido igualmente entre eles.
+    pub semestres_por_ano: u8,
+    /// Menor ano aceito para nascimentos e períodos letivos.
+    pub ano_minimo: i32,
+    /// Dias depois do fim do período em que ainda se aceitam notas e aulas
+    /// com data dentro dele.
+    pub tolerancia_periodo_dias: i64,
+}
+
+impl Default for RegrasValidacao {
+    fn default() -> Self {
+        RegrasValidacao {
+            nota_minima: 0.0,
+            nota_maxima: 10.0,
+            semestres_por_ano: 2,
+            ano_minimo: 1900,
+            tolerancia_periodo_dias: 0,
+        }
+    }
+}
+
+impl RegrasValidacao {
+    /// Limites que dependem apenas da própria transação. Datas são comparadas
+    /// com o `timestamp` da transação, e não com o relógio local, para que a
+    /// validação dê o mesmo resultado quando a cadeia for reaplicada; o
+    /// próprio timestamp é limitado pelo do bloco que inclui a transação
+    /// (`ParametrosCadeia::verificar_timestamp_transacao`).
+    pub fn verificar(&self, transacao: &Transacao) -> Result<(), TransacaoErro> {
+        let hoje = transacao.timestamp.date_naive();
+
+        match &transacao.tipo {
+            TipoTransacao::MatriculaEstudante { data_nascimento, .. }
+                if data_nascimento.year() < self.ano_minimo || *data_nascimento > hoje =>
+            {
+                return Err(TransacaoErro::DataNascimentoInvalida(*data_nascimento));
+            }
+            TipoTransacao::RegistroPeriodo { ano, semestre, .. } => {
+                // Períodos podem ser registrados com até um ano de antecedência
+                if (*ano as i32) < self.ano_minimo || *ano as i32 > hoje.year() + 1 {
+                    return Err(TransacaoErro::AnoInvalido(*ano));
+                }
+                if *semestre == 0 || *semestre > self.semestres_por_ano {
+                    return Err(TransacaoErro::SemestreInvalido {
+                        semestre: *semestre,
+                        maximo: self.semestres_por_ano,
+                    });
+                }
+            }
+            TipoTransacao::LancamentoNota { nota, .. } | TipoTransacao::CorrecaoNota { nota, .. } => {
+                self.verificar_nota(nota)?;
+                exigir_passada(nota.data, hoje)?;
+            }
+            TipoTransacao::LancamentoFrequencia { aula, .. } => exigir_passada(aula.data, hoje)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn verificar_nota(&self, nota: &Nota) -> Result<(), TransacaoErro> {
+        if nota.valor < self.nota_minima || nota.valor > self.nota_maxima {
+            return Err(TransacaoErro::NotaForaDaEscala {
+                valor: nota.valor,
+                minima: self.nota_minima,
+                maxima: self.nota_maxima,
+            });
+        }
+        Ok(())
+    }
+
+    /// Primeiro e último dia do período letivo.
+    pub fn intervalo_periodo(&self, ano: u32, semestre: u8) -> Option<(NaiveDate, NaiveDate)> {
+        let semestres = self.semestres_por_ano as u32;
+        let semestre = semestre as u32;
+        if semestre == 0 || semestre > semestres {
+            return None;
+        }
+        let mes_inicial = (semestre - 1) * 12 / semestres + 1;
+        let mes_final = semestre * 12 / semestres;
+        let inicio = NaiveDate::from_ymd_opt(ano as i32, mes_inicial, 1)?;
+        let fim = if mes_final == 12 {
+            NaiveDate::from_ymd_opt(ano as i32 + 1, 1, 1)?
+        } else {
+            NaiveDate::from_ymd_opt(ano as i32, mes_final + 1, 1)?
+        }
+        .pred_opt()?;
+        Some((inicio, fim))
+    }
+
+    /// Confere se uma nota ou aula datada de `data` pertence ao período.
+    pub fn verificar_data_no_periodo(&self, data: NaiveDate, ano: u32, semestre: u8) -> Result<(), TransacaoErro> {
+        let dentro = self.intervalo_periodo(ano, semestre).is_some_and(|(inicio, fim)| {
+            data >= inicio && data <= fim + chrono::Duration::days(self.tolerancia_periodo_dias)
+        });
+        if !dentro {
+            return Err(TransacaoErro::DataForaDoPeriodo { data, ano, semestre });
+        }
+        Ok(())
+    }
+}
+
+fn exigir_passada(data: NaiveDate, hoje: NaiveDate) -> Result<(), TransacaoErro> {
+    if data > hoje {
+        return Err(TransacaoErro::DataFutura(data));
+    }
+    Ok(())
+}
+
+impl CodificacaoCanonica for RegrasValidacao {
+    fn codificar(&self, saida: &mut Codificador) {
+        saida
+            .f32(self.nota_minima)
+            .f32(self.nota_maxima)
+            .u8(self.semestres_por_ano)
+            .i32(self.ano_minimo)
+            .i64(self.tolerancia_periodo_dias);
+    }
+}
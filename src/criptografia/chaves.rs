@@ -1,13 +1,20 @@
-
 use rsa::RsaPrivateKey;
 use rsa::pkcs8::DecodePrivateKey;
 use std::fs;
 
 pub fn carregar_chave_privada(id_autoridade: u32) -> RsaPrivateKey {
-    let caminho = format!("chaves_privadas/autoridade_{}.pem", id_autoridade);
-    let chave_privada_pem = fs::read_to_string(&caminho)
-        .unwrap_or_else(|err| panic!("Falha ao ler a chave privada da autoridade no caminho '{}': {}", caminho, err));
+    carregar_chave(&format!("chaves_privadas/autoridade_{}.pem", id_autoridade), "da autoridade")
+}
+
+/// Chave com que professores e secretaria assinam as transações que emitem.
+pub fn carregar_chave_emissor(id_emissor: u32) -> RsaPrivateKey {
+    carregar_chave(&format!("chaves_privadas/emissor_{}.pem", id_emissor), "do emissor")
+}
+
+fn carregar_chave(caminho: &str, dono: &str) -> RsaPrivateKey {
+    let chave_privada_pem = fs::read_to_string(caminho)
+        .unwrap_or_else(|err| panic!("Falha ao ler a chave privada {} no caminho '{}': {}", dono, caminho, err));
 
     RsaPrivateKey::from_pkcs8_pem(&chave_privada_pem)
-        .expect("Falha ao parsear a chave privada da autoridade")
+        .unwrap_or_else(|_| panic!("Falha ao parsear a chave privada {}", dono))
 }
@@ -2,249 +2,728 @@ mod blockchain;
 mod rede;
 mod criptografia;
 mod utils;
+mod armazenamento;
 
-use blockchain::{Blockchain, Estudante, PeriodoLetivo, Transacao, Disciplina, Nota};
-use rede::{iniciar_rede, P2PEvent};
+use blockchain::{espera_para_produzir, hash_alteracao, AssinaturaAutoridade, Blockchain, Bloco, DetectorEquivocacao, InstantaneoAssinado, Mempool, MotorFinalidade, Progresso, ProvaEquivocacao, Transacao, TipoTransacao, Nota, ProvaInclusao, RegistroAula, RegistroIndexado, SituacaoDisciplina};
+use rede::{iniciar_rede, GerenciadorSincronizacao, P2PEvent};
+use rede::p2p::P2PSwarm;
+use std::path::Path;
 use std::sync::Arc;
+use chrono::NaiveDate;
+use rsa::RsaPrivateKey;
 use tokio::sync::Mutex;
-use criptografia::chaves::carregar_chave_privada;
+use criptografia::chaves::{carregar_chave_emissor, carregar_chave_privada};
 use tokio::io::{self, AsyncBufReadExt};
+use utils::erros::{BlocoErro, FinalidadeErro};
+use armazenamento::ArmazenamentoBlocos;
+use utils::config::Config;
 
 #[tokio::main]
 async fn main() {
     let config = utils::config::Config::carregar_configuracao();
-    println!("Chaves públicas carregadas: {:?}", config.chaves_publicas.keys());
+    println!("Chaves públicas carregadas: {:?}", config.genesis.autoridades.keys());
 
-    // Determina se o nó é autoridade com base no argumento de linha de comando
-    let is_autoridade = std::env::args().any(|arg| arg == "--autoridade");
-    let id_autoridade = if is_autoridade { 1 } else { 0 };
-
-    // Carrega a chave privada somente se for autoridade
-    let chave_privada_autoridade = if is_autoridade {
-        Some(carregar_chave_privada(id_autoridade))
-    } else {
-        None
+    // Autoridades informam `--autoridade <id>` para produzir blocos, e
+    // professores e secretaria `--emissor <id>` para assinar as transações
+    // que criam
+    let argumentos: Vec<String> = std::env::args().collect();
+    let ler_id = |opcao| {
+        ler_id_argumento(&argumentos, opcao).unwrap_or_else(|uso| {
+            println!("{}", uso);
+            std::process::exit(2)
+        })
     };
+    let chave_autoridade = ler_id("--autoridade")
+        .map(|id_autoridade| (id_autoridade, carregar_chave_privada(id_autoridade)));
+    let chave_emissor = ler_id("--emissor")
+        .map(|id_emissor| (id_emissor, carregar_chave_emissor(id_emissor)));
 
-    // Carrega ou inicializa a blockchain
-    let blockchain = match Blockchain::carregar_do_disco("blockchain.json") {
+    // Abre o armazenamento de blocos, migrando um eventual blockchain.json
+    let mut armazenamento = ArmazenamentoBlocos::abrir(config.armazenamento.clone())
+        .expect("Não foi possível abrir o armazenamento de blocos");
+    match armazenamento.migrar_json("blockchain.json", &config) {
+        Ok(true) => println!("blockchain.json migrado para o armazenamento de blocos."),
+        Ok(false) => {}
+        Err(e) => println!("Erro ao migrar blockchain.json: {:?}.", e),
+    }
+
+    // Carrega a blockchain, ou a inicializa a partir do instantâneo
+    // configurado ou do gênesis
+    let blockchain = match Blockchain::carregar_do_armazenamento(&armazenamento, &config) {
         Ok(bc) => Arc::new(Mutex::new(bc)),
         Err(e) => {
             println!("Erro ao carregar a blockchain local: {:?}.", e);
-            println!("Inicializando uma blockchain vazia e solicitando atualização da rede...");
-            Arc::new(Mutex::new(Blockchain::nova_blockchain()))
+            let bc = iniciar_do_instantaneo(&mut armazenamento, &config).unwrap_or_else(|| {
+                println!("Inicializando uma blockchain vazia e solicitando atualização da rede...");
+                let bc = Blockchain::nova_blockchain(&config.genesis);
+                if let Err(e) = armazenamento.sincronizar_com(&bc.cadeia) {
+                    println!("Erro ao salvar a blockchain: {:?}", e);
+                }
+                bc
+            });
+            Arc::new(Mutex::new(bc))
         }
     };
 
-    let mut p2p_swarm = iniciar_rede(blockchain.clone()).await;
+    // Recupera as transações pendentes da execução anterior
+    let caminho_mempool = config.armazenamento.diretorio.join("mempool.json");
+    match Mempool::ler_entradas(&caminho_mempool) {
+        Ok(entradas) if !entradas.is_empty() => {
+            let gravadas = entradas.len();
+            let mut bc = blockchain.lock().await;
+            let recuperadas = bc.restaurar_mempool(entradas, &config);
+            println!("{} de {} transação(ões) pendente(s) recuperada(s).", recuperadas, gravadas);
+            salvar_mempool(&bc, &caminho_mempool);
+        }
+        Ok(_) => {}
+        Err(e) => println!("Erro ao ler as transações pendentes: {:?}", e),
+    }
+
+    // A rede é identificada pelo gênesis da especificação, que um nó
+    // iniciado por instantâneo não guarda; peers com outro gênesis são
+    // recusados
+    let hash_genesis = Blockchain::hash_genesis(&config.genesis);
+    println!(
+        "Cadeia {} ({}), gênesis {}.",
+        config.genesis.id_cadeia, config.genesis.instituicao, hash_genesis
+    );
+    let mut p2p_swarm = iniciar_rede(blockchain.clone(), hash_genesis.clone()).await;
 
-    // Se a blockchain foi inicializada vazia, solicita atualização da rede
+    // Sincronização: a situação dos peers é trocada ao conectar e
+    // periodicamente, e os blocos que faltam são baixados por faixas
+    let mut sincronizacao = GerenciadorSincronizacao::novo(hash_genesis);
+    let mut relogio_sincronizacao = tokio::time::interval(config.sincronizacao.intervalo_status.to_std().unwrap());
+    relogio_sincronizacao.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    // Votação de finalidade: autoridades votam na ponta da cadeia, e todos
+    // os nós acompanham os votos e guardam os certificados
+    let mut motor_finalidade = MotorFinalidade::novo(chave_autoridade.clone());
+    let mut relogio_finalidade = tokio::time::interval(std::time::Duration::from_secs(1));
     {
-        let bc = blockchain.lock().await;
-        if bc.cadeia.len() <= 1 {
-            p2p_swarm.solicitar_blockchain();
+        let mut bc = blockchain.lock().await;
+        // Votos e travas da execução anterior valem antes de qualquer voto novo
+        match armazenamento.carregar_votos() {
+            Ok(votos) => motor_finalidade.restaurar_votos(votos, &bc, chrono::Utc::now()),
+            Err(e) => {
+                // Sem os votos anteriores, votar poderia contradizê-los
+                println!("Erro ao carregar os votos de finalidade; o nó apenas acompanhará a votação: {:?}", e);
+                motor_finalidade = MotorFinalidade::novo(None);
+            }
         }
+        let progresso = motor_finalidade.ao_mudar_ponta(&bc, &config, chrono::Utc::now());
+        aplicar_progresso(progresso, &mut bc, &mut motor_finalidade, &mut armazenamento, &mut p2p_swarm, &config);
     }
 
+    // Provas de que uma autoridade assinou blocos ou votos conflitantes
+    let mut detector_equivocacao = DetectorEquivocacao::default();
+
+    // Produção automática: a cada intervalo, a autoridade tenta produzir o
+    // bloco seguinte; fora da sua vez, ou sem transações quando os blocos
+    // vazios são pulados, apenas espera o próximo intervalo
+    let producao_automatica = chave_autoridade.is_some() && config.producao.intervalo > chrono::Duration::zero();
+    let mut relogio_producao = tokio::time::interval(
+        config.producao.intervalo.max(chrono::Duration::seconds(1)).to_std().unwrap(),
+    );
+    relogio_producao.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
     let stdin = io::BufReader::new(tokio::io::stdin());
     let mut stdin_lines = stdin.lines();
 
     println!("Digite o comando (ex: 'transacao', 'criar_bloco' ou 'exibir_blockchain'):");
 
+    // Um único futuro para o Ctrl+C, criado fora do laço para que o sinal
+    // não se perca entre duas iterações
+    let desligamento = tokio::signal::ctrl_c();
+    tokio::pin!(desligamento);
+
     loop {
         tokio::select! {
             event = p2p_swarm.next_event() => {
                 if let Some(event) = event {
                     match event {
-                        P2PEvent::NovoBloco(bloco_recebido) => {
+                        P2PEvent::NovoBloco { peer, bloco: bloco_recebido } => {
                             println!("Bloco recebido: {:?}", bloco_recebido);
                             let mut bc = blockchain.lock().await;
-                            if let Err(e) = bc.adicionar_bloco_externo(bloco_recebido, &config) {
+                            observar_bloco(&mut detector_equivocacao, &bloco_recebido, &bc, &mut p2p_swarm, &config);
+                            if let Err(e) = bc.adicionar_bloco_externo(bloco_recebido.clone(), &config) {
                                 println!("Erro ao adicionar bloco externo: {:?}", e);
+                                // Um bloco que não se encadeia indica blocos faltando
+                                // ou uma bifurcação: o gerenciador busca o que falta.
+                                if matches!(e, BlocoErro::HashAnteriorNaoCorresponde | BlocoErro::IndiceInvalido { .. } | BlocoErro::AlturaIncorreta { .. }) {
+                                    sincronizacao.ao_receber_orfao(peer, bloco_recebido, &bc, &mut p2p_swarm);
+                                }
                             } else {
                                 // Salva a blockchain após adicionar o bloco
-                                if let Err(e) = bc.salvar_em_disco("blockchain.json") {
+                                if let Err(e) = armazenamento.anexar_novos(&bc.cadeia) {
                                     println!("Erro ao salvar a blockchain: {:?}", e);
                                 }
+                                salvar_mempool(&bc, &caminho_mempool);
+                                let progresso = motor_finalidade.ao_mudar_ponta(&bc, &config, chrono::Utc::now());
+                                aplicar_progresso(progresso, &mut bc, &mut motor_finalidade, &mut armazenamento, &mut p2p_swarm, &config);
+                            }
+                        }
+                        P2PEvent::VotoRecebido(voto) => {
+                            let mut bc = blockchain.lock().await;
+                            match motor_finalidade.ao_receber_voto(voto, &bc, &config) {
+                                Ok(mut progresso) => {
+                                    if let Some(prova) = progresso.evidencia.take() {
+                                        if detector_equivocacao.registrar(prova.clone()) {
+                                            println!("Autoridade {} votou em blocos diferentes na altura {}.", prova.id_autoridade(), prova.altura());
+                                            p2p_swarm.difundir_equivocacao(&prova);
+                                        }
+                                    }
+                                    aplicar_progresso(progresso, &mut bc, &mut motor_finalidade, &mut armazenamento, &mut p2p_swarm, &config);
+                                }
+                                Err(e) => println!("Voto rejeitado: {}", e),
+                            }
+                        }
+                        P2PEvent::CertificadoRecebido(certificado) => {
+                            let mut bc = blockchain.lock().await;
+                            let progresso = Progresso { certificado: Some(certificado), ..Default::default() };
+                            aplicar_progresso(progresso, &mut bc, &mut motor_finalidade, &mut armazenamento, &mut p2p_swarm, &config);
+                        }
+                        P2PEvent::EquivocacaoRecebida(prova) => {
+                            let bc = blockchain.lock().await;
+                            match prova.verificar(&bc.autoridades_em(prova.altura(), &config), &config) {
+                                Ok(id_autoridade) => {
+                                    if detector_equivocacao.registrar(prova) {
+                                        println!("Prova de equivocação da autoridade {} recebida; veja 'equivocacoes'.", id_autoridade);
+                                    }
+                                }
+                                Err(e) => println!("Prova de equivocação rejeitada: {}", e),
                             }
                         }
                         P2PEvent::NovaTransacao(transacao_recebida) => {
                             println!("Transação recebida: {:?}", transacao_recebida);
                             let mut bc = blockchain.lock().await;
-                            bc.adicionar_transacao(transacao_recebida);
+                            match bc.adicionar_transacao(transacao_recebida, &config) {
+                                Ok(despejada) => {
+                                    if let Some(despejada) = despejada {
+                                        println!("Pool cheio: transação {} descartada.", despejada.id_transacao);
+                                    }
+                                    salvar_mempool(&bc, &caminho_mempool);
+                                }
+                                Err(e) => println!("Transação recebida rejeitada: {}", e),
+                            }
                         }
-                        P2PEvent::BlockchainSolicitada { peer, channel } => {
-                            println!("Nó {} solicitou a blockchain.", peer);
+                        P2PEvent::PeerConectado(peer) => {
                             let bc = blockchain.lock().await;
-                            p2p_swarm.enviar_blockchain(&bc.cadeia, channel);
+                            sincronizacao.ao_conectar(peer, &bc, &mut p2p_swarm);
                         }
-                        P2PEvent::BlockchainRecebida(cadeia_recebida) => {
-                            println!("Blockchain recebida da rede.");
+                        P2PEvent::PeerDesconectado(peer) => sincronizacao.ao_desconectar(peer),
+                        P2PEvent::SolicitacaoRecebida { peer, solicitacao, channel } => {
+                            let bc = blockchain.lock().await;
+                            let resposta = sincronizacao.ao_receber_solicitacao(peer, solicitacao, &bc, &mut p2p_swarm, &config);
+                            p2p_swarm.responder(channel, resposta);
+                        }
+                        P2PEvent::RespostaRecebida { peer, id, resposta } => {
                             let mut bc = blockchain.lock().await;
-                            if bc.cadeia.len() < cadeia_recebida.len() {
-                                bc.cadeia = cadeia_recebida;
-                                // Salva a blockchain após receber
-                                if let Err(e) = bc.salvar_em_disco("blockchain.json") {
-                                    println!("Erro ao salvar a blockchain: {:?}", e);
+                            let ramo = sincronizacao.ao_receber_resposta(peer, id, resposta, &bc, &mut p2p_swarm, &config);
+                            if !ramo.is_empty() {
+                                for bloco in &ramo {
+                                    observar_bloco(&mut detector_equivocacao, bloco, &bc, &mut p2p_swarm, &config);
                                 }
-                            } else {
-                                println!("A blockchain local já está atualizada.");
+                                if !aplicar_ramo(ramo, &mut bc, &mut armazenamento, &mut p2p_swarm, &mut motor_finalidade, &caminho_mempool, &config) {
+                                    sincronizacao.ao_rejeitar_ramo();
+                                }
+                                sincronizacao.avancar(&bc, &mut p2p_swarm, &config);
                             }
                         }
+                        P2PEvent::SolicitacaoFalhou { peer, id } => {
+                            let bc = blockchain.lock().await;
+                            sincronizacao.ao_falhar(peer, id, &bc, &mut p2p_swarm, &config);
+                        }
+                        P2PEvent::GenesisDiferente { peer, hash_genesis } => {
+                            println!("Nó {} pertence a outra rede (gênesis {}); sincronização recusada.", peer, hash_genesis);
+                        }
                     }
                 }
             }
+            _ = relogio_sincronizacao.tick() => {
+                let bc = blockchain.lock().await;
+                sincronizacao.consultar_peers(&bc, &mut p2p_swarm);
+            }
+            _ = relogio_finalidade.tick() => {
+                let mut bc = blockchain.lock().await;
+                let progresso = motor_finalidade.ao_expirar(&bc, &config, chrono::Utc::now());
+                aplicar_progresso(progresso, &mut bc, &mut motor_finalidade, &mut armazenamento, &mut p2p_swarm, &config);
+            }
+            _ = relogio_producao.tick(), if producao_automatica => {
+                let chave = chave_autoridade.as_ref().unwrap();
+                let mut bc = blockchain.lock().await;
+                let resultado = produzir_bloco(&mut bc, chave, !config.producao.pular_blocos_vazios, &mut armazenamento, &mut p2p_swarm, &mut motor_finalidade, &config);
+                salvar_mempool(&bc, &caminho_mempool);
+                match resultado {
+                    Ok(bloco) => println!("Bloco {} produzido automaticamente com {} transação(ões).", bloco.cabecalho.indice, bloco.transacoes.len()),
+                    Err(BlocoErro::ForaDoTurno { .. } | BlocoErro::BlocoVazio) => {}
+                    Err(e) => println!("Produção automática de bloco falhou: {}", e),
+                }
+            }
+            _ = &mut desligamento => break,
             Ok(Some(line)) = stdin_lines.next_line() => {
                 let command = line.trim().to_string();
                 match command.as_str() {
                     "transacao" => {
-                        // Leitura dos dados da transação
+                        let Some((id_emissor, chave_privada_emissor)) = &chave_emissor else {
+                            println!("Este nó não tem chave de emissor; inicie-o com --emissor <id>.");
+                            continue;
+                        };
                         println!("Digite o ID da transação:");
                         let id_transacao = ler_u32_async(&mut stdin_lines).await;
-                        println!("Digite o ID do estudante:");
-                        let id_estudante = ler_u32_async(&mut stdin_lines).await;
-                        println!("Digite o nome do estudante:");
-                        let nome_estudante = ler_string_async(&mut stdin_lines).await;
-                        println!("Digite o ano de nascimento do estudante:");
-                        let ano_nascimento = ler_i32_async(&mut stdin_lines).await;
-                        println!("Digite o mês de nascimento do estudante:");
-                        let mes_nascimento = ler_u32_async(&mut stdin_lines).await;
-                        println!("Digite o dia de nascimento do estudante:");
-                        let dia_nascimento = ler_u32_async(&mut stdin_lines).await;
-                        println!("Digite o ano do período letivo:");
-                        let ano_periodo = ler_u32_async(&mut stdin_lines).await;
-                        println!("Digite o semestre do período letivo:");
-                        let semestre = ler_u8_async(&mut stdin_lines).await;
-
-                        let mut estudante = Estudante::novo_estudante(
-                            id_estudante,
-                            &nome_estudante,
-                            ano_nascimento,
-                            mes_nascimento,
-                            dia_nascimento,
-                        );
-                        let mut periodo_letivo = PeriodoLetivo::novo_periodo(
-                            id_transacao,
-                            ano_periodo,
-                            semestre,
-                        );
-
-                        // Pergunta se o usuário deseja adicionar disciplinas
-                        println!("Deseja adicionar disciplinas ao período letivo? (s/n):");
-                        let adicionar_disciplinas = ler_string_async(&mut stdin_lines).await;
-
-                        if adicionar_disciplinas.eq_ignore_ascii_case("s") {
-                            loop {
+                        println!("Tipo da transação (estudante, periodo, disciplina, nota, correcao_nota, retratacao_nota, frequencia, encerramento, autoridades, suspensao):");
+                        let tipo = ler_string_async(&mut stdin_lines).await;
+
+                        let tipo = match tipo.as_str() {
+                            "estudante" => {
+                                println!("Digite o ID do estudante:");
+                                let id_estudante = ler_u32_async(&mut stdin_lines).await;
+                                println!("Digite o nome do estudante:");
+                                let nome = ler_string_async(&mut stdin_lines).await;
+                                let data_nascimento = ler_data_async(&mut stdin_lines, "de nascimento do estudante").await;
+                                TipoTransacao::MatriculaEstudante { id_estudante, nome, data_nascimento }
+                            }
+                            "periodo" => {
+                                println!("Digite o ID do estudante:");
+                                let id_estudante = ler_u32_async(&mut stdin_lines).await;
+                                println!("Digite o ID do período letivo:");
+                                let id_periodo = ler_u32_async(&mut stdin_lines).await;
+                                println!("Digite o ano do período letivo:");
+                                let ano = ler_u32_async(&mut stdin_lines).await;
+                                println!("Digite o semestre do período letivo:");
+                                let semestre = ler_u8_async(&mut stdin_lines).await;
+                                TipoTransacao::RegistroPeriodo { id_estudante, id_periodo, ano, semestre }
+                            }
+                            "disciplina" => {
+                                println!("Digite o ID do estudante:");
+                                let id_estudante = ler_u32_async(&mut stdin_lines).await;
+                                println!("Digite o ID do período letivo:");
+                                let id_periodo = ler_u32_async(&mut stdin_lines).await;
                                 println!("Digite o ID da disciplina:");
                                 let id_disciplina = ler_u32_async(&mut stdin_lines).await;
-
                                 println!("Digite o nome da disciplina:");
-                                let nome_disciplina = ler_string_async(&mut stdin_lines).await;
-
+                                let nome = ler_string_async(&mut stdin_lines).await;
                                 println!("Digite o código da disciplina:");
-                                let codigo_disciplina = ler_string_async(&mut stdin_lines).await;
-
-                                let mut disciplina = Disciplina::nova_disciplina(
-                                    id_disciplina,
-                                    &nome_disciplina,
-                                    &codigo_disciplina,
-                                );
-
-                                // Pergunta se o usuário deseja adicionar notas à disciplina
-                                println!("Deseja adicionar notas à disciplina? (s/n):");
-                                let adicionar_notas = ler_string_async(&mut stdin_lines).await;
-
-                                if adicionar_notas.eq_ignore_ascii_case("s") {
-                                    loop {
+                                let codigo = ler_string_async(&mut stdin_lines).await;
+                                TipoTransacao::MatriculaDisciplina { id_estudante, id_periodo, id_disciplina, nome, codigo }
+                            }
+                            "nota" => {
+                                let (id_estudante, id_periodo, codigo_disciplina) = ler_disciplina_async(&mut stdin_lines).await;
+                                let nota = ler_nota_async(&mut stdin_lines).await;
+                                TipoTransacao::LancamentoNota { id_estudante, id_periodo, codigo_disciplina, nota }
+                            }
+                            "correcao_nota" | "retratacao_nota" => {
+                                let (id_estudante, id_periodo, codigo_disciplina) = ler_disciplina_async(&mut stdin_lines).await;
+                                println!("Digite o ID da transação com a versão vigente da nota:");
+                                let id_transacao_corrigida = ler_u32_async(&mut stdin_lines).await;
+                                let nota = if tipo == "correcao_nota" {
+                                    Some(ler_nota_async(&mut stdin_lines).await)
+                                } else {
+                                    None
+                                };
+                                let id_nota = match &nota {
+                                    Some(nota) => nota.id_nota,
+                                    None => {
                                         println!("Digite o ID da nota:");
-                                        let id_nota = ler_u32_async(&mut stdin_lines).await;
-
-                                        println!("Digite o valor da nota:");
-                                        let valor_nota = ler_f32_async(&mut stdin_lines).await;
-
-                                        println!("Digite o tipo da nota (ex: Prova, Trabalho):");
-                                        let tipo_nota = ler_string_async(&mut stdin_lines).await;
-
-                                        println!("Digite o ano da nota:");
-                                        let ano_nota = ler_i32_async(&mut stdin_lines).await;
-
-                                        println!("Digite o mês da nota:");
-                                        let mes_nota = ler_u32_async(&mut stdin_lines).await;
-
-                                        println!("Digite o dia da nota:");
-                                        let dia_nota = ler_u32_async(&mut stdin_lines).await;
-
-                                        // Cria uma nova nota
-                                        let nota = Nota::nova_nota(
-                                            id_nota,
-                                            valor_nota,
-                                            &tipo_nota,
-                                            ano_nota,
-                                            mes_nota,
-                                            dia_nota,
-                                        );
-
-                                        // Adiciona a nota à disciplina
-                                        disciplina.adicionar_nota(nota);
-
-                                        println!("Deseja adicionar outra nota? (s/n):");
-                                        let continuar_notas = ler_string_async(&mut stdin_lines).await;
-                                        if continuar_notas.eq_ignore_ascii_case("n") {
-                                            break;
-                                        }
+                                        ler_u32_async(&mut stdin_lines).await
                                     }
+                                };
+                                println!("Digite o motivo:");
+                                let motivo = ler_string_async(&mut stdin_lines).await;
+                                match nota {
+                                    Some(nota) => TipoTransacao::CorrecaoNota {
+                                        id_estudante,
+                                        id_periodo,
+                                        codigo_disciplina,
+                                        id_transacao_corrigida,
+                                        nota,
+                                        motivo,
+                                    },
+                                    None => TipoTransacao::RetratacaoNota {
+                                        id_estudante,
+                                        id_periodo,
+                                        codigo_disciplina,
+                                        id_transacao_corrigida,
+                                        id_nota,
+                                        motivo,
+                                    },
                                 }
-
-                                // Adiciona a disciplina ao período letivo
-                                periodo_letivo.adicionar_disciplina(disciplina);
-
-                                println!("Deseja adicionar outra disciplina? (s/n):");
-                                let continuar_disciplinas = ler_string_async(&mut stdin_lines).await;
-                                if continuar_disciplinas.eq_ignore_ascii_case("n") {
-                                    break;
+                            }
+                            "frequencia" => {
+                                let (id_estudante, id_periodo, codigo_disciplina) = ler_disciplina_async(&mut stdin_lines).await;
+                                let data = ler_data_async(&mut stdin_lines, "da aula").await;
+                                println!("O estudante esteve presente? (s/n):");
+                                let presente = ler_string_async(&mut stdin_lines).await.eq_ignore_ascii_case("s");
+                                TipoTransacao::LancamentoFrequencia {
+                                    id_estudante,
+                                    id_periodo,
+                                    codigo_disciplina,
+                                    aula: RegistroAula { data, presente },
                                 }
                             }
-                        }
+                            "encerramento" => {
+                                let (id_estudante, id_periodo, codigo_disciplina) = ler_disciplina_async(&mut stdin_lines).await;
+                                println!("Situação final (encerrar, trancado, dispensado):");
+                                let situacao = ler_string_async(&mut stdin_lines).await;
 
-                        // Adiciona o período letivo ao estudante
-                        estudante.adicionar_periodo_letivo(periodo_letivo.clone());
+                                // Média e frequência saem das notas e aulas já lançadas, inclusive as pendentes
+                                let disciplina = {
+                                    let mut bc = blockchain.lock().await;
+                                    bc.estado_com_pendentes(&config)
+                                        .disciplina(id_estudante, id_periodo, &codigo_disciplina)
+                                        .cloned()
+                                };
+                                let Some(mut disciplina) = disciplina else {
+                                    println!("Disciplina não matriculada para o estudante e período informados.");
+                                    continue;
+                                };
+                                disciplina.recalcular(&config.genesis.politica_avaliacao);
+                                match situacao.as_str() {
+                                    "trancado" => disciplina.situacao = SituacaoDisciplina::Trancado,
+                                    "dispensado" => disciplina.situacao = SituacaoDisciplina::Dispensado,
+                                    _ => disciplina.encerrar(&config.genesis.politica_avaliacao),
+                                }
+                                println!(
+                                    "Média {:.2}, frequência {:.2}%: {}.",
+                                    disciplina.media, disciplina.frequencia, disciplina.situacao
+                                );
+                                TipoTransacao::EncerramentoDisciplina {
+                                    id_estudante,
+                                    id_periodo,
+                                    codigo_disciplina,
+                                    media: disciplina.media,
+                                    frequencia: disciplina.frequencia,
+                                    situacao: disciplina.situacao,
+                                }
+                            }
+                            "autoridades" => {
+                                let Some((id_autoridade, chave_publica_pem, altura_vigencia)) = ler_alteracao_async(&mut stdin_lines).await else {
+                                    continue;
+                                };
+                                println!("Cole as assinaturas geradas pelas autoridades com 'assinar_alteracao', uma por linha (linha vazia para terminar):");
+                                let mut assinaturas = Vec::new();
+                                loop {
+                                    let linha = ler_string_async(&mut stdin_lines).await;
+                                    if linha.is_empty() {
+                                        break;
+                                    }
+                                    match serde_json::from_str::<AssinaturaAutoridade>(&linha) {
+                                        Ok(assinatura) => assinaturas.push(assinatura),
+                                        Err(e) => println!("Assinatura malformada, ignorada: {}", e),
+                                    }
+                                }
+                                TipoTransacao::AlteracaoAutoridades { id_autoridade, chave_publica_pem, altura_vigencia, assinaturas }
+                            }
+                            "suspensao" => {
+                                println!("Digite o número da prova de equivocação (veja 'equivocacoes'):");
+                                let numero = ler_u32_async(&mut stdin_lines).await;
+                                let Some(prova) = detector_equivocacao.provas().get(numero as usize).cloned() else {
+                                    println!("Prova de equivocação não encontrada.");
+                                    continue;
+                                };
+                                TipoTransacao::SuspensaoAutoridade { prova }
+                            }
+                            _ => {
+                                println!("Tipo de transação desconhecido.");
+                                continue;
+                            }
+                        };
 
-                        let transacao = Transacao::nova_transacao(
-                            id_transacao,
-                            estudante,
-                            periodo_letivo,
-                        );
+                        let mut transacao = Transacao::nova_transacao(id_transacao, tipo);
+                        transacao.assinar(chave_privada_emissor, *id_emissor);
 
-                        {
+                        let resultado = {
                             let mut bc = blockchain.lock().await;
-                            bc.adicionar_transacao(transacao.clone());
-                        }
+                            let resultado = bc.adicionar_transacao(transacao.clone(), &config);
+                            if resultado.is_ok() {
+                                salvar_mempool(&bc, &caminho_mempool);
+                            }
+                            resultado
+                        };
 
-                        p2p_swarm.difundir_transacao(&transacao);
-                        println!("Transação criada e difundida.\nDigite o próximo comando:");
+                        match resultado {
+                            Ok(despejada) => {
+                                if let Some(despejada) = despejada {
+                                    println!("Pool cheio: transação {} descartada.", despejada.id_transacao);
+                                }
+                                p2p_swarm.difundir_transacao(&transacao);
+                                println!("Transação criada e difundida.\nDigite o próximo comando:");
+                            }
+                            Err(e) => println!("Transação rejeitada: {}", e),
+                        }
                     }
                     "criar_bloco" => {
-                        if let Some(chave_privada) = &chave_privada_autoridade {
+                        if let Some(chave) = &chave_autoridade {
                             println!("Criando bloco...");
-                            let novo_bloco = {
-                                let mut bc = blockchain.lock().await;
-                                let bloco = bc.criar_e_adicionar_bloco(
-                                    chave_privada,
-                                    id_autoridade,
-                                );
-
-                                // Salva a blockchain após criar o bloco
-                                if let Err(e) = bc.salvar_em_disco("blockchain.json") {
-                                    println!("Erro ao salvar a blockchain: {:?}", e);
+                            let mut bc = blockchain.lock().await;
+                            let resultado = produzir_bloco(&mut bc, chave, true, &mut armazenamento, &mut p2p_swarm, &mut motor_finalidade, &config);
+                            salvar_mempool(&bc, &caminho_mempool);
+                            match resultado {
+                                Ok(_) => println!("Bloco criado e difundido."),
+                                Err(e) => {
+                                    if let BlocoErro::ForaDoTurno { .. } = e {
+                                        let ultimo = &bc.cadeia.last().unwrap().cabecalho;
+                                        let autoridades = bc.autoridades_em(ultimo.indice + 1, &config);
+                                        if let Some(espera) = espera_para_produzir(ultimo, chave.0, chrono::Utc::now(), &autoridades, &config) {
+                                            println!("Esta autoridade poderá produzir o bloco em {} segundo(s).", espera.num_seconds() + 1);
+                                        }
+                                    }
+                                    println!("Bloco não criado: {}", e);
                                 }
-                                bloco
-                            };
-                            p2p_swarm.difundir_bloco(&novo_bloco);
-                            println!("Bloco criado e difundido.");
+                            }
                         } else {
                             println!("Este nó não é autoridade e não pode criar blocos.");
                         }
                     }
+                    "sair" => break,
+                    "assinar_alteracao" => {
+                        let Some((id_autoridade, chave_privada)) = &chave_autoridade else {
+                            println!("Este nó não é autoridade e não pode aprovar alterações de autoridades.");
+                            continue;
+                        };
+                        let Some((id_alterada, chave_publica_pem, altura_vigencia)) = ler_alteracao_async(&mut stdin_lines).await else {
+                            continue;
+                        };
+                        let hash = hash_alteracao(&config.genesis.id_cadeia, id_alterada, chave_publica_pem.as_deref(), altura_vigencia);
+                        let assinatura = AssinaturaAutoridade::nova(&hash, chave_privada, *id_autoridade);
+                        println!("Assinatura da autoridade {} (entregue à secretaria):", id_autoridade);
+                        println!("{}", serde_json::to_string(&assinatura).unwrap());
+                    }
+                    "autoridades" => {
+                        let bc = blockchain.lock().await;
+                        let altura = bc.altura() + 1;
+                        println!("Autoridades em vigor no bloco {}: {:?}", altura, bc.autoridades_em(altura, &config).ids());
+                        for (altura_vigencia, id_autoridade, incluida) in bc.estado().alteracoes_agendadas(altura) {
+                            let alteracao = if incluida { "incluída ou com nova chave" } else { "removida" };
+                            println!("A partir do bloco {}: autoridade {} {}.", altura_vigencia, id_autoridade, alteracao);
+                        }
+                    }
+                    "prova_inclusao" => {
+                        println!("Digite o índice do bloco:");
+                        let indice_bloco = ler_u32_async(&mut stdin_lines).await;
+                        println!("Digite o ID da transação:");
+                        let id_transacao = ler_u32_async(&mut stdin_lines).await;
+
+                        let bc = blockchain.lock().await;
+                        match bc.bloco(indice_bloco)
+                            .and_then(|bloco| ProvaInclusao::gerar(bloco, id_transacao))
+                        {
+                            Some(prova) => {
+                                println!("Prova de inclusão (hash do bloco {}):", prova.cabecalho.calcular_hash());
+                                println!("{}", serde_json::to_string(&prova).unwrap());
+                            }
+                            None => println!("Transação não encontrada no bloco informado."),
+                        }
+                    }
+                    "verificar_prova" => {
+                        println!("Cole a prova de inclusão (JSON em uma linha):");
+                        let prova_json = ler_string_async(&mut stdin_lines).await;
+                        match serde_json::from_str::<ProvaInclusao>(&prova_json) {
+                            Ok(prova) => {
+                                let bc = blockchain.lock().await;
+                                match bc.bloco(prova.cabecalho.indice) {
+                                    Some(bloco) if prova.verificar(&bloco.hash_atual) => {
+                                        println!("Prova válida: a transação {} está no bloco {}.", prova.transacao.id_transacao, bloco.cabecalho.indice);
+                                    }
+                                    _ => println!("Prova inválida para a cadeia local."),
+                                }
+                            }
+                            Err(e) => println!("Prova malformada: {}", e),
+                        }
+                    }
+                    "exibir_bloco" => {
+                        println!("Digite a altura ou o hash do bloco:");
+                        let chave = ler_string_async(&mut stdin_lines).await;
+                        let resultado = match chave.parse::<u64>() {
+                            Ok(altura) => armazenamento.bloco_por_altura(altura),
+                            Err(_) => armazenamento.bloco_por_hash(&chave),
+                        };
+                        match resultado {
+                            Ok(Some(bloco)) => println!("{:#?}", bloco),
+                            Ok(None) => println!("Bloco não encontrado."),
+                            Err(e) => println!("Erro ao ler o bloco: {:?}", e),
+                        }
+                    }
+                    "consultar" => {
+                        println!("Consultar por (estudante, periodo, disciplina, transacao):");
+                        let criterio = ler_string_async(&mut stdin_lines).await;
+                        let registros = match criterio.as_str() {
+                            "estudante" => {
+                                println!("Digite o ID do estudante:");
+                                let id_estudante = ler_u32_async(&mut stdin_lines).await;
+                                let bc = blockchain.lock().await;
+                                exibir_registros(&bc.consultar_por_estudante(id_estudante))
+                            }
+                            "periodo" => {
+                                println!("Digite o ano do período letivo:");
+                                let ano = ler_u32_async(&mut stdin_lines).await;
+                                println!("Digite o semestre do período letivo:");
+                                let semestre = ler_u8_async(&mut stdin_lines).await;
+                                let bc = blockchain.lock().await;
+                                exibir_registros(&bc.consultar_por_periodo(ano, semestre))
+                            }
+                            "disciplina" => {
+                                println!("Digite o código da disciplina:");
+                                let codigo = ler_string_async(&mut stdin_lines).await;
+                                let bc = blockchain.lock().await;
+                                exibir_registros(&bc.consultar_por_disciplina(&codigo))
+                            }
+                            "transacao" => {
+                                println!("Digite o ID da transação:");
+                                let id_transacao = ler_u32_async(&mut stdin_lines).await;
+                                let bc = blockchain.lock().await;
+                                let registro = bc.consultar_por_transacao(id_transacao);
+                                exibir_registros(registro.as_slice())
+                            }
+                            _ => {
+                                println!("Critério desconhecido.");
+                                continue;
+                            }
+                        };
+                        if registros == 0 {
+                            println!("Nenhum registro encontrado.");
+                        }
+                    }
+                    "historico" => {
+                        println!("Digite o ID do estudante:");
+                        let id_estudante = ler_u32_async(&mut stdin_lines).await;
+                        println!("Formato (texto, json, csv):");
+                        let formato = ler_string_async(&mut stdin_lines).await;
+                        let bc = blockchain.lock().await;
+                        match bc.historico_escolar(id_estudante) {
+                            Some(historico) => match formato.as_str() {
+                                "json" => println!("{}", historico.para_json()),
+                                "csv" => print!("{}", historico.para_csv()),
+                                _ => print!("{}", historico.para_texto()),
+                            },
+                            None => println!("Nenhum registro encontrado para o estudante."),
+                        }
+                    }
+                    "nota" => {
+                        let (id_estudante, id_periodo, codigo) = ler_disciplina_async(&mut stdin_lines).await;
+                        println!("Digite o ID da nota:");
+                        let id_nota = ler_u32_async(&mut stdin_lines).await;
+                        let bc = blockchain.lock().await;
+                        let alteracoes = bc.estado().alteracoes_nota(id_estudante, id_periodo, &codigo, id_nota);
+                        match alteracoes.last() {
+                            None => println!("Nota não encontrada."),
+                            Some(vigente) => {
+                                match &vigente.nota {
+                                    Some(nota) => println!("Valor vigente: {:.2} ({})", nota.valor, nota.tipo),
+                                    None => println!("Nota anulada."),
+                                }
+                                println!("Histórico de alterações:");
+                                for alteracao in alteracoes {
+                                    let valor = alteracao
+                                        .nota
+                                        .as_ref()
+                                        .map(|nota| format!("{:.2}", nota.valor))
+                                        .unwrap_or_else(|| String::from("anulada"));
+                                    match &alteracao.motivo {
+                                        Some(motivo) => println!(
+                                            "  transação {} em {}: {} (emissor {}: {})",
+                                            alteracao.id_transacao, alteracao.timestamp, valor, alteracao.id_emissor, motivo
+                                        ),
+                                        None => println!(
+                                            "  transação {} em {}: {} (lançamento pelo emissor {})",
+                                            alteracao.id_transacao, alteracao.timestamp, valor, alteracao.id_emissor
+                                        ),
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    "estado" => {
+                        let bc = blockchain.lock().await;
+                        let ultimo = bc.cadeia.last().unwrap();
+                        println!("Altura: {}", ultimo.cabecalho.indice);
+                        println!("Raiz de estado: {}", ultimo.cabecalho.raiz_estado);
+                        match bc.reproduzir_do_genesis(&config) {
+                            Ok(estado) if estado.raiz() == bc.estado().raiz() => {
+                                println!("Reaplicação a partir do gênesis confere com o estado atual.");
+                            }
+                            Ok(estado) => println!("Reaplicação a partir do gênesis diverge: raiz {}.", estado.raiz()),
+                            Err(e) => println!("Erro ao reaplicar a cadeia: {}", e),
+                        }
+                    }
+                    "validar_cadeia" => {
+                        let bc = blockchain.lock().await;
+                        match bc.validar_cadeia(&config) {
+                            Ok(()) => println!("Cadeia válida até o bloco {}.", bc.altura()),
+                            Err(e) => println!("Cadeia inválida: {}", e),
+                        }
+                    }
+                    "finalidade" => {
+                        let bc = blockchain.lock().await;
+                        let altura_finalizada = bc.altura_finalizada();
+                        println!("Último bloco final: {} ({})", altura_finalizada, bc.bloco(altura_finalizada).unwrap().hash_atual);
+                        if let Some(certificado) = bc.certificado(altura_finalizada) {
+                            let autoridades: Vec<u32> = certificado.precommits.iter().map(|v| v.id_autoridade).collect();
+                            println!("Certificado da rodada {} assinado pelas autoridades {:?}.", certificado.rodada, autoridades);
+                        }
+                        let altura = bc.altura();
+                        if let Some(rodada) = motor_finalidade.rodada(altura) {
+                            println!("Votação do bloco {} na rodada {}.", altura, rodada);
+                        }
+                    }
+                    "equivocacoes" => {
+                        let bc = blockchain.lock().await;
+                        if detector_equivocacao.provas().is_empty() {
+                            println!("Nenhuma equivocação detectada.");
+                        }
+                        for (numero, prova) in detector_equivocacao.provas().iter().enumerate() {
+                            let tipo = match prova {
+                                ProvaEquivocacao::BlocosConflitantes(..) => "blocos conflitantes",
+                                ProvaEquivocacao::VotosConflitantes(..) => "votos conflitantes",
+                            };
+                            let situacao = if bc.estado().autoridade_suspensa(prova.id_autoridade()) {
+                                "suspensa"
+                            } else {
+                                "ainda não suspensa"
+                            };
+                            println!("{}: autoridade {}, altura {}, {} ({})", numero, prova.id_autoridade(), prova.altura(), tipo, situacao);
+                        }
+                    }
+                    "mempool" => {
+                        let bc = blockchain.lock().await;
+                        let mempool = bc.mempool();
+                        if mempool.is_empty() {
+                            println!("Nenhuma transação pendente.");
+                            continue;
+                        }
+                        println!("{} transação(ões) pendente(s) de {}:", mempool.len(), config.mempool.capacidade);
+                        for entrada in mempool.entradas() {
+                            println!(
+                                "{} ({}), {}, recebida em {}",
+                                entrada.transacao.id_transacao,
+                                entrada.hash,
+                                entrada.transacao.tipo.nome(),
+                                entrada.recebida_em
+                            );
+                        }
+                    }
+                    "reconstruir_indices" => {
+                        let mut bc = blockchain.lock().await;
+                        bc.reconstruir_indices();
+                        println!("Índices reconstruídos a partir de {} bloco(s).", bc.cadeia.len());
+                    }
+                    "exportar_instantaneo" => {
+                        let Some((id_autoridade, chave_privada)) = &chave_autoridade else {
+                            println!("Este nó não é autoridade e não pode assinar instantâneos.");
+                            continue;
+                        };
+                        println!("Digite a altura do bloco:");
+                        let altura = ler_u32_async(&mut stdin_lines).await;
+                        println!("Digite o caminho do arquivo:");
+                        let caminho = ler_string_async(&mut stdin_lines).await;
+                        let bc = blockchain.lock().await;
+                        match bc.exportar_instantaneo(altura, chave_privada, *id_autoridade, &config) {
+                            Ok(instantaneo) => match instantaneo.salvar(Path::new(&caminho)) {
+                                Ok(()) => println!("Instantâneo gravado em {}; checkpoint {}.", caminho, instantaneo.bloco.hash_atual),
+                                Err(e) => println!("Erro ao gravar o instantâneo: {:?}", e),
+                            },
+                            Err(e) => println!("Instantâneo não exportado: {}", e),
+                        }
+                    }
                     "exibir_blockchain" => {
                         let bc = blockchain.lock().await;
                         println!("Blockchain atual:");
@@ -252,7 +731,7 @@ async fn main() {
                             println!("{:#?}", bloco);
                         }
                     }
-                    _ => println!("Comando desconhecido. Tente 'transacao', 'criar_bloco' ou 'exibir_blockchain'."),
+                    _ => println!("Comando desconhecido. Tente 'transacao', 'criar_bloco', 'exibir_blockchain', 'exibir_bloco', 'consultar', 'historico', 'nota', 'estado', 'finalidade', 'autoridades', 'assinar_alteracao', 'equivocacoes', 'mempool', 'reconstruir_indices', 'prova_inclusao', 'verificar_prova', 'exportar_instantaneo' ou 'sair'."),
                 }
             }
             else => {
@@ -260,6 +739,280 @@ async fn main() {
             }
         }
     }
+
+    // Encerramento: nenhum bloco está sendo produzido fora do laço, então
+    // basta gravar em disco o que a política de fsync ainda não gravou
+    println!("Encerrando o nó...");
+    let bc = blockchain.lock().await;
+    salvar_mempool(&bc, &caminho_mempool);
+    if let Err(e) = armazenamento.sincronizar_disco() {
+        println!("Erro ao gravar o armazenamento de blocos: {:?}", e);
+    }
+}
+
+/// Lê o id que segue uma opção de linha de comando, como `--autoridade 2`.
+/// Devolve a mensagem de uso se o id faltar ou não for um número.
+fn ler_id_argumento(argumentos: &[String], opcao: &str) -> Result<Option<u32>, String> {
+    let Some(posicao) = argumentos.iter().position(|arg| arg == opcao) else {
+        return Ok(None);
+    };
+    argumentos
+        .get(posicao + 1)
+        .and_then(|id| id.parse().ok())
+        .map(Some)
+        .ok_or_else(|| format!("Uso: {} <id>, com o ID numérico após a opção (ex: {} 1).", opcao, opcao))
+}
+
+/// Difunde os votos produzidos pela votação de finalidade e, se uma altura
+/// ficou final, grava e difunde o certificado.
+fn aplicar_progresso(
+    progresso: Progresso,
+    bc: &mut Blockchain,
+    motor: &mut MotorFinalidade,
+    armazenamento: &mut ArmazenamentoBlocos,
+    p2p_swarm: &mut P2PSwarm,
+    config: &Config,
+) {
+    // Um voto só é difundido depois de gravado; sem a gravação, um reinício
+    // permitiria votar em outro bloco na mesma rodada
+    for voto in &progresso.votos {
+        match armazenamento.anexar_voto(voto) {
+            Ok(()) => p2p_swarm.difundir_voto(voto),
+            Err(e) => println!("Erro ao salvar o voto de finalidade: {:?}", e),
+        }
+    }
+    let Some(certificado) = progresso.certificado else {
+        return;
+    };
+    let altura = certificado.altura;
+    match bc.registrar_certificado(certificado.clone(), config) {
+        Ok(true) => {
+            if let Err(e) = armazenamento.anexar_certificado(&certificado) {
+                println!("Erro ao salvar o certificado de finalidade: {:?}", e);
+            }
+            motor.descartar_ate(altura);
+            p2p_swarm.difundir_certificado(&certificado);
+            println!("Bloco {} finalizado.", altura);
+        }
+        Ok(false) => {}
+        Err(e @ (FinalidadeErro::BlocoDesconhecido(_) | FinalidadeErro::BlocoDivergente(_))) => {
+            // O certificado é válido, mas para um bloco que este nó não tem;
+            // a próxima consulta aos peers traz a cadeia certificada
+            println!("Certificado de finalidade para outra cadeia: {}", e);
+        }
+        Err(e) => println!("Certificado de finalidade rejeitado: {}", e),
+    }
+}
+
+/// Cria o bloco seguinte com as transações pendentes, grava, difunde e
+/// leva a nova ponta à votação de finalidade. Quem chama grava o pool, que
+/// muda mesmo quando o bloco não é criado.
+fn produzir_bloco(
+    bc: &mut Blockchain,
+    (id_autoridade, chave_privada): &(u32, RsaPrivateKey),
+    permitir_vazio: bool,
+    armazenamento: &mut ArmazenamentoBlocos,
+    p2p_swarm: &mut P2PSwarm,
+    motor: &mut MotorFinalidade,
+    config: &Config,
+) -> Result<Bloco, BlocoErro> {
+    let novo_bloco = bc.criar_e_adicionar_bloco(chave_privada, *id_autoridade, permitir_vazio, config)?;
+
+    if let Err(e) = armazenamento.anexar_novos(&bc.cadeia) {
+        println!("Erro ao salvar a blockchain: {:?}", e);
+    }
+    p2p_swarm.difundir_bloco(&novo_bloco);
+    let progresso = motor.ao_mudar_ponta(bc, config, chrono::Utc::now());
+    aplicar_progresso(progresso, bc, motor, armazenamento, p2p_swarm, config);
+    Ok(novo_bloco)
+}
+
+/// Aplica um ramo obtido pela sincronização, que pode estender a cadeia
+/// local ou substituí-la a partir do ancestral comum. Devolve `false` se o
+/// ramo foi rejeitado.
+fn aplicar_ramo(
+    ramo: Vec<Bloco>,
+    bc: &mut Blockchain,
+    armazenamento: &mut ArmazenamentoBlocos,
+    p2p_swarm: &mut P2PSwarm,
+    motor: &mut MotorFinalidade,
+    caminho_mempool: &Path,
+    config: &Config,
+) -> bool {
+    match bc.reorganizar(ramo, config) {
+        Ok(Some(reorganizacao)) => {
+            if reorganizacao.blocos_removidos > 0 {
+                println!(
+                    "Cadeia reorganizada a partir do bloco {}: {} bloco(s) removido(s), {} adicionado(s), {} transação(ões) devolvida(s) ao pool.",
+                    reorganizacao.altura_ancestral,
+                    reorganizacao.blocos_removidos,
+                    reorganizacao.blocos_adicionados,
+                    reorganizacao.transacoes_devolvidas,
+                );
+            } else {
+                println!("{} bloco(s) sincronizado(s); altura atual {}.", reorganizacao.blocos_adicionados, bc.altura());
+            }
+            // Um ramo que só estende a cadeia é anexado; depois de uma
+            // reorganização o armazenamento é refeito a partir do ancestral
+            let gravado = if reorganizacao.blocos_removidos > 0 {
+                armazenamento.sincronizar_com(&bc.cadeia)
+            } else {
+                armazenamento.anexar_novos(&bc.cadeia)
+            };
+            if let Err(e) = gravado {
+                println!("Erro ao salvar a blockchain: {:?}", e);
+            }
+            salvar_mempool(bc, caminho_mempool);
+            let progresso = motor.ao_mudar_ponta(bc, config, chrono::Utc::now());
+            aplicar_progresso(progresso, bc, motor, armazenamento, p2p_swarm, config);
+            true
+        }
+        Ok(None) => true,
+        Err(e) => {
+            println!("Ramo recebido rejeitado: {}", e);
+            false
+        }
+    }
+}
+
+/// Compara um bloco recebido com os já vistos e difunde a prova se a
+/// autoridade que o assinou tiver se equivocado.
+fn observar_bloco(
+    detector: &mut DetectorEquivocacao,
+    bloco: &Bloco,
+    bc: &Blockchain,
+    p2p_swarm: &mut P2PSwarm,
+    config: &Config,
+) {
+    if let Some(prova) = detector.observar_bloco(bloco, bc, config) {
+        println!("Autoridade {} assinou dois blocos diferentes na altura {}.", prova.id_autoridade(), prova.altura());
+        p2p_swarm.difundir_equivocacao(&prova);
+    }
+}
+
+/// Parte do instantâneo de `[instantaneo]`, se houver um configurado e ele
+/// conferir com o checkpoint. O instantâneo é copiado para o armazenamento
+/// antes dos blocos, para que a cadeia possa ser validada nos próximos
+/// inícios.
+fn iniciar_do_instantaneo(armazenamento: &mut ArmazenamentoBlocos, config: &Config) -> Option<Blockchain> {
+    let (arquivo, hash_checkpoint) = config.instantaneo.origem()?;
+    let instantaneo = match InstantaneoAssinado::ler(arquivo) {
+        Ok(instantaneo) => instantaneo,
+        Err(e) => {
+            println!("Erro ao ler o instantâneo {}: {:?}", arquivo.display(), e);
+            return None;
+        }
+    };
+    if let Err(e) = instantaneo.verificar(hash_checkpoint, config) {
+        println!("Instantâneo recusado: {}", e);
+        return None;
+    }
+    println!(
+        "Inicializando a blockchain a partir do instantâneo da altura {}, assinado pela autoridade {}.",
+        instantaneo.altura(),
+        instantaneo.id_autoridade
+    );
+    if let Err(e) = armazenamento.gravar_instantaneo(&instantaneo) {
+        println!("Erro ao salvar o instantâneo: {:?}", e);
+    }
+    let bc = Blockchain::do_instantaneo(instantaneo);
+    if let Err(e) = armazenamento.sincronizar_com(&bc.cadeia) {
+        println!("Erro ao salvar a blockchain: {:?}", e);
+    }
+    Some(bc)
+}
+
+/// Grava as transações pendentes, para que sobrevivam a um reinício.
+fn salvar_mempool(bc: &Blockchain, caminho: &Path) {
+    if let Err(e) = bc.mempool().salvar(caminho) {
+        println!("Erro ao salvar as transações pendentes: {:?}", e);
+    }
+}
+
+/// Exibe os registros de uma consulta e retorna quantos foram exibidos.
+fn exibir_registros(registros: &[RegistroIndexado]) -> usize {
+    for registro in registros {
+        println!(
+            "Bloco {} ({}), {}: {:#?}",
+            registro.altura,
+            registro.hash_bloco,
+            registro.transacao.tipo.nome(),
+            registro.transacao
+        );
+        if let Some(situacao) = registro.situacao {
+            println!("Situação atual na disciplina: {}", situacao);
+        }
+    }
+    registros.len()
+}
+
+/// Lê uma alteração do conjunto de autoridades: id, chave pública (do
+/// arquivo PEM informado; nenhuma para remover) e altura de vigência.
+async fn ler_alteracao_async(
+    stdin_lines: &mut tokio::io::Lines<tokio::io::BufReader<tokio::io::Stdin>>,
+) -> Option<(u32, Option<String>, u32)> {
+    println!("Digite o ID da autoridade:");
+    let id_autoridade = ler_u32_async(stdin_lines).await;
+    println!("Digite o caminho da chave pública em PEM (vazio para remover a autoridade):");
+    let caminho = ler_string_async(stdin_lines).await;
+    let chave_publica_pem = if caminho.is_empty() {
+        None
+    } else {
+        match std::fs::read_to_string(&caminho) {
+            Ok(pem) => Some(pem),
+            Err(e) => {
+                println!("Erro ao ler a chave pública: {}", e);
+                return None;
+            }
+        }
+    };
+    println!("Digite a altura a partir da qual a alteração vale:");
+    let altura_vigencia = ler_u32_async(stdin_lines).await;
+    Some((id_autoridade, chave_publica_pem, altura_vigencia))
+}
+
+/// Lê estudante, período e código que identificam uma disciplina matriculada.
+async fn ler_disciplina_async(
+    stdin_lines: &mut tokio::io::Lines<tokio::io::BufReader<tokio::io::Stdin>>,
+) -> (u32, u32, String) {
+    println!("Digite o ID do estudante:");
+    let id_estudante = ler_u32_async(stdin_lines).await;
+    println!("Digite o ID do período letivo:");
+    let id_periodo = ler_u32_async(stdin_lines).await;
+    println!("Digite o código da disciplina:");
+    let codigo = ler_string_async(stdin_lines).await;
+    (id_estudante, id_periodo, codigo)
+}
+
+async fn ler_nota_async(
+    stdin_lines: &mut tokio::io::Lines<tokio::io::BufReader<tokio::io::Stdin>>,
+) -> Nota {
+    println!("Digite o ID da nota:");
+    let id_nota = ler_u32_async(stdin_lines).await;
+    println!("Digite o valor da nota:");
+    let valor = ler_f32_async(stdin_lines).await;
+    println!("Digite o tipo da nota (ex: Prova, Trabalho):");
+    let tipo = ler_string_async(stdin_lines).await;
+    let data = ler_data_async(stdin_lines, "da nota").await;
+    Nota::nova_nota(id_nota, valor, &tipo, data)
+}
+
+async fn ler_data_async(
+    stdin_lines: &mut tokio::io::Lines<tokio::io::BufReader<tokio::io::Stdin>>,
+    descricao: &str,
+) -> NaiveDate {
+    loop {
+        println!("Digite o ano {}:", descricao);
+        let ano = ler_i32_async(stdin_lines).await;
+        println!("Digite o mês {}:", descricao);
+        let mes = ler_u32_async(stdin_lines).await;
+        println!("Digite o dia {}:", descricao);
+        let dia = ler_u32_async(stdin_lines).await;
+        match NaiveDate::from_ymd_opt(ano, mes, dia) {
+            Some(data) => return data,
+            None => println!("Data inválida, tente novamente."),
+        }
+    }
 }
 
 async fn ler_u32_async(
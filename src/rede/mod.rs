@@ -1,4 +1,5 @@
 pub use p2p::{iniciar_rede, P2PEvent};
+pub use sincronizacao::{ConfigSincronizacao, GerenciadorSincronizacao, MAX_BLOCOS_POR_RESPOSTA};
 
 pub mod p2p;
-
+pub mod sincronizacao;
@@ -8,13 +8,13 @@ use libp2p::{
     request_response::{
         RequestResponse, RequestResponseCodec, RequestResponseEvent,
         RequestResponseMessage, ProtocolName, ProtocolSupport,
-        RequestResponseConfig, ResponseChannel,
+        RequestResponseConfig, ResponseChannel, RequestId,
     },
     swarm::SwarmEvent,
 };
 use futures::prelude::*;
 use serde_json;
-use crate::blockchain::{Bloco, Transacao};
+use crate::blockchain::{Bloco, CabecalhoBloco, CertificadoQuorum, ProvaEquivocacao, Transacao, Voto};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use std::io;
@@ -48,23 +48,68 @@ impl From<RequestResponseEvent<BlockchainRequest, BlockchainResponse>> for MyBeh
     }
 }
 
-pub enum P2PEvent {
+/// Envelope das mensagens difundidas por gossip. O tipo de transação vai
+/// dentro da própria `Transacao`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MensagemGossip {
     NovoBloco(Bloco),
     NovaTransacao(Transacao),
-    BlockchainSolicitada {
+    Equivocacao(ProvaEquivocacao),
+}
+
+/// Mensagens da votação de finalidade, difundidas em um tópico próprio
+/// para não competir com blocos e transações.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MensagemFinalidade {
+    Voto(Voto),
+    Certificado(CertificadoQuorum),
+}
+
+pub enum P2PEvent {
+    /// Bloco difundido por gossip; `peer` é quem o repassou.
+    NovoBloco {
+        peer: PeerId,
+        bloco: Bloco,
+    },
+    NovaTransacao(Transacao),
+    VotoRecebido(Voto),
+    CertificadoRecebido(CertificadoQuorum),
+    EquivocacaoRecebida(ProvaEquivocacao),
+    PeerConectado(PeerId),
+    PeerDesconectado(PeerId),
+    SolicitacaoRecebida {
         peer: PeerId,
+        solicitacao: BlockchainRequest,
         channel: ResponseChannel<BlockchainResponse>,
     },
-    BlockchainRecebida(Vec<Bloco>),
+    RespostaRecebida {
+        peer: PeerId,
+        id: RequestId,
+        resposta: BlockchainResponse,
+    },
+    /// Solicitação sem resposta: o peer desconectou, expirou o prazo ou
+    /// recusou o protocolo.
+    SolicitacaoFalhou {
+        peer: PeerId,
+        id: RequestId,
+    },
+    /// Peer de outra rede, identificado pelo gênesis divergente; ele é
+    /// banido e nada é sincronizado com ele.
+    GenesisDiferente {
+        peer: PeerId,
+        hash_genesis: String,
+    },
 }
 
 pub struct P2PSwarm {
     pub swarm: Swarm<MyBehaviour>,
     topic: Topic,
+    topico_finalidade: Topic,
+    hash_genesis: String,
 }
 
 impl P2PSwarm {
-    pub async fn new(_blockchain: Arc<Mutex<crate::blockchain::Blockchain>>) -> Self {
+    pub async fn new(_blockchain: Arc<Mutex<crate::blockchain::Blockchain>>, hash_genesis: String) -> Self {
         let local_key = identity::Keypair::generate_ed25519();
         let peer_id = PeerId::from(local_key.public());
 
@@ -78,8 +123,12 @@ impl P2PSwarm {
         )
             .unwrap();
 
-        let topic = Topic::new("blockchain");
+        // Os tópicos levam o hash do gênesis, para que redes diferentes não
+        // troquem mensagens
+        let topic = Topic::new(format!("blockchain/{}", hash_genesis));
         gossipsub.subscribe(&topic).unwrap();
+        let topico_finalidade = Topic::new(format!("finalidade/{}", hash_genesis));
+        gossipsub.subscribe(&topico_finalidade).unwrap();
 
         // Configuração do RequestResponse
         let protocols = std::iter::once((
@@ -115,79 +164,99 @@ impl P2PSwarm {
             .dial(outro_peer.parse::<Multiaddr>().unwrap())
             .unwrap_or_else(|e| println!("Falha ao conectar com peer: {:?}", e));
 
-        P2PSwarm { swarm, topic }
+        P2PSwarm { swarm, topic, topico_finalidade, hash_genesis }
     }
 
     pub async fn next_event(&mut self) -> Option<P2PEvent> {
         loop {
-            match self.swarm.select_next_some().await {
-                SwarmEvent::Behaviour(event) => match event {
-                    MyBehaviourEvent::Gossipsub(GossipsubEvent::Message { message, .. }) => {
-                        let data_str = String::from_utf8_lossy(&message.data);
-                        if let Ok(bloco) = serde_json::from_str::<Bloco>(&data_str) {
-                            return Some(P2PEvent::NovoBloco(bloco));
-                        } else if let Ok(transacao) =
-                            serde_json::from_str::<Transacao>(&data_str)
-                        {
+            let event = match self.swarm.select_next_some().await {
+                SwarmEvent::Behaviour(event) => event,
+                SwarmEvent::ConnectionEstablished { peer_id, num_established, .. } if num_established.get() == 1 => {
+                    return Some(P2PEvent::PeerConectado(peer_id));
+                }
+                SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                    return Some(P2PEvent::PeerDesconectado(peer_id));
+                }
+                _ => continue,
+            };
+            match event {
+                MyBehaviourEvent::Gossipsub(GossipsubEvent::Message { message, .. })
+                    if message.topic == self.topico_finalidade.hash() =>
+                {
+                    match serde_json::from_slice::<MensagemFinalidade>(&message.data) {
+                        Ok(MensagemFinalidade::Voto(voto)) => {
+                            return Some(P2PEvent::VotoRecebido(voto));
+                        }
+                        Ok(MensagemFinalidade::Certificado(certificado)) => {
+                            return Some(P2PEvent::CertificadoRecebido(certificado));
+                        }
+                        Err(e) => println!("Mensagem de finalidade ignorada: {}", e),
+                    }
+                }
+                MyBehaviourEvent::Gossipsub(GossipsubEvent::Message { propagation_source, message, .. }) => {
+                    match serde_json::from_slice::<MensagemGossip>(&message.data) {
+                        Ok(MensagemGossip::NovoBloco(bloco)) => {
+                            return Some(P2PEvent::NovoBloco { peer: propagation_source, bloco });
+                        }
+                        Ok(MensagemGossip::NovaTransacao(transacao)) => {
                             return Some(P2PEvent::NovaTransacao(transacao));
                         }
+                        Ok(MensagemGossip::Equivocacao(prova)) => {
+                            return Some(P2PEvent::EquivocacaoRecebida(prova));
+                        }
+                        Err(e) => println!("Mensagem de gossip ignorada: {}", e),
                     }
-                    MyBehaviourEvent::RequestResponse(event) => {
-                        match event {
-                            RequestResponseEvent::Message { peer, message } => {
-                                match message {
-                                    RequestResponseMessage::Request { request, channel, .. } => {
-                                        match request {
-                                            BlockchainRequest::SolicitacaoBlockchain => {
-                                                // Envia a blockchain em resposta
-                                                return Some(P2PEvent::BlockchainSolicitada {
-                                                    peer,
-                                                    channel,
-                                                });
-                                            }
-                                        }
-                                    }
-                                    RequestResponseMessage::Response { response, .. } => {
-                                        match response {
-                                            BlockchainResponse::Blockchain(cadeia) => {
-                                                return Some(P2PEvent::BlockchainRecebida(cadeia));
-                                            }
-                                        }
-                                    }
+                }
+                MyBehaviourEvent::RequestResponse(RequestResponseEvent::Message { peer, message }) => {
+                    match message {
+                        RequestResponseMessage::Request { request, channel, .. } => {
+                            if let BlockchainRequest::Status(status) = &request {
+                                if status.hash_genesis != self.hash_genesis {
+                                    return Some(self.recusar_peer(peer, status.hash_genesis.clone()));
+                                }
+                            }
+                            return Some(P2PEvent::SolicitacaoRecebida { peer, solicitacao: request, channel });
+                        }
+                        RequestResponseMessage::Response { request_id, response } => {
+                            if let BlockchainResponse::Status(status) = &response {
+                                if status.hash_genesis != self.hash_genesis {
+                                    return Some(self.recusar_peer(peer, status.hash_genesis.clone()));
                                 }
                             }
-                            _ => {}
+                            return Some(P2PEvent::RespostaRecebida { peer, id: request_id, resposta: response });
                         }
                     }
-                    _ => {}
-                },
+                }
+                MyBehaviourEvent::RequestResponse(RequestResponseEvent::OutboundFailure { peer, request_id, error }) => {
+                    println!("Solicitação ao nó {} falhou: {:?}", peer, error);
+                    return Some(P2PEvent::SolicitacaoFalhou { peer, id: request_id });
+                }
                 _ => {}
             }
         }
     }
 
-    pub fn solicitar_blockchain(&mut self) {
-        let peers: Vec<_> = self.swarm.behaviour().gossipsub.all_mesh_peers().cloned().collect();
-        for peer in peers {
-            self.swarm.behaviour_mut().request_response.send_request(
-                &peer,
-                BlockchainRequest::SolicitacaoBlockchain,
-            );
-        }
+    /// Bane um peer cujo gênesis difere do local.
+    fn recusar_peer(&mut self, peer: PeerId, hash_genesis: String) -> P2PEvent {
+        self.swarm.ban_peer_id(peer);
+        P2PEvent::GenesisDiferente { peer, hash_genesis }
+    }
+
+    pub fn solicitar(&mut self, peer: &PeerId, solicitacao: BlockchainRequest) -> RequestId {
+        self.swarm.behaviour_mut().request_response.send_request(peer, solicitacao)
     }
 
-    pub fn enviar_blockchain(&mut self, cadeia: &Vec<Bloco>, channel: ResponseChannel<BlockchainResponse>) {
-        let response = BlockchainResponse::Blockchain(cadeia.clone());
+    pub fn responder(&mut self, channel: ResponseChannel<BlockchainResponse>, resposta: BlockchainResponse) {
         if let Err(e) = self.swarm.behaviour_mut().request_response.send_response(
             channel,
-            response,
+            resposta,
         ) {
-            println!("Erro ao enviar blockchain: {:?}", e);
+            println!("Erro ao enviar resposta: {:?}", e);
         }
     }
 
     pub fn difundir_transacao(&mut self, transacao: &Transacao) {
-        let data = serde_json::to_string(transacao).unwrap();
+        let data = serde_json::to_string(&MensagemGossip::NovaTransacao(transacao.clone())).unwrap();
         if let Err(e) = self.swarm
             .behaviour_mut()
             .gossipsub
@@ -196,8 +265,38 @@ impl P2PSwarm {
         }
     }
 
+    pub fn difundir_voto(&mut self, voto: &Voto) {
+        let data = serde_json::to_string(&MensagemFinalidade::Voto(voto.clone())).unwrap();
+        if let Err(e) = self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(self.topico_finalidade.clone(), data.as_bytes()) {
+            println!("Erro ao difundir voto: {:?}", e);
+        }
+    }
+
+    pub fn difundir_certificado(&mut self, certificado: &CertificadoQuorum) {
+        let data = serde_json::to_string(&MensagemFinalidade::Certificado(certificado.clone())).unwrap();
+        if let Err(e) = self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(self.topico_finalidade.clone(), data.as_bytes()) {
+            println!("Erro ao difundir certificado: {:?}", e);
+        }
+    }
+
+    pub fn difundir_equivocacao(&mut self, prova: &ProvaEquivocacao) {
+        let data = serde_json::to_string(&MensagemGossip::Equivocacao(prova.clone())).unwrap();
+        if let Err(e) = self.swarm
+            .behaviour_mut()
+            .gossipsub
+            .publish(self.topic.clone(), data.as_bytes()) {
+            println!("Erro ao difundir prova de equivocação: {:?}", e);
+        }
+    }
+
     pub fn difundir_bloco(&mut self, bloco: &Bloco) {
-        let data = serde_json::to_string(bloco).unwrap();
+        let data = serde_json::to_string(&MensagemGossip::NovoBloco(bloco.clone())).unwrap();
         if let Err(e) = self.swarm
             .behaviour_mut()
             .gossipsub
@@ -214,21 +313,41 @@ pub struct BlockchainExchangeProtocol();
 
 impl ProtocolName for BlockchainExchangeProtocol {
     fn protocol_name(&self) -> &[u8] {
-        b"/blockchain-exchange/1.0.0"
+        b"/blockchain-exchange/3.0.0"
     }
 }
 
 #[derive(Clone)]
 pub struct BlockchainExchangeCodec();
 
+/// Situação da cadeia de um nó, trocada ao conectar e periodicamente.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusCadeia {
+    pub hash_genesis: String,
+    pub altura: u32,
+    pub hash_ponta: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BlockchainRequest {
-    SolicitacaoBlockchain,
+    /// Leva a situação de quem pede e recebe a de quem responde; pedidos de
+    /// outra rede (gênesis diferente) são recusados.
+    Status(StatusCadeia),
+    /// Até `quantidade` cabeçalhos consecutivos a partir da altura `inicio`.
+    Cabecalhos { inicio: u32, quantidade: u32 },
+    /// Até `quantidade` blocos consecutivos a partir da altura `inicio`.
+    Blocos { inicio: u32, quantidade: u32 },
+    BlocoPorHash(String),
 }
 
+/// Respostas por faixa podem vir mais curtas que o pedido: quem responde
+/// limita o tamanho e só tem os blocos da sua cadeia.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BlockchainResponse {
-    Blockchain(Vec<Bloco>),
+    Status(StatusCadeia),
+    Cabecalhos(Vec<CabecalhoBloco>),
+    Blocos(Vec<Bloco>),
+    Bloco(Option<Bloco>),
 }
 
 #[async_trait]
@@ -294,6 +413,6 @@ impl RequestResponseCodec for BlockchainExchangeCodec {
     }
 }
 
-pub async fn iniciar_rede(blockchain: Arc<Mutex<crate::blockchain::Blockchain>>) -> P2PSwarm {
-    P2PSwarm::new(blockchain).await
+pub async fn iniciar_rede(blockchain: Arc<Mutex<crate::blockchain::Blockchain>>, hash_genesis: String) -> P2PSwarm {
+    P2PSwarm::new(blockchain, hash_genesis).await
 }
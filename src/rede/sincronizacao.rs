@@ -0,0 +1,667 @@
+//! Sincronização de blocos por faixas de altura. Os nós trocam sua situação
+//! (altura, ponta e gênesis) ao conectar e a cada `intervalo_status`; quando
+//! um peer está à frente, ou em outra ponta, o gerenciador baixa primeiro os
+//! cabeçalhos dele a partir do último bloco final local, localiza o ancestral
+//! comum e então pede os blocos do ramo em faixas, a vários peers em
+//! paralelo. Cada bloco recebido precisa ter o hash do cabeçalho da mesma
+//! altura, de modo que qualquer peer pode servir qualquer faixa.
+//!
+//! Quando o ramo continua a ponta local, os blocos são entregues para
+//! aplicação assim que chegam em sequência; quando diverge dela, só o ramo
+//! completo é entregue, para a escolha de bifurcação compará-lo com o local.
+//!
+//! Blocos difundidos por gossip que não se encadeiam à ponta são resolvidos
+//! buscando seus ancestrais por hash no peer que os repassou, até alguns
+//! blocos de profundidade; além disso, recorre-se à troca de situação.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use chrono::Duration;
+use libp2p::request_response::RequestId;
+use libp2p::PeerId;
+
+use crate::blockchain::{Blockchain, Bloco, CabecalhoBloco};
+use crate::utils::config::Config;
+
+use super::p2p::{BlockchainRequest, BlockchainResponse, P2PSwarm, StatusCadeia};
+
+/// Máximo de cabeçalhos devolvidos por resposta.
+pub const MAX_CABECALHOS_POR_RESPOSTA: u32 = 512;
+/// Máximo de blocos devolvidos por resposta.
+pub const MAX_BLOCOS_POR_RESPOSTA: u32 = 128;
+/// Quantos ancestrais de um bloco órfão são buscados por hash antes de
+/// recorrer à sincronização por faixas.
+const PROFUNDIDADE_MAXIMA_ORFAOS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct ConfigSincronizacao {
+    /// Blocos por pedido de faixa, até `MAX_BLOCOS_POR_RESPOSTA`.
+    pub blocos_por_pedido: u32,
+    /// Pedidos de blocos simultâneos a um mesmo peer.
+    pub pedidos_por_peer: usize,
+    /// De quanto em quanto tempo a situação dos peers é consultada.
+    pub intervalo_status: Duration,
+}
+
+impl Default for ConfigSincronizacao {
+    fn default() -> Self {
+        ConfigSincronizacao {
+            blocos_por_pedido: 32,
+            pedidos_por_peer: 2,
+            intervalo_status: Duration::seconds(30),
+        }
+    }
+}
+
+impl StatusCadeia {
+    pub fn da_blockchain(blockchain: &Blockchain, hash_genesis: &str) -> Self {
+        StatusCadeia {
+            hash_genesis: hash_genesis.to_string(),
+            altura: blockchain.altura(),
+            hash_ponta: blockchain.cadeia.last().unwrap().hash_atual.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Peer {
+    status: Option<StatusCadeia>,
+    /// Última ponta do peer já sincronizada ou recusada pela escolha de
+    /// bifurcação; não é buscada de novo.
+    ponta_avaliada: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Pedido {
+    Cabecalhos,
+    Blocos { inicio: u32, quantidade: u32 },
+    Ancestral,
+}
+
+/// Sincronização em andamento com a cadeia de um peer (o alvo).
+#[derive(Debug)]
+struct Sessao {
+    alvo: PeerId,
+    hash_alvo: String,
+    altura_alvo: u32,
+    /// Hashes dos cabeçalhos do alvo, a partir da altura `inicio`.
+    inicio: u32,
+    hashes: Vec<String>,
+    /// Altura do último bloco comum às duas cadeias; definida quando todos
+    /// os cabeçalhos chegaram.
+    ancestral: Option<u32>,
+    /// Se o ramo parte da ponta local, caso em que os blocos são entregues
+    /// à medida que chegam.
+    extensao: bool,
+    /// Faixas ainda não pedidas, como (início, quantidade).
+    faixas: VecDeque<(u32, u32)>,
+    blocos: BTreeMap<u32, Bloco>,
+    /// Próxima altura a entregar para aplicação.
+    proxima: u32,
+    /// Peers que não serviram uma faixa e não recebem outra nesta sessão.
+    excluidos: HashSet<PeerId>,
+}
+
+impl Sessao {
+    fn fim(&self) -> u32 {
+        self.inicio + self.hashes.len() as u32 - 1
+    }
+
+    fn hash(&self, altura: u32) -> Option<&String> {
+        self.hashes.get(altura.checked_sub(self.inicio)? as usize)
+    }
+
+    /// Guarda o prefixo de uma faixa recebida que confere com os cabeçalhos
+    /// do alvo e devolve quantos blocos aproveitou.
+    fn guardar_faixa(&mut self, inicio: u32, quantidade: u32, blocos: Vec<Bloco>) -> u32 {
+        let mut recebidos = 0;
+        for (altura, bloco) in (inicio..inicio + quantidade).zip(blocos) {
+            let confere = self.hash(altura).is_some_and(|hash| {
+                bloco.cabecalho.indice == altura && &bloco.hash_atual == hash && &bloco.calcular_hash() == hash
+            });
+            if !confere {
+                break;
+            }
+            self.blocos.insert(altura, bloco);
+            recebidos += 1;
+        }
+        recebidos
+    }
+
+    /// Tira os blocos já em sequência, ou só o ramo completo quando ele
+    /// diverge da ponta local.
+    fn retirar_prontos(&mut self) -> Vec<Bloco> {
+        let completo = self.blocos.len() as u32 == self.fim() - self.proxima + 1;
+        let mut prontos = Vec::new();
+        if self.extensao || completo {
+            while let Some(bloco) = self.blocos.remove(&self.proxima) {
+                prontos.push(bloco);
+                self.proxima += 1;
+            }
+        }
+        prontos
+    }
+}
+
+/// Faixas de até `passo` blocos cobrindo as alturas de `inicio` a `fim`.
+fn dividir_em_faixas(inicio: u32, fim: u32, passo: u32) -> VecDeque<(u32, u32)> {
+    (inicio..=fim)
+        .step_by(passo as usize)
+        .map(|inicio| (inicio, passo.min(fim - inicio + 1)))
+        .collect()
+}
+
+pub struct GerenciadorSincronizacao {
+    hash_genesis: String,
+    peers: HashMap<PeerId, Peer>,
+    pendentes: HashMap<RequestId, (PeerId, Pedido)>,
+    sessao: Option<Sessao>,
+    /// Bloco órfão recebido por gossip seguido dos ancestrais já obtidos,
+    /// do mais novo para o mais antigo.
+    orfaos: Vec<Bloco>,
+}
+
+impl GerenciadorSincronizacao {
+    pub fn novo(hash_genesis: String) -> Self {
+        GerenciadorSincronizacao {
+            hash_genesis,
+            peers: HashMap::new(),
+            pendentes: HashMap::new(),
+            sessao: None,
+            orfaos: Vec::new(),
+        }
+    }
+
+    pub fn ao_conectar(&mut self, peer: PeerId, blockchain: &Blockchain, p2p: &mut P2PSwarm) {
+        self.peers.entry(peer).or_default();
+        self.pedir_status(peer, blockchain, p2p);
+    }
+
+    pub fn ao_desconectar(&mut self, peer: PeerId) {
+        self.peers.remove(&peer);
+        if self.sessao.as_ref().is_some_and(|s| s.alvo == peer) {
+            self.sessao = None;
+        }
+    }
+
+    /// Consulta a situação de todos os peers conectados, para alcançá-los
+    /// mesmo sem ter recebido os blocos por gossip (por exemplo, depois de
+    /// um período desligado).
+    pub fn consultar_peers(&mut self, blockchain: &Blockchain, p2p: &mut P2PSwarm) {
+        let peers: Vec<PeerId> = self.peers.keys().copied().collect();
+        for peer in peers {
+            self.pedir_status(peer, blockchain, p2p);
+        }
+    }
+
+    /// Responde a uma solicitação de um peer com os dados da cadeia local.
+    /// A situação que vem em um pedido de status também é aproveitada.
+    pub fn ao_receber_solicitacao(
+        &mut self,
+        peer: PeerId,
+        solicitacao: BlockchainRequest,
+        blockchain: &Blockchain,
+        p2p: &mut P2PSwarm,
+        config: &Config,
+    ) -> BlockchainResponse {
+        match solicitacao {
+            BlockchainRequest::Status(status) => {
+                self.registrar_status(peer, status);
+                self.avancar(blockchain, p2p, config);
+                BlockchainResponse::Status(StatusCadeia::da_blockchain(blockchain, &self.hash_genesis))
+            }
+            BlockchainRequest::Cabecalhos { inicio, quantidade } => BlockchainResponse::Cabecalhos(
+                faixa(blockchain, inicio, quantidade.min(MAX_CABECALHOS_POR_RESPOSTA))
+                    .map(|bloco| bloco.cabecalho.clone())
+                    .collect(),
+            ),
+            BlockchainRequest::Blocos { inicio, quantidade } => BlockchainResponse::Blocos(
+                faixa(blockchain, inicio, quantidade.min(MAX_BLOCOS_POR_RESPOSTA))
+                    .cloned()
+                    .collect(),
+            ),
+            BlockchainRequest::BlocoPorHash(hash) => {
+                BlockchainResponse::Bloco(blockchain.bloco_por_hash(&hash).cloned())
+            }
+        }
+    }
+
+    /// Processa a resposta de um peer. Devolve os blocos prontos para
+    /// aplicação com `Blockchain::reorganizar`, em ordem de altura.
+    pub fn ao_receber_resposta(
+        &mut self,
+        peer: PeerId,
+        id: RequestId,
+        resposta: BlockchainResponse,
+        blockchain: &Blockchain,
+        p2p: &mut P2PSwarm,
+        config: &Config,
+    ) -> Vec<Bloco> {
+        if let BlockchainResponse::Status(status) = resposta {
+            self.registrar_status(peer, status);
+            self.avancar(blockchain, p2p, config);
+            return Vec::new();
+        }
+        let Some((_, pedido)) = self.pendentes.remove(&id) else {
+            return Vec::new();
+        };
+        match (pedido, resposta) {
+            (Pedido::Cabecalhos, BlockchainResponse::Cabecalhos(cabecalhos)) => {
+                self.receber_cabecalhos(peer, cabecalhos, blockchain, p2p, config);
+                Vec::new()
+            }
+            (Pedido::Blocos { inicio, quantidade }, BlockchainResponse::Blocos(blocos)) => {
+                self.receber_blocos(peer, inicio, quantidade, blocos, p2p, config)
+            }
+            (Pedido::Ancestral, BlockchainResponse::Bloco(bloco)) => {
+                self.receber_ancestral(peer, bloco, blockchain, p2p)
+            }
+            (pedido, _) => {
+                self.ao_falhar_pedido(peer, pedido, blockchain, p2p, config);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Uma solicitação ficou sem resposta: a faixa volta para a fila e o
+    /// peer deixa de recebê-las nesta sessão.
+    pub fn ao_falhar(&mut self, peer: PeerId, id: RequestId, blockchain: &Blockchain, p2p: &mut P2PSwarm, config: &Config) {
+        if let Some((_, pedido)) = self.pendentes.remove(&id) {
+            self.ao_falhar_pedido(peer, pedido, blockchain, p2p, config);
+        }
+    }
+
+    /// Um bloco recebido por gossip não se encadeia à ponta local. Se está
+    /// adiante dela, falta uma faixa de blocos e a situação do peer é
+    /// consultada; senão, ele pode ser a ponta de outro ramo, e os
+    /// ancestrais são buscados por hash.
+    pub fn ao_receber_orfao(&mut self, peer: PeerId, bloco: Bloco, blockchain: &Blockchain, p2p: &mut P2PSwarm) {
+        if blockchain.bloco(bloco.cabecalho.indice).is_some_and(|local| local.hash_atual == bloco.hash_atual) {
+            return;
+        }
+        if bloco.cabecalho.indice > blockchain.altura() + 1 || !self.orfaos.is_empty() {
+            self.pedir_status(peer, blockchain, p2p);
+            return;
+        }
+        let hash_anterior = bloco.cabecalho.hash_anterior.clone();
+        self.orfaos.push(bloco);
+        self.solicitar(peer, Pedido::Ancestral, BlockchainRequest::BlocoPorHash(hash_anterior), p2p);
+    }
+
+    /// Os blocos entregues não puderam ser aplicados: a sessão é encerrada
+    /// sem voltar a buscar a ponta do alvo.
+    pub fn ao_rejeitar_ramo(&mut self) {
+        self.encerrar_sessao();
+        self.orfaos.clear();
+    }
+
+    /// Inicia uma sessão com o peer mais adiantado cuja ponta ainda não foi
+    /// avaliada, ou distribui as faixas pendentes da sessão em andamento.
+    pub fn avancar(&mut self, blockchain: &Blockchain, p2p: &mut P2PSwarm, config: &Config) {
+        if self.sessao.as_ref().is_some_and(|s| s.ancestral.is_some()) {
+            self.distribuir(p2p, config);
+            return;
+        }
+        if self.sessao.is_some() {
+            return;
+        }
+        let ponta_local = &blockchain.cadeia.last().unwrap().hash_atual;
+        let alvo = self
+            .peers
+            .iter()
+            .filter_map(|(peer, estado)| Some((peer, estado.status.as_ref()?, estado)))
+            .filter(|(_, status, estado)| {
+                status.altura >= blockchain.altura()
+                    && &status.hash_ponta != ponta_local
+                    && estado.ponta_avaliada.as_ref() != Some(&status.hash_ponta)
+            })
+            .max_by_key(|(_, status, _)| status.altura)
+            .map(|(peer, status, _)| (*peer, status.clone()));
+        let Some((alvo, status)) = alvo else {
+            return;
+        };
+
+        // Parte do último bloco final: nenhum ramo pode divergir antes dele
+        let inicio = blockchain.altura_finalizada();
+        println!("Sincronizando com o nó {} até a altura {}.", alvo, status.altura);
+        self.sessao = Some(Sessao {
+            alvo,
+            hash_alvo: status.hash_ponta,
+            altura_alvo: status.altura,
+            inicio,
+            hashes: Vec::new(),
+            ancestral: None,
+            extensao: false,
+            faixas: VecDeque::new(),
+            blocos: BTreeMap::new(),
+            proxima: 0,
+            excluidos: HashSet::new(),
+        });
+        self.pedir_cabecalhos(inicio, status.altura, p2p);
+    }
+
+    fn pedir_status(&mut self, peer: PeerId, blockchain: &Blockchain, p2p: &mut P2PSwarm) {
+        let status = StatusCadeia::da_blockchain(blockchain, &self.hash_genesis);
+        p2p.solicitar(&peer, BlockchainRequest::Status(status));
+    }
+
+    fn pedir_cabecalhos(&mut self, inicio: u32, altura_alvo: u32, p2p: &mut P2PSwarm) {
+        let Some(alvo) = self.sessao.as_ref().map(|s| s.alvo) else {
+            return;
+        };
+        let quantidade = (altura_alvo - inicio + 1).min(MAX_CABECALHOS_POR_RESPOSTA);
+        self.solicitar(alvo, Pedido::Cabecalhos, BlockchainRequest::Cabecalhos { inicio, quantidade }, p2p);
+    }
+
+    fn solicitar(&mut self, peer: PeerId, pedido: Pedido, solicitacao: BlockchainRequest, p2p: &mut P2PSwarm) {
+        let id = p2p.solicitar(&peer, solicitacao);
+        self.pendentes.insert(id, (peer, pedido));
+    }
+
+    fn registrar_status(&mut self, peer: PeerId, status: StatusCadeia) {
+        if status.hash_genesis == self.hash_genesis {
+            self.peers.entry(peer).or_default().status = Some(status);
+        }
+    }
+
+    /// Encerra a sessão sem voltar a buscar a ponta do alvo.
+    fn encerrar_sessao(&mut self) {
+        if let Some(sessao) = self.sessao.take() {
+            if let Some(estado) = self.peers.get_mut(&sessao.alvo) {
+                estado.ponta_avaliada = Some(sessao.hash_alvo);
+            }
+        }
+    }
+
+    fn ao_falhar_pedido(&mut self, peer: PeerId, pedido: Pedido, blockchain: &Blockchain, p2p: &mut P2PSwarm, config: &Config) {
+        match pedido {
+            Pedido::Cabecalhos => {
+                println!("O nó {} não enviou os cabeçalhos pedidos; sincronização interrompida.", peer);
+                self.encerrar_sessao();
+                self.avancar(blockchain, p2p, config);
+            }
+            Pedido::Blocos { inicio, quantidade } => {
+                if let Some(sessao) = self.sessao.as_mut() {
+                    sessao.faixas.push_front((inicio, quantidade));
+                    sessao.excluidos.insert(peer);
+                }
+                self.distribuir(p2p, config);
+            }
+            Pedido::Ancestral => self.orfaos.clear(),
+        }
+    }
+
+    fn receber_cabecalhos(
+        &mut self,
+        peer: PeerId,
+        cabecalhos: Vec<CabecalhoBloco>,
+        blockchain: &Blockchain,
+        p2p: &mut P2PSwarm,
+        config: &Config,
+    ) {
+        let Some(sessao) = self.sessao.as_mut().filter(|s| s.alvo == peer && s.ancestral.is_none()) else {
+            return;
+        };
+
+        // Os cabeçalhos precisam ser consecutivos e encadeados entre si, ao
+        // lote anterior e, no primeiro lote, ao último bloco final local
+        let mut anterior = sessao.hashes.last().cloned();
+        for (altura, cabecalho) in (sessao.inicio + sessao.hashes.len() as u32..).zip(&cabecalhos) {
+            let hash = cabecalho.calcular_hash();
+            let encadeado = match &anterior {
+                Some(hash_anterior) => &cabecalho.hash_anterior == hash_anterior,
+                None => blockchain.bloco(altura).is_some_and(|local| local.hash_atual == hash),
+            };
+            if cabecalho.indice != altura || !encadeado {
+                println!("O nó {} enviou cabeçalhos que não se encadeiam; sincronização interrompida.", peer);
+                self.encerrar_sessao();
+                self.avancar(blockchain, p2p, config);
+                return;
+            }
+            sessao.hashes.push(hash.clone());
+            anterior = Some(hash);
+        }
+
+        if !cabecalhos.is_empty() && sessao.fim() < sessao.altura_alvo {
+            let (proximo, altura_alvo) = (sessao.fim() + 1, sessao.altura_alvo);
+            self.pedir_cabecalhos(proximo, altura_alvo, p2p);
+            return;
+        }
+        if sessao.hashes.is_empty() {
+            self.encerrar_sessao();
+            self.avancar(blockchain, p2p, config);
+            return;
+        }
+
+        // Todos os cabeçalhos chegaram: o ancestral comum é o último da
+        // sequência inicial que a cadeia local também tem
+        let fim = sessao.fim();
+        sessao.altura_alvo = fim;
+        let comuns = sessao
+            .hashes
+            .iter()
+            .zip(sessao.inicio..)
+            .take_while(|(hash, altura)| blockchain.bloco(*altura).is_some_and(|local| &&local.hash_atual == hash))
+            .count() as u32;
+        let ancestral = sessao.inicio + comuns - 1;
+        if ancestral == fim {
+            self.encerrar_sessao();
+            self.avancar(blockchain, p2p, config);
+            return;
+        }
+        sessao.ancestral = Some(ancestral);
+        sessao.extensao = ancestral == blockchain.altura();
+        sessao.proxima = ancestral + 1;
+        sessao.faixas = dividir_em_faixas(ancestral + 1, fim, config.sincronizacao.blocos_por_pedido);
+        self.distribuir(p2p, config);
+    }
+
+    /// Pede as faixas pendentes aos peers que as têm, respeitando o limite
+    /// de pedidos simultâneos por peer. Se nenhum peer puder servir o que
+    /// falta, a sessão é abandonada e retomada na próxima consulta.
+    fn distribuir(&mut self, p2p: &mut P2PSwarm, config: &Config) {
+        let Some(sessao) = self.sessao.as_mut() else {
+            return;
+        };
+        let mut em_andamento: HashMap<PeerId, usize> = HashMap::new();
+        for (peer, pedido) in self.pendentes.values() {
+            if let Pedido::Blocos { .. } = pedido {
+                *em_andamento.entry(*peer).or_default() += 1;
+            }
+        }
+
+        let mut pedidos = Vec::new();
+        while let Some(&(inicio, quantidade)) = sessao.faixas.front() {
+            let fim = inicio + quantidade - 1;
+            let peer = self
+                .peers
+                .iter()
+                .filter(|(peer, estado)| {
+                    !sessao.excluidos.contains(peer)
+                        && estado.status.as_ref().is_some_and(|s| s.altura >= fim)
+                        && em_andamento.get(peer).copied().unwrap_or(0) < config.sincronizacao.pedidos_por_peer
+                })
+                .min_by_key(|(peer, _)| em_andamento.get(peer).copied().unwrap_or(0))
+                .map(|(peer, _)| *peer);
+            let Some(peer) = peer else {
+                break;
+            };
+            sessao.faixas.pop_front();
+            *em_andamento.entry(peer).or_default() += 1;
+            pedidos.push((peer, inicio, quantidade));
+        }
+
+        if pedidos.is_empty() && !sessao.faixas.is_empty() && em_andamento.is_empty() {
+            println!("Nenhum peer disponível para os blocos que faltam; sincronização adiada.");
+            self.sessao = None;
+            return;
+        }
+        for (peer, inicio, quantidade) in pedidos {
+            self.solicitar(peer, Pedido::Blocos { inicio, quantidade }, BlockchainRequest::Blocos { inicio, quantidade }, p2p);
+        }
+    }
+
+    fn receber_blocos(
+        &mut self,
+        peer: PeerId,
+        inicio: u32,
+        quantidade: u32,
+        blocos: Vec<Bloco>,
+        p2p: &mut P2PSwarm,
+        config: &Config,
+    ) -> Vec<Bloco> {
+        let Some(sessao) = self.sessao.as_mut() else {
+            return Vec::new();
+        };
+
+        // Aproveita o prefixo que confere com os cabeçalhos do alvo; o resto
+        // da faixa volta para a fila
+        let recebidos = sessao.guardar_faixa(inicio, quantidade, blocos);
+        if recebidos < quantidade {
+            sessao.faixas.push_front((inicio + recebidos, quantidade - recebidos));
+            sessao.excluidos.insert(peer);
+        }
+
+        let prontos = sessao.retirar_prontos();
+        if sessao.proxima > sessao.fim() {
+            self.encerrar_sessao();
+        } else {
+            self.distribuir(p2p, config);
+        }
+        prontos
+    }
+
+    fn receber_ancestral(&mut self, peer: PeerId, bloco: Option<Bloco>, blockchain: &Blockchain, p2p: &mut P2PSwarm) -> Vec<Bloco> {
+        let Some(filho) = self.orfaos.last() else {
+            return Vec::new();
+        };
+        let Some(bloco) = bloco.filter(|b| b.hash_atual == filho.cabecalho.hash_anterior && b.calcular_hash() == b.hash_atual) else {
+            self.orfaos.clear();
+            return Vec::new();
+        };
+
+        // O ramo se encadeia a um bloco local: pode ir para a escolha de
+        // bifurcação
+        let encadeado = bloco
+            .cabecalho
+            .indice
+            .checked_sub(1)
+            .and_then(|altura| blockchain.bloco(altura))
+            .is_some_and(|local| local.hash_atual == bloco.cabecalho.hash_anterior);
+        let hash_anterior = bloco.cabecalho.hash_anterior.clone();
+        self.orfaos.push(bloco);
+        if encadeado {
+            let mut ramo = std::mem::take(&mut self.orfaos);
+            ramo.reverse();
+            return ramo;
+        }
+        if self.orfaos.len() >= PROFUNDIDADE_MAXIMA_ORFAOS {
+            self.orfaos.clear();
+            self.pedir_status(peer, blockchain, p2p);
+            return Vec::new();
+        }
+        self.solicitar(peer, Pedido::Ancestral, BlockchainRequest::BlocoPorHash(hash_anterior), p2p);
+        Vec::new()
+    }
+}
+
+/// Blocos locais consecutivos a partir de `inicio`, até `quantidade`.
+fn faixa(blockchain: &Blockchain, inicio: u32, quantidade: u32) -> impl Iterator<Item = &Bloco> {
+    (inicio..inicio.saturating_add(quantidade)).map_while(move |altura| blockchain.bloco(altura))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::EspecificacaoGenesis;
+
+    /// Ramo encadeado de `quantidade` blocos depois de `inicio`, sem
+    /// assinatura: a sessão só confere alturas e hashes.
+    fn ramo(inicio: u32, quantidade: u32) -> Vec<Bloco> {
+        let mut hash_anterior = String::from("ancestral");
+        (inicio + 1..=inicio + quantidade)
+            .map(|altura| {
+                let mut bloco = Bloco::novo_bloco("uniblockchain", altura, hash_anterior.clone(), Vec::new(), String::new(), None);
+                bloco.hash_atual = bloco.calcular_hash();
+                hash_anterior = bloco.hash_atual.clone();
+                bloco
+            })
+            .collect()
+    }
+
+    /// Sessão com os cabeçalhos do ramo, partindo do ancestral em `inicio`.
+    fn sessao(ramo: &[Bloco], extensao: bool) -> Sessao {
+        let inicio = ramo[0].cabecalho.indice;
+        let fim = inicio + ramo.len() as u32 - 1;
+        Sessao {
+            alvo: PeerId::random(),
+            hash_alvo: ramo.last().unwrap().hash_atual.clone(),
+            altura_alvo: fim,
+            inicio,
+            hashes: ramo.iter().map(|bloco| bloco.hash_atual.clone()).collect(),
+            ancestral: Some(inicio - 1),
+            extensao,
+            faixas: dividir_em_faixas(inicio, fim, 2),
+            blocos: BTreeMap::new(),
+            proxima: inicio,
+            excluidos: HashSet::new(),
+        }
+    }
+
+    fn alturas(blocos: &[Bloco]) -> Vec<u32> {
+        blocos.iter().map(|bloco| bloco.cabecalho.indice).collect()
+    }
+
+    #[test]
+    fn faixas_cobrem_o_ramo_sem_sobrepor() {
+        assert_eq!(dividir_em_faixas(5, 14, 4), [(5, 4), (9, 4), (13, 2)]);
+        assert_eq!(dividir_em_faixas(5, 12, 4), [(5, 4), (9, 4)]);
+        assert_eq!(dividir_em_faixas(1, 1, 32), [(1, 1)]);
+    }
+
+    #[test]
+    fn extensao_entrega_os_blocos_assim_que_ficam_em_sequencia() {
+        let ramo = ramo(6, 4);
+        let mut sessao = sessao(&ramo, true);
+
+        assert_eq!(sessao.guardar_faixa(9, 2, ramo[2..].to_vec()), 2);
+        assert!(sessao.retirar_prontos().is_empty());
+        assert_eq!(sessao.guardar_faixa(7, 2, ramo[..2].to_vec()), 2);
+        assert_eq!(alturas(&sessao.retirar_prontos()), [7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn ramo_divergente_so_e_entregue_completo() {
+        let ramo = ramo(6, 4);
+        let mut sessao = sessao(&ramo, false);
+
+        sessao.guardar_faixa(7, 2, ramo[..2].to_vec());
+        assert!(sessao.retirar_prontos().is_empty());
+        sessao.guardar_faixa(9, 2, ramo[2..].to_vec());
+        assert_eq!(alturas(&sessao.retirar_prontos()), [7, 8, 9, 10]);
+    }
+
+    #[test]
+    fn faixa_so_aproveita_o_prefixo_que_confere_com_os_cabecalhos() {
+        let ramo = ramo(6, 4);
+        let mut sessao = sessao(&ramo, true);
+        let mut adulterado = ramo[1].clone();
+        adulterado.cabecalho.timestamp += Duration::seconds(1);
+
+        let recebidos = sessao.guardar_faixa(7, 3, vec![ramo[0].clone(), adulterado, ramo[2].clone()]);
+        assert_eq!(recebidos, 1);
+        assert_eq!(alturas(&sessao.retirar_prontos()), [7]);
+
+        // Blocos fora de ordem ou além dos cabeçalhos também param a faixa
+        assert_eq!(sessao.guardar_faixa(8, 2, vec![ramo[2].clone(), ramo[1].clone()]), 0);
+        assert_eq!(sessao.guardar_faixa(10, 2, vec![ramo[3].clone(), ramo[0].clone()]), 1);
+    }
+
+    #[test]
+    fn faixa_servida_para_na_ponta_local() {
+        let blockchain = Blockchain::nova_blockchain(&EspecificacaoGenesis::default());
+        assert_eq!(faixa(&blockchain, 0, 10).count(), 1);
+        assert_eq!(faixa(&blockchain, 1, 10).count(), 0);
+        assert_eq!(faixa(&blockchain, u32::MAX, 10).count(), 0);
+    }
+}
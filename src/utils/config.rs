@@ -1,12 +1,36 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use chrono::{DateTime, Utc};
 use rsa::RsaPublicKey;
 use rsa::pkcs8::DecodePublicKey;
 use std::fs;
+use std::path::PathBuf;
 use toml::Value;
 
+use crate::armazenamento::{ConfigArmazenamento, PoliticaFsync};
+use crate::rede::{ConfigSincronizacao, MAX_BLOCOS_POR_RESPOSTA};
+use crate::blockchain::{
+    Arredondamento, ConfigConsenso, ConfigFinalidade, ConfigInstantaneo, ConfigProducao, ConfigGovernanca, ConfigMempool, Emissor, EspecificacaoGenesis, Papel, ParametrosCadeia, PoliticaAvaliacao, PoliticaDespejo, RegraRecuperacao, RegrasValidacao, TipoMedia,
+};
+
+/// Identificador usado quando a especificação do gênesis não define
+/// `id_cadeia`.
+pub const ID_CADEIA_PADRAO: &str = "uniblockchain";
+
+/// Arquivo da especificação do gênesis quando o `config.toml` não define
+/// `arquivo_genesis`.
+pub const ARQUIVO_GENESIS_PADRAO: &str = "genesis.toml";
+
 #[derive(Debug)]
 pub struct Config {
-    pub chaves_publicas: HashMap<u32, RsaPublicKey>,
+    /// Identidade da cadeia, parâmetros de consenso, emissores e regras
+    /// acadêmicas, lidos da especificação do gênesis.
+    pub genesis: EspecificacaoGenesis,
+    pub armazenamento: ConfigArmazenamento,
+    pub mempool: ConfigMempool,
+    pub producao: ConfigProducao,
+    pub finalidade: ConfigFinalidade,
+    pub instantaneo: ConfigInstantaneo,
+    pub sincronizacao: ConfigSincronizacao,
 }
 
 impl Config {
@@ -14,24 +38,395 @@ impl Config {
         let conteudo = fs::read_to_string("config.toml").expect("Não foi possível ler o arquivo config.toml");
         let value = conteudo.parse::<Value>().expect("Erro ao parsear o arquivo config.toml");
 
-        let mut chaves_publicas = HashMap::new();
+        let arquivo_genesis = value
+            .get("arquivo_genesis")
+            .and_then(|v| v.as_str())
+            .unwrap_or(ARQUIVO_GENESIS_PADRAO);
+        let conteudo_genesis = fs::read_to_string(arquivo_genesis)
+            .unwrap_or_else(|_| panic!("Não foi possível ler a especificação do gênesis {}", arquivo_genesis));
+        let genesis = Self::carregar_genesis(
+            &conteudo_genesis.parse::<Value>().expect("Erro ao parsear a especificação do gênesis"),
+        );
+
+        // Seções que passaram para a especificação do gênesis não podem ser
+        // ignoradas em silêncio em um config.toml antigo
+        for secao in ["emissores", "avaliacao", "validacao"] {
+            if value.get(secao).is_some() {
+                panic!("[{}] pertence à especificação do gênesis ({}), não ao config.toml", secao, arquivo_genesis);
+            }
+        }
+
+        let mut armazenamento = ConfigArmazenamento::default();
+
+        if let Some(secao) = value.get("armazenamento").and_then(|v| v.as_table()) {
+            if let Some(diretorio) = secao.get("diretorio").and_then(|v| v.as_str()) {
+                armazenamento.diretorio = PathBuf::from(diretorio);
+            }
+            if let Some(politica) = secao.get("politica_fsync") {
+                armazenamento.politica_fsync = match (politica.as_str(), politica.as_integer()) {
+                    (Some("sempre"), _) => PoliticaFsync::Sempre,
+                    (Some("nunca"), _) => PoliticaFsync::Nunca,
+                    (_, Some(n)) if n > 0 => PoliticaFsync::ACada(n as u32),
+                    _ => panic!("politica_fsync inválida: use \"sempre\", \"nunca\" ou um número de blocos"),
+                };
+            }
+            if let Some(tamanho) = secao.get("tamanho_maximo_segmento").and_then(|v| v.as_integer()) {
+                armazenamento.tamanho_maximo_segmento = tamanho as u64;
+            }
+        }
+
+        let mut producao = ConfigProducao::default();
+
+        if let Some(secao) = value.get("producao").and_then(|v| v.as_table()) {
+            if let Some(segundos) = secao.get("intervalo_segundos").and_then(|v| v.as_integer()) {
+                if segundos < 0 {
+                    panic!("intervalo_segundos inválido: use um número não negativo");
+                }
+                producao.intervalo = chrono::Duration::seconds(segundos);
+            }
+            if let Some(maximo) = secao.get("max_transacoes_por_bloco").and_then(|v| v.as_integer()) {
+                if maximo < 1 {
+                    panic!("max_transacoes_por_bloco inválido: use um número positivo");
+                }
+                producao.max_transacoes_por_bloco = maximo as usize;
+            }
+            if let Some(pular) = secao.get("pular_blocos_vazios").and_then(|v| v.as_bool()) {
+                producao.pular_blocos_vazios = pular;
+            }
+        }
+
+        let mut finalidade = ConfigFinalidade::default();
+
+        if let Some(secao) = value.get("finalidade").and_then(|v| v.as_table()) {
+            if let Some(segundos) = secao.get("tempo_limite_rodada_segundos").and_then(|v| v.as_integer()) {
+                if segundos < 1 {
+                    panic!("tempo_limite_rodada_segundos inválido: use um número positivo");
+                }
+                finalidade.tempo_limite_rodada = chrono::Duration::seconds(segundos);
+            }
+        }
+
+        let mut instantaneo = ConfigInstantaneo::default();
+
+        if let Some(secao) = value.get("instantaneo").and_then(|v| v.as_table()) {
+            instantaneo.arquivo = secao.get("arquivo").and_then(|v| v.as_str()).map(PathBuf::from);
+            instantaneo.hash_checkpoint = secao.get("hash_checkpoint").and_then(|v| v.as_str()).map(String::from);
+            if instantaneo.arquivo.is_some() && instantaneo.hash_checkpoint.is_none() {
+                panic!("[instantaneo] precisa de hash_checkpoint para verificar o arquivo");
+            }
+        }
+
+        let mut sincronizacao = ConfigSincronizacao::default();
 
-        if let Some(autoridades) = value.get("autoridades").and_then(|v| v.as_table()) {
-            for (id_str, chave_pem) in autoridades {
+        if let Some(secao) = value.get("sincronizacao").and_then(|v| v.as_table()) {
+            if let Some(blocos) = secao.get("blocos_por_pedido").and_then(|v| v.as_integer()) {
+                if blocos < 1 || blocos > MAX_BLOCOS_POR_RESPOSTA as i64 {
+                    panic!("blocos_por_pedido inválido: use um número de 1 a {}", MAX_BLOCOS_POR_RESPOSTA);
+                }
+                sincronizacao.blocos_por_pedido = blocos as u32;
+            }
+            if let Some(pedidos) = secao.get("pedidos_por_peer").and_then(|v| v.as_integer()) {
+                if pedidos < 1 {
+                    panic!("pedidos_por_peer inválido: use um número positivo");
+                }
+                sincronizacao.pedidos_por_peer = pedidos as usize;
+            }
+            if let Some(segundos) = secao.get("intervalo_status_segundos").and_then(|v| v.as_integer()) {
+                if segundos < 1 {
+                    panic!("intervalo_status_segundos inválido: use um número positivo");
+                }
+                sincronizacao.intervalo_status = chrono::Duration::seconds(segundos);
+            }
+        }
+
+        let mempool = value
+            .get("mempool")
+            .and_then(|v| v.as_table())
+            .map(Self::carregar_config_mempool)
+            .unwrap_or_default();
+
+        Config {
+            genesis,
+            armazenamento,
+            mempool,
+            producao,
+            finalidade,
+            instantaneo,
+            sincronizacao,
+        }
+    }
+
+    /// Lê a especificação do gênesis. Ela precisa ser idêntica em todos os
+    /// nós da rede, já que o hash do gênesis a compromete.
+    fn carregar_genesis(value: &Value) -> EspecificacaoGenesis {
+        let id_cadeia = value
+            .get("id_cadeia")
+            .and_then(|v| v.as_str())
+            .unwrap_or(ID_CADEIA_PADRAO)
+            .to_string();
+
+        let mut parametros_cadeia = ParametrosCadeia::default();
+
+        if let Some(secao) = value.get("parametros_cadeia").and_then(|v| v.as_table()) {
+            if let Some(tamanho) = secao.get("tamanho_maximo_bloco").and_then(|v| v.as_integer()) {
+                if tamanho < 1 {
+                    panic!("tamanho_maximo_bloco inválido: use um número positivo");
+                }
+                parametros_cadeia.tamanho_maximo_bloco = tamanho as usize;
+            }
+            if let Some(maximo) = secao.get("max_transacoes_bloco").and_then(|v| v.as_integer()) {
+                if maximo < 1 {
+                    panic!("max_transacoes_bloco inválido: use um número positivo");
+                }
+                parametros_cadeia.max_transacoes_bloco = maximo as usize;
+            }
+            if let Some(segundos) = secao.get("deriva_maxima_relogio_segundos").and_then(|v| v.as_integer()) {
+                if segundos < 0 {
+                    panic!("deriva_maxima_relogio_segundos inválido: use um número não negativo");
+                }
+                parametros_cadeia.deriva_maxima_relogio = chrono::Duration::seconds(segundos);
+            }
+        }
+
+        let mut autoridades = BTreeMap::new();
+
+        if let Some(tabela) = value.get("autoridades").and_then(|v| v.as_table()) {
+            for (id_str, chave_pem) in tabela {
                 let id_autoridade: u32 = id_str.parse().expect("ID da autoridade inválido");
                 let chave_pem = chave_pem.as_str().expect("Chave PEM inválida");
 
                 let chave_publica = RsaPublicKey::from_public_key_pem(chave_pem)
                     .expect("Erro ao carregar chave pública da autoridade");
 
-                chaves_publicas.insert(id_autoridade, chave_publica);
+                autoridades.insert(id_autoridade, chave_publica);
+            }
+        }
+
+        let mut pesos_autoridades = BTreeMap::new();
+
+        if let Some(pesos) = value.get("pesos_autoridades").and_then(|v| v.as_table()) {
+            for (id_str, peso) in pesos {
+                let id_autoridade: u32 = id_str.parse().expect("ID da autoridade inválido");
+                let peso = peso.as_integer().expect("Peso da autoridade inválido");
+
+                pesos_autoridades.insert(id_autoridade, peso as u64);
+            }
+        }
+
+        let mut consenso = ConfigConsenso::default();
+
+        if let Some(secao) = value.get("consenso").and_then(|v| v.as_table()) {
+            if let Some(segundos) = secao.get("tempo_limite_slot_segundos").and_then(|v| v.as_integer()) {
+                if segundos < 0 {
+                    panic!("tempo_limite_slot_segundos inválido: use um número não negativo");
+                }
+                consenso.tempo_limite_slot = chrono::Duration::seconds(segundos);
+            }
+        }
+
+        let mut governanca = ConfigGovernanca::default();
+
+        if let Some(secao) = value.get("governanca").and_then(|v| v.as_table()) {
+            if let Some(percentual) = secao.get("quorum_percentual").and_then(|v| v.as_integer()) {
+                if !(1..=100).contains(&percentual) {
+                    panic!("quorum_percentual inválido: use um valor de 1 a 100");
+                }
+                governanca.quorum_percentual = percentual as u32;
+            }
+        }
+
+        if autoridades.is_empty() {
+            panic!("A especificação do gênesis precisa de ao menos uma autoridade");
+        }
+
+        let mut emissores = BTreeMap::new();
+
+        if let Some(tabela) = value.get("emissores").and_then(|v| v.as_table()) {
+            for (id_str, secao) in tabela {
+                let id_emissor: u32 = id_str.parse().expect("ID do emissor inválido");
+                let secao = secao.as_table().expect("Emissor deve ser uma tabela");
+                emissores.insert(id_emissor, Self::carregar_emissor(secao));
             }
         }
 
-        Config { chaves_publicas }
+        let politica_avaliacao = value
+            .get("avaliacao")
+            .and_then(|v| v.as_table())
+            .map(Self::carregar_politica_avaliacao)
+            .unwrap_or_default();
+
+        let regras_validacao = value
+            .get("validacao")
+            .and_then(|v| v.as_table())
+            .map(Self::carregar_regras_validacao)
+            .unwrap_or_default();
+
+        let instituicao = value
+            .get("instituicao")
+            .and_then(|v| v.as_str())
+            .expect("Nome da instituição ausente na especificação do gênesis")
+            .to_string();
+
+        let mut timestamp = EspecificacaoGenesis::default().timestamp;
+
+        if let Some(valor) = value.get("timestamp") {
+            let texto = valor
+                .as_datetime()
+                .map(|data_hora| data_hora.to_string())
+                .or_else(|| valor.as_str().map(String::from))
+                .expect("timestamp do gênesis inválido");
+            timestamp = DateTime::parse_from_rfc3339(&texto)
+                .expect("timestamp do gênesis inválido: use data, hora e fuso, como 2024-01-01T00:00:00Z")
+                .with_timezone(&Utc);
+        }
+
+        EspecificacaoGenesis {
+            id_cadeia,
+            instituicao,
+            timestamp,
+            autoridades,
+            pesos_autoridades,
+            parametros_cadeia,
+            consenso,
+            governanca,
+            emissores,
+            politica_avaliacao,
+            regras_validacao,
+        }
+    }
+
+    fn carregar_emissor(secao: &toml::Table) -> Emissor {
+        let chave_pem = secao
+            .get("chave_publica")
+            .and_then(|v| v.as_str())
+            .expect("Chave pública do emissor ausente");
+        let chave_publica = RsaPublicKey::from_public_key_pem(chave_pem)
+            .expect("Erro ao carregar chave pública do emissor");
+
+        let papel = match secao.get("papel").and_then(|v| v.as_str()) {
+            Some("secretaria") => Papel::Secretaria,
+            Some("professor") => Papel::Professor,
+            _ => panic!("papel do emissor inválido: use \"secretaria\" ou \"professor\""),
+        };
+
+        let disciplinas: HashSet<String> = secao
+            .get("disciplinas")
+            .and_then(|v| v.as_array())
+            .map(|codigos| {
+                codigos
+                    .iter()
+                    .map(|c| c.as_str().expect("Código de disciplina inválido").to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Emissor { chave_publica, papel, disciplinas }
+    }
+
+    fn carregar_politica_avaliacao(secao: &toml::Table) -> PoliticaAvaliacao {
+        let mut politica = PoliticaAvaliacao::default();
+
+        if let Some(media) = secao.get("media").and_then(|v| v.as_str()) {
+            politica.tipo_media = match media {
+                "aritmetica" => TipoMedia::Aritmetica,
+                "ponderada" => {
+                    let mut pesos = HashMap::new();
+                    if let Some(tabela) = secao.get("pesos").and_then(|v| v.as_table()) {
+                        for (tipo, peso) in tabela {
+                            let peso = Self::numero(peso).expect("Peso de nota inválido");
+                            pesos.insert(tipo.clone(), peso);
+                        }
+                    }
+                    TipoMedia::Ponderada(pesos)
+                }
+                outro => panic!("Tipo de média inválido: {}", outro),
+            };
+        }
+        if let Some(descartar) = secao.get("descartar_menor").and_then(|v| v.as_bool()) {
+            politica.descartar_menor = descartar;
+        }
+        if let Some(tipo) = secao.get("tipo_recuperacao").and_then(|v| v.as_str()) {
+            politica.tipo_recuperacao = Some(tipo.to_string());
+        }
+        if let Some(regra) = secao.get("regra_recuperacao").and_then(|v| v.as_str()) {
+            politica.regra_recuperacao = match regra {
+                "substitui_media" => RegraRecuperacao::SubstituiMedia,
+                "substitui_menor" => RegraRecuperacao::SubstituiMenor,
+                "media_com_recuperacao" => RegraRecuperacao::MediaComRecuperacao,
+                outra => panic!("Regra de recuperação inválida: {}", outra),
+            };
+        }
+        if let Some(arredondamento) = secao.get("arredondamento") {
+            politica.arredondamento = match (arredondamento.as_str(), arredondamento.as_integer()) {
+                (Some("nenhum"), _) => Arredondamento::Nenhum,
+                (Some("meio_ponto"), _) => Arredondamento::MeioPonto,
+                (_, Some(casas)) if casas >= 0 => Arredondamento::CasasDecimais(casas as u32),
+                _ => panic!("arredondamento inválido: use \"nenhum\", \"meio_ponto\" ou um número de casas decimais"),
+            };
+        }
+
+        if let Some(media) = secao.get("media_minima").and_then(Self::numero) {
+            politica.media_minima = media;
+        }
+        if let Some(frequencia) = secao.get("frequencia_minima").and_then(Self::numero) {
+            politica.frequencia_minima = frequencia;
+        }
+
+        politica
+    }
+
+    fn carregar_regras_validacao(secao: &toml::Table) -> RegrasValidacao {
+        let mut regras = RegrasValidacao::default();
+
+        if let Some(nota) = secao.get("nota_minima").and_then(Self::numero) {
+            regras.nota_minima = nota;
+        }
+        if let Some(nota) = secao.get("nota_maxima").and_then(Self::numero) {
+            regras.nota_maxima = nota;
+        }
+        if let Some(semestres) = secao.get("semestres_por_ano").and_then(|v| v.as_integer()) {
+            if !(1..=12).contains(&semestres) {
+                panic!("semestres_por_ano inválido: use um valor de 1 a 12");
+            }
+            regras.semestres_por_ano = semestres as u8;
+        }
+        if let Some(ano) = secao.get("ano_minimo").and_then(|v| v.as_integer()) {
+            regras.ano_minimo = ano as i32;
+        }
+        if let Some(dias) = secao.get("tolerancia_periodo_dias").and_then(|v| v.as_integer()) {
+            regras.tolerancia_periodo_dias = dias;
+        }
+
+        regras
+    }
+
+    fn carregar_config_mempool(secao: &toml::Table) -> ConfigMempool {
+        let mut mempool = ConfigMempool::default();
+
+        if let Some(capacidade) = secao.get("capacidade").and_then(|v| v.as_integer()) {
+            if capacidade < 1 {
+                panic!("capacidade do mempool inválida: use um número positivo");
+            }
+            mempool.capacidade = capacidade as usize;
+        }
+        if let Some(politica) = secao.get("politica_despejo").and_then(|v| v.as_str()) {
+            mempool.politica_despejo = match politica {
+                "recusar" => PoliticaDespejo::Recusar,
+                "descartar_mais_antiga" => PoliticaDespejo::DescartarMaisAntiga,
+                _ => panic!("politica_despejo inválida: use \"recusar\" ou \"descartar_mais_antiga\""),
+            };
+        }
+        if let Some(horas) = secao.get("validade_horas").and_then(|v| v.as_integer()) {
+            mempool.validade = chrono::Duration::hours(horas);
+        }
+
+        mempool
     }
 
-    pub fn obter_chave_publica(&self, id_autoridade: u32) -> Option<&RsaPublicKey> {
-        self.chaves_publicas.get(&id_autoridade)
+    fn numero(valor: &Value) -> Option<f32> {
+        valor
+            .as_float()
+            .or_else(|| valor.as_integer().map(|n| n as f64))
+            .map(|n| n as f32)
     }
 }
@@ -1,6 +1,9 @@
 
+use chrono::{DateTime, NaiveDate, Utc};
 use thiserror::Error;
 
+use crate::blockchain::SituacaoDisciplina;
+
 #[derive(Error, Debug)]
 pub enum BlocoErro {
     #[error("Autoridade desconhecida")]
@@ -9,4 +12,194 @@ pub enum BlocoErro {
     AssinaturaInvalida,
     #[error("Hash anterior não corresponde")]
     HashAnteriorNaoCorresponde,
+    #[error("Índice do bloco inválido (esperado {esperado}, encontrado {encontrado})")]
+    IndiceInvalido { esperado: u32, encontrado: u32 },
+    #[error("Bloco de índice {indice} não ocupa a altura {altura} da cadeia")]
+    AlturaIncorreta { altura: u32, indice: u32 },
+    #[error("Hash do bloco não corresponde ao conteúdo")]
+    HashInvalido,
+    #[error("Raiz de Merkle não corresponde às transações")]
+    RaizMerkleInvalida,
+    #[error("Raiz de estado não corresponde ao estado resultante do bloco")]
+    RaizEstadoInvalida,
+    #[error("Timestamp anterior ao do bloco precedente")]
+    TimestampRetroativo,
+    #[error("Timestamp do bloco ({0}) à frente do relógio local além da deriva permitida")]
+    TimestampFuturo(DateTime<Utc>),
+    #[error("Bloco com {tamanho} bytes excede o tamanho máximo de {maximo}")]
+    TamanhoExcedido { tamanho: usize, maximo: usize },
+    #[error("Bloco com {quantidade} transações excede o máximo de {maximo}")]
+    TransacoesDemais { quantidade: usize, maximo: usize },
+    #[error("Versão de formato de bloco não suportada: {0}")]
+    VersaoNaoSuportada(u32),
+    #[error("Bloco pertence a outra cadeia")]
+    CadeiaIncorreta,
+    #[error("Bloco gênesis inválido")]
+    GenesisInvalido,
+    #[error("Autoridade {0} suspensa por equivocação")]
+    AutoridadeSuspensa(u32),
+    #[error("Autoridade {id_autoridade} produziu o bloco {indice} fora da sua vez (autoridade da vez: {esperada})")]
+    ForaDoTurno { id_autoridade: u32, indice: u32, esperada: u32 },
+    #[error("Nenhuma transação válida pendente para o bloco")]
+    BlocoVazio,
+    #[error("Transação {id_transacao} inválida: {erro}")]
+    TransacaoInvalida {
+        id_transacao: u32,
+        #[source]
+        erro: TransacaoErro,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum TransacaoErro {
+    #[error("Média da disciplina {codigo} não confere com a política de avaliação (gravada {gravada}, calculada {calculada})")]
+    MediaInconsistente {
+        codigo: String,
+        gravada: f32,
+        calculada: f32,
+    },
+    #[error("Frequência da disciplina {codigo} não confere com as aulas registradas (gravada {gravada}, calculada {calculada})")]
+    FrequenciaInconsistente {
+        codigo: String,
+        gravada: f32,
+        calculada: f32,
+    },
+    #[error("Situação da disciplina {codigo} contradiz as notas registradas (gravada {gravada}, esperada {esperada})")]
+    SituacaoContraditoria {
+        codigo: String,
+        gravada: SituacaoDisciplina,
+        esperada: SituacaoDisciplina,
+    },
+    #[error("Campo obrigatório vazio: {0}")]
+    CampoVazio(&'static str),
+    #[error("Campo {0} não é um número finito")]
+    ValorNaoNumerico(&'static str),
+    #[error("Chave pública em PEM inválida")]
+    ChavePublicaInvalida,
+    #[error("Encerramento da disciplina {0} não pode manter a situação 'cursando'")]
+    EncerramentoEmCurso(String),
+    #[error("Estudante {0} desconhecido")]
+    EstudanteDesconhecido(u32),
+    #[error("Estudante {0} já matriculado")]
+    EstudanteJaMatriculado(u32),
+    #[error("Período {id_periodo} não registrado para o estudante {id_estudante}")]
+    PeriodoDesconhecido { id_estudante: u32, id_periodo: u32 },
+    #[error("Período {id_periodo} já registrado para o estudante {id_estudante}")]
+    PeriodoJaRegistrado { id_estudante: u32, id_periodo: u32 },
+    #[error("Disciplina {codigo} não matriculada no período {id_periodo}")]
+    DisciplinaDesconhecida { id_periodo: u32, codigo: String },
+    #[error("Disciplina {codigo} já matriculada no período {id_periodo}")]
+    DisciplinaJaMatriculada { id_periodo: u32, codigo: String },
+    #[error("Disciplina {0} já encerrada")]
+    DisciplinaEncerrada(String),
+    #[error("Nota {id_nota} já lançada na disciplina {codigo}")]
+    NotaDuplicada { codigo: String, id_nota: u32 },
+    #[error("Transação {0} não é um lançamento, correção ou retratação de nota conhecido")]
+    ReferenciaDesconhecida(u32),
+    #[error("Transação {0} se refere a outra nota")]
+    ReferenciaIncompativel(u32),
+    #[error("Transação {referenciada} não é a versão vigente da nota (vigente: {vigente})")]
+    CorrecaoDesatualizada { referenciada: u32, vigente: u32 },
+    #[error("Nota {id_nota} da disciplina {codigo} já está anulada")]
+    NotaAnulada { codigo: String, id_nota: u32 },
+    #[error("Autoridade {0} desconhecida")]
+    AutoridadeDesconhecida(u32),
+    #[error("Transação {0} já registrada")]
+    TransacaoDuplicada(u32),
+    #[error("Transação {0} já está no pool")]
+    JaPendente(String),
+    #[error("Pool de transações cheio ({0} transações)")]
+    PoolCheio(usize),
+    #[error("Emissor {0} desconhecido")]
+    EmissorDesconhecido(u32),
+    #[error("Assinatura do emissor inválida")]
+    AssinaturaInvalida,
+    #[error("Emissor {id_emissor} não autorizado a emitir {operacao}")]
+    EmissorNaoAutorizado { id_emissor: u32, operacao: &'static str },
+    #[error("Disciplina {codigo} não atribuída ao emissor {id_emissor}")]
+    DisciplinaNaoAtribuida { id_emissor: u32, codigo: String },
+    #[error("Nota {valor} fora da escala de {minima} a {maxima}")]
+    NotaForaDaEscala { valor: f32, minima: f32, maxima: f32 },
+    #[error("Semestre {semestre} fora do intervalo de 1 a {maximo}")]
+    SemestreInvalido { semestre: u8, maximo: u8 },
+    #[error("Ano {0} fora do intervalo aceito")]
+    AnoInvalido(u32),
+    #[error("Data de nascimento inválida: {0}")]
+    DataNascimentoInvalida(NaiveDate),
+    #[error("Data {0} posterior à da transação")]
+    DataFutura(NaiveDate),
+    #[error("Timestamp da transação ({0}) à frente do bloco ou do relógio local além da deriva permitida")]
+    TimestampFuturo(DateTime<Utc>),
+    #[error("Data {data} fora do período {ano}/{semestre}")]
+    DataForaDoPeriodo { data: NaiveDate, ano: u32, semestre: u8 },
+    #[error("Prova de equivocação inválida: {0}")]
+    ProvaInvalida(&'static str),
+    #[error("Autoridade {0} já suspensa")]
+    AutoridadeJaSuspensa(u32),
+    #[error("Assinatura inválida da autoridade {0} na alteração de autoridades")]
+    AssinaturaAutoridadeInvalida(u32),
+    #[error("Autoridade {0} assinou mais de uma vez a alteração de autoridades")]
+    AssinaturaAutoridadeRepetida(u32),
+    #[error("Alteração de autoridades com {assinaturas} assinatura(s), abaixo do quórum de {quorum}")]
+    QuorumAutoridadesInsuficiente { assinaturas: usize, quorum: usize },
+    #[error("Alteração de autoridades vigoraria na altura {altura_vigencia}; a mínima é {minima}")]
+    VigenciaInvalida { altura_vigencia: u32, minima: u32 },
+    #[error("A alteração deixaria a cadeia sem autoridades")]
+    SemAutoridades,
+}
+
+#[derive(Error, Debug)]
+pub enum FinalidadeErro {
+    #[error("Voto pertence a outra cadeia")]
+    CadeiaIncorreta,
+    #[error("Autoridade {0} desconhecida")]
+    AutoridadeDesconhecida(u32),
+    #[error("Assinatura inválida no voto da autoridade {0}")]
+    AssinaturaInvalida(u32),
+    #[error("Voto da autoridade {0} não corresponde ao certificado")]
+    VotoIncompativel(u32),
+    #[error("Autoridade {0} aparece mais de uma vez no certificado")]
+    VotoRepetido(u32),
+    #[error("Certificado com {votos} voto(s), abaixo do quórum de {quorum}")]
+    QuorumInsuficiente { votos: usize, quorum: usize },
+    #[error("Autoridade {0} suspensa por equivocação")]
+    AutoridadeSuspensa(u32),
+    #[error("Bloco {0} do certificado ainda não está na cadeia local")]
+    BlocoDesconhecido(u32),
+    #[error("Certificado finaliza um bloco diferente do local na altura {0}")]
+    BlocoDivergente(u32),
+    #[error("Voto para a altura {altura}, muito à frente da ponta local {ponta}")]
+    VotoMuitoAFrente { altura: u32, ponta: u32 },
+}
+
+#[derive(Error, Debug)]
+pub enum CadeiaErro {
+    #[error("Cadeia vazia")]
+    CadeiaVazia,
+    #[error("Cadeia sem ancestral comum com a cadeia local")]
+    SemAncestralComum,
+    #[error("Bloco {indice} ({hash}) inválido: {erro}")]
+    BlocoInvalido {
+        indice: usize,
+        hash: String,
+        erro: BlocoErro,
+    },
+    #[error("A cadeia recebida diverge antes do bloco finalizado {0}")]
+    BlocoFinalizado(u32),
+}
+
+#[derive(Error, Debug)]
+pub enum InstantaneoErro {
+    #[error("Instantâneo do bloco {0}, diferente do checkpoint configurado")]
+    CheckpointDivergente(String),
+    #[error("Bloco do instantâneo inválido: {0}")]
+    BlocoInvalido(#[from] BlocoErro),
+    #[error("Estado do instantâneo é da altura {estado}, mas o bloco é da altura {bloco}")]
+    AlturaDivergente { estado: u32, bloco: u32 },
+    #[error("Autoridade {0} não estava em vigor na altura do instantâneo")]
+    AutoridadeDesconhecida(u32),
+    #[error("Assinatura do instantâneo inválida")]
+    AssinaturaInvalida,
+    #[error("Altura {0} fora da cadeia local")]
+    AlturaIndisponivel(u32),
 }
@@ -0,0 +1,16 @@
+/// Converte bytes em uma string hexadecimal minúscula.
+pub fn para_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Converte uma string hexadecimal em bytes; retorna `None` se ela for
+/// malformada.
+pub fn de_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
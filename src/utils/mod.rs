@@ -1,3 +1,4 @@
 
 pub mod config;
-pub mod erros;
\ No newline at end of file
+pub mod erros;
+pub mod helpers;